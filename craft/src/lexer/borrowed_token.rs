@@ -0,0 +1,169 @@
+use crate::lexer::{Span, Token, TokenReader, TokenType, TokenValue};
+use crate::lexer::simple_lexer::TokenSink;
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{Lexer, Token, TokenReader, TokenType, TokenValue};
+    use crate::lexer::simple_lexer::SimpleLexer;
+
+    #[test]
+    pub fn test_same_as_tokenize() {
+        let lexer = SimpleLexer::new();
+        let script = "int 年龄 = 45; /* 注释 */ if (年龄 >= 17) s = \"a\\tb\"; // end\nelse x = 1;";
+        let mut owned = lexer.tokenize(script).unwrap();
+        let mut borrowed = lexer.tokenize_borrowed(script).unwrap();
+
+        while let Some(expected) = owned.read() {
+            let token = borrowed.read().unwrap();
+            assert_eq!(token.get_type(), expected.get_type());
+            assert_eq!(token.get_text(), expected.get_text());
+            assert_eq!(token.get_span(), expected.get_span());
+            assert_eq!(token.get_value(), expected.get_value());
+        }
+        assert!(borrowed.read().is_none());
+        assert_eq!(borrowed.get_comments().len(), 2);
+    }
+
+    #[test]
+    pub fn test_zero_copy() {
+        let script = String::from("a = \"x\\ny\";");
+        let token_reader = SimpleLexer::new().tokenize_borrowed(&script).unwrap();
+
+        // Token 的文本就是源码的一部分，没有复制
+        let tokens = token_reader.tokens();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[0].text.as_ptr(), script.as_ptr());
+        assert_eq!(tokens[2].text, "\"x\\ny\"");
+        assert_eq!(tokens[2].token_type, TokenType::StringLiteral);
+        assert_eq!(tokens[2].get_value(), Some(&TokenValue::Str("x\ny".to_string())));
+
+        assert!(SimpleLexer::new().tokenize_borrowed("a $").is_err());
+    }
+}
+
+
+/// 借用源码的 Token：文本直接指向脚本中的一段，不需要为每个 Token 分配内存。
+/// 只有字符串字面量需要保存处理完转义之后的值。
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorrowedToken<'src> {
+    pub token_type: TokenType,
+    pub text: &'src str,
+    pub span: Span,
+    pub value: Option<TokenValue>,
+}
+
+impl Token for BorrowedToken<'_> {
+    fn get_type(&self) -> TokenType {
+        self.token_type
+    }
+
+    fn get_text(&self) -> &str {
+        self.text
+    }
+
+    fn get_span(&self) -> Span {
+        self.span
+    }
+
+    fn get_value(&self) -> Option<&TokenValue> {
+        self.value.as_ref()
+    }
+}
+
+
+// 按 Token 的位置从源码中截取文本，保存成 BorrowedToken
+pub(crate) struct BorrowedTokens<'src> {
+    source: &'src str,
+    tokens: Vec<BorrowedToken<'src>>,
+    comments: Vec<BorrowedToken<'src>>,
+}
+
+impl<'src> BorrowedTokens<'src> {
+    pub(crate) fn new(source: &'src str) -> Self {
+        BorrowedTokens {
+            source,
+            tokens: Vec::new(),
+            comments: Vec::new(),
+        }
+    }
+
+    pub(crate) fn into_reader(self) -> BorrowedTokenReader<'src> {
+        BorrowedTokenReader {
+            tokens: self.tokens,
+            comments: self.comments,
+            pos: 0,
+        }
+    }
+}
+
+impl TokenSink for BorrowedTokens<'_> {
+    fn push(&mut self, token_type: TokenType, _text: &str, span: Span, value: Option<TokenValue>) {
+        let token = BorrowedToken {
+            token_type,
+            text: &self.source[span.start.offset..span.end.offset],
+            span,
+            value,
+        };
+
+        if token_type == TokenType::Comment {
+            self.comments.push(token);
+        } else {
+            self.tokens.push(token);
+        }
+    }
+}
+
+
+/// 在连续存放的 BorrowedToken 上读取 Token 流
+pub struct BorrowedTokenReader<'src> {
+    tokens: Vec<BorrowedToken<'src>>,
+    comments: Vec<BorrowedToken<'src>>,
+    pos: usize,
+}
+
+impl<'src> BorrowedTokenReader<'src> {
+    // 所有的 Token，不包括注释
+    pub fn tokens(&self) -> &[BorrowedToken<'src>] {
+        &self.tokens
+    }
+
+    // 脚本中的所有注释
+    pub fn get_comments(&self) -> &[BorrowedToken<'src>] {
+        &self.comments
+    }
+}
+
+impl TokenReader for BorrowedTokenReader<'_> {
+    fn read(&mut self) -> Option<&dyn Token> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn peek(&self) -> Option<&dyn Token> {
+        self.tokens.get(self.pos).map(|token| token as &dyn Token)
+    }
+
+    fn peek_nth(&mut self, k: usize) -> Option<&dyn Token> {
+        self.tokens.get(self.pos + k).map(|token| token as &dyn Token)
+    }
+
+    fn unread(&mut self) {
+        if self.pos > 0 {
+            self.pos -= 1;
+        }
+    }
+
+    fn get_position(&self) -> usize {
+        self.pos
+    }
+
+    fn set_position(&mut self, position: usize) -> bool {
+        if position > self.tokens.len() {
+            return false;
+        }
+
+        self.pos = position;
+        true
+    }
+}
@@ -0,0 +1,172 @@
+use crate::lexer::{Token, TokenReader};
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{TokenReader, TokenType};
+    use crate::lexer::simple_lexer::SimpleLexer;
+    use crate::lexer::stream_lexer::StreamTokenReader;
+
+    #[test]
+    pub fn test_checkpoint() {
+        let mut token_reader = SimpleLexer::new().tokenize("int a = 1;").unwrap();
+        {
+            let mut outer = token_reader.checkpoint();
+            outer.read();
+            {
+                // 没有 commit 的 Checkpoint 被丢弃时回到创建它的位置
+                let mut inner = outer.checkpoint();
+                inner.read();
+                inner.read();
+                assert_eq!(inner.get_position(), 3);
+            }
+            assert_eq!(outer.get_position(), 1);
+
+            let mut inner = outer.checkpoint();
+            inner.read();
+            inner.commit();
+            assert_eq!(outer.get_position(), 2);
+        }
+        assert_eq!(token_reader.get_position(), 0);
+
+        let mut checkpoint = token_reader.checkpoint();
+        checkpoint.set_position(5);
+        assert!(checkpoint.peek().is_none());
+        checkpoint.commit();
+        assert_eq!(token_reader.get_position(), 5);
+    }
+
+    #[test]
+    pub fn test_restore() {
+        let mut token_reader = SimpleLexer::new().tokenize("int a = 1;").unwrap();
+        let mut checkpoint = token_reader.checkpoint();
+        checkpoint.read();
+        assert!(checkpoint.restore());
+        assert_eq!(token_reader.get_position(), 0);
+
+        // 流式的 Token 流只能回退到回放窗口之内
+        let mut token_reader = StreamTokenReader::with_replay(&b"a = 1; b = 2;"[..], 2);
+        token_reader.read();
+        let mut checkpoint = token_reader.checkpoint();
+        for _ in 0..5 {
+            checkpoint.read();
+        }
+        assert!(!checkpoint.restore());
+        assert_eq!(token_reader.get_position(), 6);
+        assert!(token_reader.take_replay_error().is_some());
+    }
+
+    #[test]
+    pub fn test_peek_nth_and_expect() {
+        let mut token_reader = SimpleLexer::new().tokenize("a = 1;").unwrap();
+        assert_eq!(token_reader.peek_nth(0).unwrap().get_text(), "a");
+        assert_eq!(token_reader.peek_nth(2).unwrap().get_text(), "1");
+        assert!(token_reader.peek_nth(4).is_none());
+        assert_eq!(token_reader.get_position(), 0);
+
+        assert_eq!(token_reader.expect(TokenType::Identifier).unwrap().get_text(), "a");
+        let err = token_reader.expect(TokenType::SemiColon).err().unwrap();
+        assert_eq!(err.expected, TokenType::SemiColon);
+        assert_eq!(err.to_string(), "unexpected '=' at 1:3, expecting SemiColon");
+        assert_eq!(token_reader.get_position(), 1);
+
+        token_reader.set_position(4);
+        let err = token_reader.expect(TokenType::SemiColon).err().unwrap();
+        assert_eq!(err.to_string(), "unexpected end of script, expecting SemiColon");
+
+        // 末尾之后的位置是非法的
+        token_reader.set_position(5);
+        assert_eq!(token_reader.get_position(), 4);
+    }
+
+    #[test]
+    pub fn test_other_readers() {
+        let lexer = SimpleLexer::new();
+        let script = "a = 1;";
+        let (mut lossless, _) = lexer.tokenize_lossless(script);
+        let mut borrowed = lexer.tokenize_borrowed(script).unwrap();
+        let mut stream = lexer.tokenize_stream(script.as_bytes());
+        let readers: [&mut dyn TokenReader; 3] = [&mut lossless, &mut borrowed, &mut stream];
+
+        for token_reader in readers {
+            assert_eq!(token_reader.peek_nth(3).unwrap().get_text(), ";");
+            assert!(token_reader.peek_nth(4).is_none());
+
+            token_reader.set_position(4);
+            assert_eq!(token_reader.get_position(), 4);
+            assert!(token_reader.read().is_none());
+        }
+    }
+}
+
+
+/// Token 流上的回溯点：记下创建时的读取位置，被丢弃时自动回到这个位置，调用 commit 之后则保留读过的 Token。
+/// Checkpoint 本身也是 TokenReader，可以在它上面再创建 Checkpoint，实现嵌套的回溯。
+///
+/// 流式的 Token 流只保留回放窗口之内的 Token，超出窗口的 Checkpoint 无法回退。被丢弃时回退的结果无从得知，
+/// 需要确认是否回退成功的地方应该显式地调用 restore。
+pub struct Checkpoint<'a, R: TokenReader + ?Sized> {
+    reader: &'a mut R,
+    position: usize,
+    committed: bool,
+}
+
+impl<'a, R: TokenReader + ?Sized> Checkpoint<'a, R> {
+    pub fn new(reader: &'a mut R) -> Self {
+        let position = reader.get_position();
+        Checkpoint {
+            reader,
+            position,
+            committed: false,
+        }
+    }
+
+    // 创建 Checkpoint 时的读取位置
+    pub fn get_start(&self) -> usize {
+        self.position
+    }
+
+    // 接受已经读过的 Token，不再回退
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+
+    // 回到创建 Checkpoint 时的位置，返回是否成功。失败时读取位置不变
+    pub fn restore(mut self) -> bool {
+        self.committed = true;
+        self.reader.set_position(self.position)
+    }
+}
+
+impl<R: TokenReader + ?Sized> Drop for Checkpoint<'_, R> {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = self.reader.set_position(self.position);
+        }
+    }
+}
+
+impl<R: TokenReader + ?Sized> TokenReader for Checkpoint<'_, R> {
+    fn read(&mut self) -> Option<&dyn Token> {
+        self.reader.read()
+    }
+
+    fn peek(&self) -> Option<&dyn Token> {
+        self.reader.peek()
+    }
+
+    fn peek_nth(&mut self, k: usize) -> Option<&dyn Token> {
+        self.reader.peek_nth(k)
+    }
+
+    fn unread(&mut self) {
+        self.reader.unread()
+    }
+
+    fn get_position(&self) -> usize {
+        self.reader.get_position()
+    }
+
+    fn set_position(&mut self, position: usize) -> bool {
+        self.reader.set_position(position)
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::lexer::{DfaState, TokenType};
+use crate::lexer::keyword::KeywordTable;
+use crate::lexer::simple_calculator;
+use crate::lexer::simple_lexer::{OPERATORS, OwnedTokens, Scanner};
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::generator::g4::LexerGrammar;
+    use crate::lexer::TokenType;
+
+    use super::{simple_lexer_dot, Vocabulary};
+
+    #[test]
+    pub fn test_dot() {
+        let dot = simple_lexer_dot();
+        assert!(dot.starts_with("digraph SimpleLexer {\n"));
+        for line in [
+            "\"Id\" [shape=doublecircle];",
+            "\"Dot\" [shape=circle];",
+            "\"Initial\" -> \"Id\" [label=\"[A-Z_a-z] XID_Start\"];",
+            "\"Id\" -> \"Id\" [label=\"[0-9A-Z_a-z] XID_Start XID_Continue\"];",
+            "\"NumberZero\" -> \"RadixDigits 0x\" [label=\"[Xx]\"];",
+            "\"Operator >>\" -> \"Operator >>>\" [label=\">\"];",
+            "\"Slash\" -> \"LineComment\" [label=\"/\"];",
+            "\"LineComment\" -> \"LineComment\" [label=\"[^\\\\n\\\\r]\"];",
+            "\"StringEscape\" -> \"StringChars\" [label=\"[\\\"'\\\\\\\\nrt]\"];",
+            "\"StringChars '\" -> \"CharLiteral\" [label=\"'\"];",
+        ] {
+            assert!(dot.contains(line), "{} not in\n{}", line, dot);
+        }
+
+        // 每个状态都出现在图中
+        for state in ["BlockCommentEnd", "Colon", "StringLiteral", "ExponentSign", "NumberSuffix", "BadNumber", "Operator >>>="] {
+            assert!(dot.contains(&format!("\"{}\" [shape=", state)), "state {}", state);
+        }
+    }
+
+    #[test]
+    pub fn test_tokens_file() {
+        // 和 ANTLR 生成的 .tokens 文件完全一致
+        let grammar = LexerGrammar::parse(include_str!("../../../antlr-test/hello/hello.g4")).unwrap();
+        let vocabulary = grammar.to_vocabulary();
+        assert_eq!(vocabulary.to_tokens(), include_str!("../../../antlr-test/hello/hello.tokens"));
+
+        let text = include_str!("../../../antlr-test/play-script/PlayScriptLexer.tokens");
+        let vocabulary = Vocabulary::parse(text).unwrap();
+        assert_eq!(vocabulary.to_tokens(), text);
+        assert_eq!(vocabulary.get_id("IF"), Some(23));
+        assert_eq!(vocabulary.get_name(23), Some("IF"));
+        assert_eq!(vocabulary.literal_id(">>>="), Some(95));
+
+        let vocabulary = Vocabulary::simple_script();
+        let id = vocabulary.type_id(TokenType::GE).unwrap();
+        assert_eq!(vocabulary.literal_id(">="), Some(id));
+        assert_eq!(vocabulary.token_type(id), Some(TokenType::GE));
+        assert_eq!(Vocabulary::parse(&vocabulary.to_tokens()).unwrap().to_tokens(), vocabulary.to_tokens());
+
+        for text in ["A", "A=x", "'a=1", "=1"] {
+            assert!(Vocabulary::parse(text).is_err(), "parse {}", text);
+        }
+    }
+}
+
+
+/*
+把 SimpleLexer 的有限自动机导出成 Graphviz 的 DOT 格式，可以用 `dot -Tsvg` 画出来。
+
+状态图是直接运行 SimpleLexer 探查出来的：从初始状态开始，对每个状态试着读入每一类字符，记下 Token 还在继续时到达的状态。
+运算符按读到的内容区分状态，0x、0b、0o 之后的数字按进制区分状态。Token 在某个状态结束时是合法的，这个状态就是接受状态，
+画成双圈；从接受状态读到不能继续的字符时，结束当前 Token 回到初始状态，这些边不画出来。报错的边也不画，只保留进入 BadNumber 的边。
+*/
+pub fn simple_lexer_dot() -> String {
+    let samples = sample_chars();
+    // 状态的名字、到达这个状态的几段不同的输入、是否是接受状态
+    let mut nodes: Vec<(String, Vec<String>, bool)> = vec![("Initial".to_string(), vec![String::new()], false)];
+    let mut index: HashMap<String, usize> = HashMap::from([("Initial".to_string(), 0)]);
+    let mut edges: Vec<(usize, usize, Vec<char>)> = Vec::new();
+    // 待探查的状态和输入。同一个状态用几段不同的输入去探查，比如 ' 和 'a 之后读到 ' 的结果不一样
+    let mut queue: VecDeque<(usize, String)> = VecDeque::from([(0, String::new())]);
+
+    while let Some((i, text)) = queue.pop_front() {
+        let (_, errors) = run(&text);
+        for &ch in &samples {
+            let next = format!("{}{}", text, ch);
+            let (scanner, next_errors) = run(&next);
+            let (state, token_text) = scanner.current();
+            let continues = state == DfaState::BadNumber || (state != DfaState::Initial && token_text == next && next_errors == errors);
+            if !continues {
+                continue;
+            }
+
+            let name = state_name(state, &next);
+            let target = match index.get(&name) {
+                Some(&target) => {
+                    if nodes[target].1.len() < MAX_PROBES {
+                        nodes[target].1.push(next.clone());
+                        nodes[target].2 |= accepts(&next);
+                        queue.push_back((target, next));
+                    }
+                    target
+                }
+                None => {
+                    nodes.push((name.clone(), vec![next.clone()], accepts(&next)));
+                    index.insert(name, nodes.len() - 1);
+                    queue.push_back((nodes.len() - 1, next));
+                    nodes.len() - 1
+                }
+            };
+            match edges.iter_mut().find(|(from, to, _)| *from == i && *to == target) {
+                Some((_, _, chars)) if !chars.contains(&ch) => chars.push(ch),
+                Some(_) => {}
+                None => edges.push((i, target, vec![ch])),
+            }
+        }
+    }
+
+    // 按状态的顺序输出，同一个状态的边按目标状态的顺序
+    edges.sort_by_key(|&(from, to, _)| (from, to));
+    let mut dot = String::from("digraph SimpleLexer {\n    rankdir=LR;\n");
+    for (name, _, accepting) in &nodes {
+        let shape = if *accepting { "doublecircle" } else { "circle" };
+        writeln!(dot, "    \"{}\" [shape={}];", escape_dot(name), shape).unwrap();
+    }
+    for (from, to, chars) in &edges {
+        let label = char_class(chars, &samples);
+        writeln!(dot, "    \"{}\" -> \"{}\" [label=\"{}\"];", escape_dot(&nodes[*from].0), escape_dot(&nodes[*to].0), escape_dot(&label)).unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// 从头读入 text，返回有限自动机以及出现的错误数
+fn run(text: &str) -> (Scanner<OwnedTokens>, usize) {
+    let mut scanner = Scanner::new(OwnedTokens::new(), KeywordTable::english());
+    text.chars().for_each(|ch| scanner.feed(ch));
+    let errors = scanner.errors.len();
+    (scanner, errors)
+}
+
+// 输入在 text 之后结束时，最后一个 Token 是否合法
+fn accepts(text: &str) -> bool {
+    let (mut scanner, errors) = run(text);
+    let count = scanner.sink.tokens.len() + scanner.sink.comments.len();
+    scanner.finish();
+    scanner.errors.len() == errors && scanner.sink.tokens.len() + scanner.sink.comments.len() > count
+}
+
+// 每个状态最多用几段输入去探查
+const MAX_PROBES: usize = 3;
+
+fn state_name(state: DfaState, text: &str) -> String {
+    match state {
+        DfaState::Operator => format!("Operator {}", text),
+        DfaState::RadixDigits => format!("RadixDigits {}", text[..2].to_ascii_lowercase()),
+        // 字符字面量和字符串共用这些状态，按开始的引号区分
+        DfaState::StringChars | DfaState::StringEscape | DfaState::StringUnicodeStart | DfaState::StringUnicode if text.starts_with('\'') => {
+            format!("{:?} '", state)
+        }
+        _ => format!("{:?}", state),
+    }
+}
+
+// 探查时使用的字符：所有可见的 ASCII 字符和空白，再加上几类非 ASCII 字符的代表
+const UNICODE_SAMPLES: [(char, &str); 4] = [
+    ('年', "XID_Start"),
+    ('\u{301}', "XID_Continue"),
+    ('\u{3000}', "White_Space"),
+    ('€', "Other"),
+];
+
+fn sample_chars() -> Vec<char> {
+    let mut samples: Vec<char> = vec!['\t', '\n', '\r'];
+    samples.extend(' '..='~');
+    samples.extend(UNICODE_SAMPLES.iter().map(|&(ch, _)| ch));
+    samples
+}
+
+// 把边上的字符写成字符类，比如 [0-9A-Z_a-z]，多数字符都可以时写成 [^...]。非 ASCII 字符写它代表的类别
+fn char_class(chars: &[char], samples: &[char]) -> String {
+    let ascii: Vec<char> = chars.iter().copied().filter(char::is_ascii).collect();
+    let unicode: Vec<&str> = UNICODE_SAMPLES.iter().filter(|(ch, _)| chars.contains(ch)).map(|&(_, name)| name).collect();
+    let all_ascii: Vec<char> = samples.iter().copied().filter(char::is_ascii).collect();
+
+    if unicode.len() == UNICODE_SAMPLES.len() && ascii.len() * 2 > all_ascii.len() {
+        let rest: Vec<char> = all_ascii.into_iter().filter(|ch| !ascii.contains(ch)).collect();
+        return format!("[^{}]", char_ranges(&rest));
+    }
+
+    let mut parts = Vec::new();
+    match ascii.len() {
+        0 => {}
+        // 单个字符不在 [] 中，不需要转义 ] - ^
+        1 if matches!(ascii[0], ']' | '[' | '-' | '^') => parts.push(ascii[0].to_string()),
+        1 => parts.push(char_ranges(&ascii)),
+        _ => parts.push(format!("[{}]", char_ranges(&ascii))),
+    }
+    parts.extend(unicode.iter().map(|name| name.to_string()));
+    parts.join(" ")
+}
+
+// 连续 3 个以上的字符写成区间
+fn char_ranges(chars: &[char]) -> String {
+    let mut chars = chars.to_vec();
+    chars.sort();
+    let mut text = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let mut j = i;
+        while j + 1 < chars.len() && chars[j + 1] as u32 == chars[j] as u32 + 1 {
+            j += 1;
+        }
+        if j - i >= 2 {
+            write!(text, "{}-{}", class_char(chars[i]), class_char(chars[j])).unwrap();
+        } else {
+            chars[i..=j].iter().for_each(|&ch| text.push_str(&class_char(ch)));
+        }
+        i = j + 1;
+    }
+    text
+}
+
+fn class_char(ch: char) -> String {
+    match ch {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        ' ' => "␣".to_string(),
+        '\\' | ']' | '[' | '-' | '^' => format!("\\{}", ch),
+        _ => ch.to_string(),
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+
+/// Token 的词汇表：Token 的名字和编号，以及只匹配固定文本的 Token 的字面量，格式和 ANTLR 生成的 .tokens 文件一样：
+/// ```text
+/// If=1
+/// Id=19
+/// 'if'=1
+/// ```
+/// 和 ANTLR 生成的 Java 代码共用同一套 Token 编号。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vocabulary {
+    names: Vec<(String, u32)>,
+    // 字面量不带引号，没有转义
+    literals: Vec<(String, u32)>,
+}
+
+// SimpleLexer 的 Token 类型，按这个顺序从 1 开始编号
+const TOKEN_TYPES: [TokenType; 61] = [
+    TokenType::Plus, TokenType::Minus, TokenType::Star, TokenType::Slash, TokenType::GE, TokenType::GT, TokenType::EQ,
+    TokenType::NE, TokenType::LE, TokenType::LT, TokenType::SemiColon, TokenType::LeftParen, TokenType::RightParen,
+    TokenType::Assignment, TokenType::Percent, TokenType::Inc, TokenType::Dec, TokenType::And, TokenType::Or,
+    TokenType::Not, TokenType::BitAnd, TokenType::BitOr, TokenType::BitXor, TokenType::BitNot, TokenType::LeftShift,
+    TokenType::RightShift, TokenType::UnsignedRightShift, TokenType::PlusAssign, TokenType::MinusAssign,
+    TokenType::StarAssign, TokenType::SlashAssign, TokenType::PercentAssign, TokenType::LeftShiftAssign,
+    TokenType::RightShiftAssign, TokenType::UnsignedRightShiftAssign, TokenType::BitAndAssign, TokenType::BitOrAssign,
+    TokenType::BitXorAssign, TokenType::If, TokenType::Else, TokenType::Int, TokenType::Identifier,
+    TokenType::IntLiteral, TokenType::HexLiteral, TokenType::BinaryLiteral, TokenType::OctalLiteral,
+    TokenType::FloatLiteral, TokenType::StringLiteral, TokenType::Comment, TokenType::CharLiteral,
+    TokenType::BooleanLiteral, TokenType::NullLiteral, TokenType::While, TokenType::For, TokenType::Break,
+    TokenType::Continue, TokenType::Colon, TokenType::LeftBrace, TokenType::RightBrace, TokenType::Return,
+    TokenType::Comma,
+];
+
+impl Vocabulary {
+    pub fn new() -> Self {
+        Vocabulary {
+            names: Vec::new(),
+            literals: Vec::new(),
+        }
+    }
+
+    // SimpleLexer 的词汇表：Token 类型的名字就是它在 TokenType 中的名字
+    pub fn simple_script() -> Self {
+        let mut vocabulary = Vocabulary::new();
+        for (index, token_type) in TOKEN_TYPES.iter().enumerate() {
+            vocabulary.add_name(&format!("{:?}", token_type), index as u32 + 1);
+        }
+
+        let keywords = KeywordTable::english();
+        let literals = ["if", "else", "int", "while", "for", "break", "continue", "return", "true", "false", "null"].into_iter()
+            .map(|keyword| (keyword, keywords.lookup(keyword).unwrap()))
+            .chain(OPERATORS.iter().copied())
+            .chain([(";", TokenType::SemiColon), ("(", TokenType::LeftParen), (")", TokenType::RightParen), (":", TokenType::Colon),
+                    ("{", TokenType::LeftBrace), ("}", TokenType::RightBrace), (",", TokenType::Comma)]);
+        for (literal, token_type) in literals {
+            let id = vocabulary.type_id(token_type).unwrap();
+            vocabulary.add_literal(literal, id);
+        }
+        vocabulary
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Vocabulary, io::Error> {
+        let text = fs::read_to_string(path)?;
+        Vocabulary::parse(&text)
+    }
+
+    // 解析 .tokens 文件，每行是 名字=编号 或者 '字面量'=编号
+    pub fn parse(text: &str) -> Result<Vocabulary, io::Error> {
+        let mut vocabulary = Vocabulary::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let invalid = || {
+                let msg = format!("invalid token vocabulary line {}: {}", line_no + 1, line);
+                simple_calculator::invalid_input_err(msg.as_str())
+            };
+            let (key, id) = line.rsplit_once('=').ok_or_else(invalid)?;
+            let id: u32 = id.trim().parse().map_err(|_| invalid())?;
+            if key.starts_with('\'') {
+                let literal = key.strip_prefix('\'').and_then(|key| key.strip_suffix('\'')).ok_or_else(invalid)?;
+                vocabulary.add_literal(&unescape(literal).ok_or_else(invalid)?, id);
+            } else if !key.is_empty() {
+                vocabulary.add_name(key, id);
+            } else {
+                return Err(invalid());
+            }
+        }
+
+        Ok(vocabulary)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        fs::write(path, self.to_tokens())
+    }
+
+    // 生成 .tokens 文件的内容：先是所有的名字，再是所有的字面量，都按编号排列
+    pub fn to_tokens(&self) -> String {
+        let mut names = self.names.clone();
+        names.sort_by_key(|&(_, id)| id);
+        let mut literals = self.literals.clone();
+        literals.sort_by_key(|&(_, id)| id);
+
+        let mut text = String::new();
+        names.iter().for_each(|(name, id)| writeln!(text, "{}={}", name, id).unwrap());
+        literals.iter().for_each(|(literal, id)| writeln!(text, "'{}'={}", escape(literal), id).unwrap());
+        text
+    }
+
+    pub fn add_name(&mut self, name: &str, id: u32) {
+        self.names.push((name.to_string(), id));
+    }
+
+    pub fn add_literal(&mut self, literal: &str, id: u32) {
+        self.literals.push((literal.to_string(), id));
+    }
+
+    pub fn get_id(&self, name: &str) -> Option<u32> {
+        self.names.iter().find(|(n, _)| n == name).map(|&(_, id)| id)
+    }
+
+    pub fn get_name(&self, id: u32) -> Option<&str> {
+        self.names.iter().find(|&&(_, i)| i == id).map(|(name, _)| name.as_str())
+    }
+
+    pub fn literal_id(&self, literal: &str) -> Option<u32> {
+        self.literals.iter().find(|(l, _)| l == literal).map(|&(_, id)| id)
+    }
+
+    // Token 类型的编号：从语法文件加载的 Token 类型就是它的编号，SimpleLexer 的 Token 类型按名字查找
+    pub fn type_id(&self, token_type: TokenType) -> Option<u32> {
+        match token_type {
+            TokenType::Custom(id) => Some(id),
+            token_type => self.get_id(&format!("{:?}", token_type)),
+        }
+    }
+
+    // 编号对应的 Token 类型：名字是 SimpleLexer 的 Token 类型时返回它，否则返回 Custom(编号)
+    pub fn token_type(&self, id: u32) -> Option<TokenType> {
+        let name = self.get_name(id)?;
+        let token_type = TOKEN_TYPES.iter().find(|token_type| format!("{:?}", token_type) == name);
+        Some(token_type.copied().unwrap_or(TokenType::Custom(id)))
+    }
+}
+
+impl Default for Vocabulary {
+    fn default() -> Self {
+        Vocabulary::new()
+    }
+}
+
+// .tokens 文件中字面量的转义
+fn escape(literal: &str) -> String {
+    let mut text = String::new();
+    for ch in literal.chars() {
+        match ch {
+            '\'' => text.push_str("\\'"),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            _ => text.push(ch),
+        }
+    }
+    text
+}
+
+fn unescape(text: &str) -> Option<String> {
+    let mut literal = String::new();
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            literal.push(ch);
+            continue;
+        }
+        literal.push(match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            ch @ ('\'' | '\\') => ch,
+            _ => return None,
+        });
+    }
+    Some(literal)
+}
@@ -0,0 +1,309 @@
+use crate::lexer::{Lexer, LexError, Position, Span, TokenType, TokenValue};
+use crate::lexer::borrowed_token::{BorrowedTokenReader, BorrowedTokens};
+use crate::lexer::keyword::KeywordTable;
+use crate::lexer::simple_lexer::{operator_type, OwnedTokens, Scanner, SimpleTokenReader, TokenSink};
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{identifier, Token, TokenReader};
+    use crate::lexer::keyword::KeywordTable;
+    use crate::lexer::simple_lexer::{SimpleLexer, SimpleTokenReader};
+
+    use super::{BYTE_CLASS, ByteClass, FastLexer};
+
+    // 两个词法分析器共用的语料：覆盖各种 Token、快慢两条路径的切换，以及各种词法错误
+    const CORPUS: [&str; 16] = [
+        "int age = 45;\nage >= 17+8+20; b = age/2; // 注释\n",
+        "a+=b++; c---d; e>>>=f>>>g>>h; i&&j||!k; l&=m|n^~o%p; q/=r",
+        "x = 0x1F + 0b1010 + 0o17 + 1_000 + 3.14 + 1e10 + 2.5e-3f + 10L + .5 + 0;",
+        "s = \"plain\" + \"a\\tb\\u{5e74}\" + \"年龄\" + \"\"; c = 'x'; d = '\\n'; e = true != false; f = null;",
+        "/* 外层 /* 嵌套 */ **/ a = 1; /**/ b //\n// 末尾的注释",
+        "年龄2 = café + _tmp; 如果 (年龄 > 3) x = 1; int scope = 1; \u{455}cope = 2; \u{455}cope = 3;",
+        "a\r\nb\tc\u{b}d\u{c}e\u{3000}f\u{a0}g",
+        "a = 1 $ 2; b = @; `c` # d \\ e { f } [g] h.i",
+        "12abc 0b12 0x 1e 1.2.3 99999999999999999999 1__2 3_",
+        "s = \"abc\nd\" + \"x\\q\" + \"\\u{110000}\" + \"\\u41\"; c = ''; d = 'ab'; e = '",
+        "a = \"never closed",
+        "/* never closed",
+        "outer: while (i < 3) for (;;) { break outer; } 跳出 继续 当 对于 a::b {{}",
+        "int f(int a,int b) { return f(a, b - 1); } 返回 returned ,,",
+        "int a = 1. ; b = .",
+        "",
+    ];
+
+    fn dump(token_reader: &mut SimpleTokenReader) -> Vec<String> {
+        let mut tokens = Vec::new();
+        while let Some(token) = token_reader.read() {
+            tokens.push(format!("{:?} {:?} {:?} {:?}", token.get_type(), token.get_text(), token.get_span(), token.get_value()));
+        }
+        for comment in token_reader.get_comments() {
+            tokens.push(format!("comment {:?} {:?}", comment.get_text(), comment.get_span()));
+        }
+        for warning in token_reader.get_warnings() {
+            tokens.push(format!("warning {:?}", warning));
+        }
+        tokens
+    }
+
+    fn assert_same(keywords: KeywordTable, script: &str) {
+        let (mut expected, expected_errors) = SimpleLexer::with_keywords(keywords.clone()).tokenize_with_recovery(script);
+        let (mut actual, errors) = FastLexer::with_keywords(keywords).tokenize_with_recovery(script);
+        assert_eq!(dump(&mut actual), dump(&mut expected), "tokenize {:?}", script);
+        assert_eq!(errors, expected_errors, "tokenize {:?}", script);
+    }
+
+    #[test]
+    pub fn test_same_as_simple_lexer() {
+        for script in CORPUS {
+            assert_same(KeywordTable::english(), script);
+            assert_same(KeywordTable::chinese(), script);
+        }
+        assert_same(KeywordTable::chinese(), include_str!("../../../antlr-test/hello/hello.play"));
+
+        // 把语料拼成一个大脚本，Token 的位置要一直对得上
+        let script = CORPUS.iter().filter(|script| !script.contains("never closed")).cycle().take(500).copied().collect::<Vec<_>>().join("\n");
+        assert_same(KeywordTable::english(), &script);
+    }
+
+    #[test]
+    pub fn test_borrowed() {
+        let lexer = FastLexer::new();
+        let script = CORPUS[3];
+        let mut owned = lexer.tokenize(script).unwrap();
+        let mut borrowed = lexer.tokenize_borrowed(script).unwrap();
+        while let Some(expected) = owned.read() {
+            let token = borrowed.read().unwrap();
+            assert_eq!((token.get_type(), token.get_text(), token.get_span()), (expected.get_type(), expected.get_text(), expected.get_span()));
+        }
+        assert!(borrowed.read().is_none());
+
+        assert!(lexer.tokenize_borrowed(CORPUS[7]).is_err());
+    }
+
+    #[test]
+    pub fn test_byte_class() {
+        // 表中的分类必须和有限自动机对同一个字符的判断一致
+        for (byte, class) in BYTE_CLASS.iter().enumerate() {
+            let ch = byte as u8 as char;
+            assert_eq!(*class == ByteClass::Whitespace, byte < 0x80 && ch.is_whitespace(), "byte {:#x}", byte);
+            assert_eq!(*class == ByteClass::IdStart, byte < 0x80 && identifier::is_id_start(ch), "byte {:#x}", byte);
+            if byte < 0x80 && identifier::is_id_continue(ch) {
+                assert!(matches!(class, ByteClass::IdStart | ByteClass::Digit), "byte {:#x}", byte);
+            }
+            assert_eq!(*class == ByteClass::NonAscii, byte >= 0x80, "byte {:#x}", byte);
+        }
+    }
+}
+
+
+/// 面向大脚本的词法分析器。用一张 256 项的表给 ASCII 字节分类，成片地扫描空白、标识符、数字和运算符，
+/// 不需要逐个字符地驱动有限自动机；遇到非 ASCII 字符、转义、块注释这些少见的情况，再把字符逐个交给 SimpleLexer 的有限自动机。
+/// 两条路径共用同一个 Scanner，得到的 Token、错误和警告和 SimpleLexer 完全一样。
+#[derive(Debug)]
+pub struct FastLexer {
+    keywords: KeywordTable,
+}
+
+impl FastLexer {
+    pub fn new() -> Self {
+        FastLexer::with_keywords(KeywordTable::english())
+    }
+
+    pub fn with_keywords(keywords: KeywordTable) -> Self {
+        FastLexer { keywords }
+    }
+
+    // 词法分析，遇到词法错误时返回第一个错误
+    pub fn tokenize(&self, script: &str) -> Result<SimpleTokenReader, LexError> {
+        let (token_reader, mut errors) = self.tokenize_with_recovery(script);
+        if !errors.is_empty() {
+            return Err(errors.remove(0));
+        }
+
+        Ok(token_reader)
+    }
+
+    // 容错模式的词法分析，和 SimpleLexer::tokenize_with_recovery 一样
+    pub fn tokenize_with_recovery(&self, script: &str) -> (SimpleTokenReader, Vec<LexError>) {
+        let mut scanner = Scanner::new(OwnedTokens::new(), self.keywords.clone());
+        scan(&mut scanner, script);
+
+        let token_reader = SimpleTokenReader::new(scanner.sink.tokens, scanner.sink.comments).with_warnings(scanner.warnings);
+        (token_reader, scanner.errors)
+    }
+
+    // 不复制文本的词法分析，大脚本用它可以省掉为每个 Token 分配的内存
+    pub fn tokenize_borrowed<'src>(&self, script: &'src str) -> Result<BorrowedTokenReader<'src>, LexError> {
+        let mut scanner = Scanner::new(BorrowedTokens::new(script), self.keywords.clone());
+        scan(&mut scanner, script);
+
+        if !scanner.errors.is_empty() {
+            return Err(scanner.errors.remove(0));
+        }
+        Ok(scanner.sink.into_reader())
+    }
+}
+
+impl Lexer for FastLexer {
+    fn tokenize(&self, script: &str) -> Result<SimpleTokenReader, LexError> {
+        FastLexer::tokenize(self, script)
+    }
+}
+
+
+// 字节的分类。ASCII 以外的字节都是 NonAscii，属于某个多字节字符
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ByteClass {
+    Whitespace,
+    // 字母和 _，可以开始一个标识符
+    IdStart,
+    Digit,
+    // 运算符的第一个字符，/ 除外
+    Operator,
+    Slash,
+    // ; ( ) { } : ,
+    Punct,
+    Quote,
+    // 其他的 ASCII 字符，比如 . ' $，交给有限自动机处理
+    Other,
+    NonAscii,
+}
+
+static BYTE_CLASS: [ByteClass; 256] = byte_classes();
+
+const fn byte_classes() -> [ByteClass; 256] {
+    let mut table = [ByteClass::NonAscii; 256];
+    let mut byte = 0;
+    while byte < 0x80 {
+        table[byte] = match byte as u8 {
+            b'\t' | b'\n' | 0x0b | 0x0c | b'\r' | b' ' => ByteClass::Whitespace,
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => ByteClass::IdStart,
+            b'0'..=b'9' => ByteClass::Digit,
+            b'+' | b'-' | b'*' | b'%' | b'=' | b'!' | b'<' | b'>' | b'&' | b'|' | b'^' | b'~' => ByteClass::Operator,
+            b'/' => ByteClass::Slash,
+            b';' | b'(' | b')' | b'{' | b'}' | b':' | b',' => ByteClass::Punct,
+            b'"' => ByteClass::Quote,
+            _ => ByteClass::Other,
+        };
+        byte += 1;
+    }
+    table
+}
+
+// 第 i 个字节的分类，超出脚本时返回None
+fn class_at(bytes: &[u8], i: usize) -> Option<ByteClass> {
+    bytes.get(i).map(|&byte| BYTE_CLASS[byte as usize])
+}
+
+// 分析整个脚本。在 Token 的边界上先试快速路径，不行的话把一个字符交给有限自动机
+fn scan<S: TokenSink>(scanner: &mut Scanner<S>, script: &str) {
+    let mut i = 0;
+    while i < script.len() {
+        if scanner.at_boundary() {
+            if let Some(end) = fast_token(scanner, script, i) {
+                i = end;
+                continue;
+            }
+        }
+
+        let ch = script[i..].chars().next().unwrap();
+        scanner.feed(ch);
+        i += ch.len_utf8();
+    }
+
+    scanner.finish();
+}
+
+/*
+从 start 开始扫描一个 Token（或者一段空白），保存之后返回它的结束位置。
+需要有限自动机来处理的情况返回None，这时什么都没有做：
+  - 标识符后面紧跟着非 ASCII 字符，它可能还没有结束；
+  - 数字后面是字母或者 .，可能是 0x1F、3.14、12abc 这样的；
+  - 块注释、带转义或者没有结束的字符串，以及 Other、NonAscii 开始的内容。
+*/
+fn fast_token<S: TokenSink>(scanner: &mut Scanner<S>, script: &str, start: usize) -> Option<usize> {
+    let bytes = script.as_bytes();
+    let run = |from: usize, accept: fn(ByteClass) -> bool| {
+        from + bytes[from..].iter().take_while(|&&byte| accept(BYTE_CLASS[byte as usize])).count()
+    };
+
+    let (token_type, end, value) = match BYTE_CLASS[bytes[start] as usize] {
+        ByteClass::Whitespace => {
+            let end = run(start, |class| class == ByteClass::Whitespace);
+            for &byte in &bytes[start..end] {
+                scanner.pos.advance(byte as char);
+            }
+            return Some(end);
+        }
+        ByteClass::IdStart => {
+            let end = run(start, |class| matches!(class, ByteClass::IdStart | ByteClass::Digit));
+            if class_at(bytes, end) == Some(ByteClass::NonAscii) {
+                return None;
+            }
+            (TokenType::Identifier, end, None)
+        }
+        ByteClass::Digit => {
+            let end = start + bytes[start..].iter().take_while(|&&byte| byte.is_ascii_digit() || byte == b'_').count();
+            if matches!(class_at(bytes, end), Some(ByteClass::IdStart | ByteClass::NonAscii)) || bytes.get(end) == Some(&b'.') {
+                return None;
+            }
+            // 具体是哪一种数字由 Scanner 保存时确定
+            (TokenType::IntLiteral, end, None)
+        }
+        ByteClass::Operator => {
+            // 最长匹配。运算符后面的字符大多不是运算符，这时不用再查表
+            let mut end = start + 1;
+            while class_at(bytes, end) == Some(ByteClass::Operator) && operator_type(&script[start..end + 1]).is_some() {
+                end += 1;
+            }
+            (operator_type(&script[start..end]).unwrap(), end, None)
+        }
+        ByteClass::Slash => {
+            match bytes.get(start + 1) {
+                Some(b'/') => {
+                    let end = start + bytes[start..].iter().position(|&byte| byte == b'\n' || byte == b'\r').unwrap_or(bytes.len() - start);
+                    (TokenType::Comment, end, None)
+                }
+                Some(b'*') => return None,
+                Some(b'=') => (TokenType::SlashAssign, start + 2, None),
+                _ => (TokenType::Slash, start + 1, None),
+            }
+        }
+        ByteClass::Punct => {
+            let token_type = match bytes[start] {
+                b';' => TokenType::SemiColon,
+                b'(' => TokenType::LeftParen,
+                b')' => TokenType::RightParen,
+                b'{' => TokenType::LeftBrace,
+                b'}' => TokenType::RightBrace,
+                b',' => TokenType::Comma,
+                _ => TokenType::Colon,
+            };
+            (token_type, start + 1, None)
+        }
+        ByteClass::Quote => {
+            // 没有转义的字符串，值就是引号之间的内容
+            let len = bytes[start + 1..].iter().position(|&byte| matches!(byte, b'"' | b'\\' | b'\n' | b'\r'))?;
+            let end = start + 1 + len;
+            if bytes[end] != b'"' {
+                return None;
+            }
+            (TokenType::StringLiteral, end + 1, Some(TokenValue::Str(script[start + 1..end].to_string())))
+        }
+        ByteClass::Other | ByteClass::NonAscii => return None,
+    };
+
+    // Token 中没有换行，列号按字符数增加，多字节字符的后续字节不算
+    let text = &script[start..end];
+    let begin = scanner.pos;
+    let columns = text.bytes().filter(|&byte| byte & 0xc0 != 0x80).count();
+    let end_pos = Position {
+        line: begin.line,
+        column: begin.column + columns,
+        offset: begin.offset + text.len(),
+    };
+
+    scanner.emit(token_type, text, Span::new(begin, end_pos), value);
+    scanner.pos = end_pos;
+    Some(end)
+}
@@ -0,0 +1,328 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use crate::lexer::generator::nfa::Nfa;
+use crate::lexer::generator::regex::CharSet;
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::generator::nfa::Nfa;
+    use crate::lexer::generator::regex;
+
+    use super::Dfa;
+
+    fn build(patterns: &[&str]) -> Dfa {
+        let rules: Vec<_> = patterns.iter().map(|p| regex::parse(p).unwrap()).collect();
+        Dfa::from_nfa(&Nfa::from_rules(&rules))
+    }
+
+    fn run(dfa: &Dfa, text: &str) -> Option<usize> {
+        let mut state = dfa.start();
+        for ch in text.chars() {
+            state = dfa.next(state, ch)?;
+        }
+        dfa.accept(state)
+    }
+
+    #[test]
+    pub fn test_subset_construction() {
+        let dfa = build(&["if", "[a-z]+", "[0-9]+"]);
+        assert_eq!(run(&dfa, "if"), Some(0));
+        assert_eq!(run(&dfa, "i"), Some(1));
+        assert_eq!(run(&dfa, "iff"), Some(1));
+        assert_eq!(run(&dfa, "42"), Some(2));
+        assert_eq!(run(&dfa, "4a"), None);
+    }
+
+    #[test]
+    pub fn test_minimize() {
+        // 龙书上的经典例子，最小 DFA 有 4 个状态
+        let dfa = build(&["(a|b)*abb"]);
+        let minimized = dfa.minimize();
+        assert!(dfa.state_count() >= minimized.state_count());
+        assert_eq!(minimized.state_count(), 4);
+        for text in ["abb", "aabb", "babb", "ab", "abba", ""] {
+            assert_eq!(run(&dfa, text), run(&minimized, text), "run {}", text);
+        }
+
+        // 不同规则的接受状态不能合并
+        let minimized = build(&["a", "b", "[ab]c"]).minimize();
+        assert_eq!(run(&minimized, "a"), Some(0));
+        assert_eq!(run(&minimized, "b"), Some(1));
+        assert_eq!(run(&minimized, "bc"), Some(2));
+        assert_eq!(minimized.state_count(), 4);
+    }
+
+    #[test]
+    pub fn test_non_greedy() {
+        // 非贪婪的规则在第一个 */ 处停止，和它共用状态的贪婪规则还能继续
+        let rules: Vec<_> = [r"/\*[a-z*/]*\*/", r"/\*[a-z*/]*"].iter().map(|p| regex::parse(p).unwrap()).collect();
+        let dfa = Dfa::from_nfa(&Nfa::from_rules(&rules).with_non_greedy(&[true, false])).minimize();
+        assert_eq!(run(&dfa, "/*a*/"), Some(0));
+        assert_eq!(run(&dfa, "/*a*/b"), Some(1));
+        assert_eq!(run(&dfa, "/*a*/b*/"), Some(1));
+    }
+}
+
+
+/// 状态转换表中表示“没有转换”的死状态
+pub const DEAD: usize = usize::MAX;
+
+/// 确定的有限自动机。输入字符先映射到字符类，再按 [状态][字符类] 查转换表
+#[derive(Debug, Clone)]
+pub struct Dfa {
+    // 字符类：按所有转换条件把字符切分成的互不相交的区间，按顺序排列，下标就是字符类的编号
+    classes: Vec<(char, char)>,
+    transitions: Vec<Vec<usize>>,
+    accepts: Vec<Option<usize>>,
+    start: usize,
+}
+
+impl Dfa {
+    // 子集构造法：DFA 的每个状态对应 NFA 的一个状态集合
+    pub fn from_nfa(nfa: &Nfa) -> Dfa {
+        let sets: Vec<&CharSet> = nfa.states.iter()
+            .flat_map(|state| state.transitions.iter().map(|(set, _)| set))
+            .collect();
+        let classes = partition(&sets);
+
+        let mut dfa = Dfa {
+            classes,
+            transitions: Vec::new(),
+            accepts: Vec::new(),
+            start: 0,
+        };
+
+        let mut ids: HashMap<BTreeSet<usize>, usize> = HashMap::new();
+        let mut queue = VecDeque::new();
+        let start = nfa.stop_after_accept(nfa.epsilon_closure(&BTreeSet::from([nfa.start])));
+        dfa.add_state(nfa, &start);
+        ids.insert(start.clone(), 0);
+        queue.push_back(start);
+
+        while let Some(current) = queue.pop_front() {
+            let from = ids[&current];
+            for class in 0..dfa.classes.len() {
+                let ch = dfa.classes[class].0;
+                let moved: BTreeSet<usize> = current.iter()
+                    .flat_map(|&s| nfa.states[s].transitions.iter())
+                    .filter(|(set, _)| set.contains(ch))
+                    .map(|&(_, to)| to)
+                    .collect();
+                if moved.is_empty() {
+                    continue;
+                }
+
+                let target = nfa.stop_after_accept(nfa.epsilon_closure(&moved));
+                let to = match ids.get(&target) {
+                    Some(&to) => to,
+                    None => {
+                        let to = dfa.add_state(nfa, &target);
+                        ids.insert(target.clone(), to);
+                        queue.push_back(target);
+                        to
+                    }
+                };
+                dfa.transitions[from][class] = to;
+            }
+        }
+
+        dfa
+    }
+
+    fn add_state(&mut self, nfa: &Nfa, states: &BTreeSet<usize>) -> usize {
+        // 同时接受多条规则时，取优先级最高（下标最小）的那条
+        let accept = states.iter().filter_map(|&s| nfa.states[s].accept).min();
+        self.transitions.push(vec![DEAD; self.classes.len()]);
+        self.accepts.push(accept);
+        self.transitions.len() - 1
+    }
+
+    /*
+    Hopcroft 算法最小化 DFA：
+    先按接受的规则把状态分组（不接受的状态、死状态在同一组），然后不断用某一组 A 作为划分者：
+    对每个字符类 c，找出经过 c 能到达 A 的状态集合 X，把和 X 部分相交的组一分为二，
+    直到没有组可以再分。最后每一组就是最小 DFA 的一个状态，和死状态同组的状态都被去掉。
+    */
+    pub fn minimize(&self) -> Dfa {
+        let count = self.transitions.len();
+        let dead = count;
+        let class_count = self.classes.len();
+        let target = |state: usize, class: usize| {
+            if state == dead || self.transitions[state][class] == DEAD {
+                dead
+            } else {
+                self.transitions[state][class]
+            }
+        };
+
+        // 反向转换：inverse[c][t] 是经过 c 到达 t 的所有状态
+        let mut inverse = vec![vec![Vec::new(); count + 1]; class_count];
+        for state in 0..=count {
+            for (class, inverse) in inverse.iter_mut().enumerate() {
+                inverse[target(state, class)].push(state);
+            }
+        }
+
+        // 初始划分
+        let mut blocks: Vec<Vec<usize>> = Vec::new();
+        let mut block_of = vec![0; count + 1];
+        let mut labels: HashMap<Option<usize>, usize> = HashMap::new();
+        for (state, state_block) in block_of.iter_mut().enumerate() {
+            let accept = if state == dead { None } else { self.accepts[state] };
+            let block = *labels.entry(accept).or_insert_with(|| {
+                blocks.push(Vec::new());
+                blocks.len() - 1
+            });
+            blocks[block].push(state);
+            *state_block = block;
+        }
+
+        let mut worklist: Vec<usize> = (0..blocks.len()).collect();
+        let mut in_worklist = vec![true; blocks.len()];
+        while let Some(splitter) = worklist.pop() {
+            in_worklist[splitter] = false;
+            let splitter_states = blocks[splitter].clone();
+
+            for inverse in &inverse {
+                // 按所在的组收集 X 中的状态
+                let mut touched: HashMap<usize, Vec<usize>> = HashMap::new();
+                for &to in &splitter_states {
+                    for &from in &inverse[to] {
+                        touched.entry(block_of[from]).or_default().push(from);
+                    }
+                }
+
+                let mut touched: Vec<(usize, Vec<usize>)> = touched.into_iter().collect();
+                touched.sort();
+                for (block, mut members) in touched {
+                    members.sort();
+                    members.dedup();
+                    if members.len() == blocks[block].len() {
+                        continue;
+                    }
+
+                    let new_block = blocks.len();
+                    blocks[block].retain(|state| members.binary_search(state).is_err());
+                    for &state in &members {
+                        block_of[state] = new_block;
+                    }
+                    blocks.push(members);
+
+                    if in_worklist[block] {
+                        in_worklist.push(true);
+                        worklist.push(new_block);
+                    } else {
+                        let smaller = if blocks[block].len() <= blocks[new_block].len() { block } else { new_block };
+                        in_worklist.push(false);
+                        in_worklist[smaller] = true;
+                        worklist.push(smaller);
+                    }
+                }
+            }
+        }
+
+        // 从开始状态按广度优先给新状态编号，和死状态同组的状态不再保留
+        let dead_block = block_of[dead];
+        let mut ids: HashMap<usize, usize> = HashMap::new();
+        let mut order = vec![block_of[self.start]];
+        ids.insert(block_of[self.start], 0);
+        let mut i = 0;
+        while i < order.len() {
+            let representative = blocks[order[i]][0];
+            for class in 0..class_count {
+                let to = block_of[target(representative, class)];
+                if to != dead_block && !ids.contains_key(&to) {
+                    ids.insert(to, order.len());
+                    order.push(to);
+                }
+            }
+            i += 1;
+        }
+
+        let mut minimized = Dfa {
+            classes: self.classes.clone(),
+            transitions: Vec::new(),
+            accepts: Vec::new(),
+            start: 0,
+        };
+        for &block in &order {
+            let representative = blocks[block][0];
+            let row = (0..class_count)
+                .map(|class| {
+                    let to = block_of[target(representative, class)];
+                    if to == dead_block { DEAD } else { ids[&to] }
+                })
+                .collect();
+            minimized.transitions.push(row);
+            minimized.accepts.push(if representative == dead { None } else { self.accepts[representative] });
+        }
+
+        minimized
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn classes(&self) -> &[(char, char)] {
+        &self.classes
+    }
+
+    // 状态接受的规则编号，不是接受状态时返回None
+    pub fn accept(&self, state: usize) -> Option<usize> {
+        self.accepts[state]
+    }
+
+    // 字符所在的字符类，不在任何字符类中时返回None
+    pub fn class_of(&self, ch: char) -> Option<usize> {
+        let index = self.classes.partition_point(|&(_, hi)| hi < ch);
+        match self.classes.get(index) {
+            Some(&(lo, _)) if lo <= ch => Some(index),
+            _ => None,
+        }
+    }
+
+    // 按字符类转换
+    pub fn next_by_class(&self, state: usize, class: usize) -> Option<usize> {
+        match self.transitions[state][class] {
+            DEAD => None,
+            to => Some(to),
+        }
+    }
+
+    pub fn next(&self, state: usize, ch: char) -> Option<usize> {
+        self.next_by_class(state, self.class_of(ch)?)
+    }
+}
+
+// 把所有字符集合的区间端点收集起来，切分出互不相交的区间，只保留被某个集合覆盖的区间
+fn partition(sets: &[&CharSet]) -> Vec<(char, char)> {
+    let mut bounds = BTreeSet::new();
+    for set in sets {
+        for &(lo, hi) in set.ranges() {
+            bounds.insert(lo as u32);
+            bounds.insert(hi as u32 + 1);
+        }
+    }
+
+    let bounds: Vec<u32> = bounds.into_iter().collect();
+    let mut classes = Vec::new();
+    for pair in bounds.windows(2) {
+        let lo = if (0xD800..=0xDFFF).contains(&pair[0]) { 0xE000 } else { pair[0] };
+        let hi = if (0xD800..=0xDFFF).contains(&(pair[1] - 1)) { 0xD7FF } else { pair[1] - 1 };
+        if lo > hi {
+            continue;
+        }
+
+        let lo = char::from_u32(lo).unwrap();
+        if sets.iter().any(|set| set.contains(lo)) {
+            classes.push((lo, char::from_u32(hi).unwrap()));
+        }
+    }
+
+    classes
+}
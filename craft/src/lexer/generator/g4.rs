@@ -0,0 +1,723 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::lexer::{Position, TokenType};
+use crate::lexer::export::Vocabulary;
+use crate::lexer::generator::regex::{CharSet, Regex};
+use crate::lexer::generator::regex_lexer::{LexRule, RegexLexer};
+use crate::lexer::simple_calculator;
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{Lexer, Token, TokenReader, TokenType};
+
+    use super::LexerGrammar;
+
+    fn token_names(grammar: &LexerGrammar, script: &str) -> Vec<(String, String)> {
+        let mut token_reader = grammar.build_lexer().tokenize(script).unwrap();
+        let mut tokens = Vec::new();
+        while let Some(token) = token_reader.read() {
+            let name = grammar.token_name(token.get_type()).unwrap().to_string();
+            tokens.push((name, token.get_text().to_string()));
+        }
+        tokens
+    }
+
+    #[test]
+    pub fn test_hello() {
+        let grammar = LexerGrammar::parse(include_str!("../../../../antlr-test/hello/hello.g4")).unwrap();
+        assert_eq!(grammar.get_name(), "hello");
+        assert_eq!(grammar.token_type("If"), Some(TokenType::Custom(1)));
+        assert_eq!(grammar.token_type("Newline"), Some(TokenType::Custom(21)));
+
+        let tokens = token_names(&grammar, "int age = 45;\nif (age >= 17+8+20){\n    printf(\"Hello old man!\" \"x\");\n}");
+        let expected = [
+            ("Int", "int"), ("Id", "age"), ("AssignmentOP", "="), ("IntLiteral", "45"), ("SemiColon", ";"),
+            ("If", "if"), ("LeftParen", "("), ("Id", "age"), ("RelationalOP", ">="), ("IntLiteral", "17"),
+            ("Plus", "+"), ("IntLiteral", "8"), ("Plus", "+"), ("IntLiteral", "20"), ("RightParen", ")"),
+            ("LeftBrace", "{"), ("Id", "printf"), ("LeftParen", "("), ("StringLiteral", "\"Hello old man!\""),
+            ("StringLiteral", "\"x\""), ("RightParen", ")"), ("SemiColon", ";"), ("RightBrace", "}"),
+        ];
+        let expected: Vec<(String, String)> = expected.iter().map(|(n, t)| (n.to_string(), t.to_string())).collect();
+        assert_eq!(tokens, expected);
+
+        // 如果 不是 hello.g4 中的合法字符
+        let (_, errors) = grammar.build_lexer().tokenize_with_recovery("如果 (a)");
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    pub fn test_common_lexer() {
+        let grammar = LexerGrammar::parse(include_str!("../../../../antlr-test/play-script/CommonLexer.g4")).unwrap();
+        let tokens = token_names(&grammar, "int x = 0x1F + 1_000L * 1.5e3f; /* 注释 */ if (x >>= 'a' && s != \"\\t\") return null; // end");
+        let names: Vec<&str> = tokens.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec![
+            "INT", "Identifier", "ASSIGN", "IntegerLiteral", "ADD", "IntegerLiteral", "MUL", "FloatingPointLiteral",
+            "SEMI", "IF", "LPAREN", "Identifier", "RSHIFT_ASSIGN", "CharacterLiteral", "AND", "Identifier", "NOTEQUAL",
+            "StringLiteral", "RPAREN", "RETURN", "NullLiteral", "SEMI",
+        ]);
+
+        // -> channel(HIDDEN) 的规则放在注释里
+        let token_reader = grammar.build_lexer().tokenize("a /* b */ // c").unwrap();
+        assert_eq!(token_reader.get_comments().len(), 2);
+    }
+
+    #[test]
+    pub fn test_repeat_after_space() {
+        // * + ? 和前面的元素之间可以有空白和注释
+        let grammar = LexerGrammar::parse("lexer grammar A; B : 'a' + ; C : [0-9] * 'x' ; D : 'd' /* c */ ? 'e' ; WS : ' ' -> skip ;").unwrap();
+        let tokens = token_names(&grammar, "aaa 12x x e de");
+        let expected = [("B", "aaa"), ("C", "12x"), ("C", "x"), ("D", "e"), ("D", "de")];
+        let expected: Vec<(String, String)> = expected.iter().map(|(n, t)| (n.to_string(), t.to_string())).collect();
+        assert_eq!(tokens, expected);
+
+        // 非贪婪的规则到达接受状态就停止，不影响其他规则
+        let grammar = LexerGrammar::parse("lexer grammar A; S : '\"' .*? '\"' ; T : '\"' [a-z\"]* '!' ; WS : ' ' -> skip ;").unwrap();
+        let tokens = token_names(&grammar, "\"a\" \"b\"c\"! \"d\"\"\"");
+        let expected = [("S", "\"a\""), ("T", "\"b\"c\"!"), ("S", "\"d\""), ("S", "\"\"")];
+        let expected: Vec<(String, String)> = expected.iter().map(|(n, t)| (n.to_string(), t.to_string())).collect();
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    pub fn test_non_greedy_error() {
+        for (source, message) in [
+            ("lexer grammar A; B : 'a'*? 'b'+ ;", "non-greedy repeat in lexer rule B must not be followed by another repeat"),
+            ("lexer grammar A; B : '\"' .*? '\"' [a-z]* ;", "non-greedy repeat in lexer rule B must not be followed by another repeat"),
+            ("lexer grammar A; B : S 'x'? ; fragment S : '\"' .*? '\"' ;", "non-greedy repeat in lexer rule B must not be followed by another repeat"),
+            ("lexer grammar A; B : ('<' .*? '>')+ ;", "non-greedy repeat in lexer rule B must not be repeated"),
+        ] {
+            let err = LexerGrammar::parse(source).err().unwrap();
+            assert_eq!(err.to_string(), message, "parse {}", source);
+        }
+    }
+
+    #[test]
+    pub fn test_parse_error() {
+        let cases = [
+            "lexer grammar A; B : 'b'",
+            "lexer grammar A; B : C ;",
+            "lexer grammar A; B : 'b' -> more ;",
+            "lexer grammar A; B : B 'b' ;",
+            "lexer grammar A; B : ~'ab' ;",
+            "parser grammar A;",
+        ];
+
+        for source in cases {
+            assert!(LexerGrammar::parse(source).is_err(), "parse {}", source);
+        }
+    }
+}
+
+
+/// 从 ANTLR 的 .g4 文件加载的词法规则。支持 lexer grammar 的一个子集：
+/// 字符串字面量、字符集合 [...]、取反 ~、'a'..'z'、.、分组、| * + ?、非贪婪的 *? +? ??、
+/// fragment、规则之间的引用，以及 -> skip 和 -> channel(...)。语义谓词 {...}? 和动作 {...} 会被忽略。
+///
+/// 非贪婪的规则到达接受状态就停止匹配，所以非贪婪重复之后不能再有别的重复，比如 '/*' .*? '*/' 可以，
+/// '"' .*? '"' [a-z]* 会报错。
+pub struct LexerGrammar {
+    name: String,
+    // 非 fragment 的规则，按定义的顺序排列，编号从 1 开始，和 ANTLR 生成的 .tokens 文件一致
+    tokens: Vec<GrammarRule>,
+    fragments: HashMap<String, GrammarRule>,
+}
+
+struct GrammarRule {
+    name: String,
+    body: Element,
+    command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Emit,
+    Skip,
+    Hidden,
+}
+
+// 规则右边的语法树，引用其他规则的地方在生成词法分析器的时候才展开
+#[derive(Clone)]
+enum Element {
+    Regex(Regex),
+    Ref(String),
+    Concat(Vec<Element>),
+    Alt(Vec<Element>),
+    Not(Box<Element>),
+    Repeat(Box<Element>, char, bool),
+}
+
+impl Element {
+    // 只匹配一段固定文本时返回这段文本，比如 'if'、'>' '='
+    fn as_literal(&self) -> Option<String> {
+        match self {
+            Element::Regex(regex) => regex.as_literal(),
+            Element::Concat(items) => items.iter().map(Element::as_literal).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl LexerGrammar {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<LexerGrammar, io::Error> {
+        let source = fs::read_to_string(path)?;
+        LexerGrammar::parse(&source)
+    }
+
+    pub fn parse(source: &str) -> Result<LexerGrammar, io::Error> {
+        let mut parser = GrammarParser {
+            chars: source.chars().collect(),
+            index: 0,
+            pos: Position::new(),
+        };
+        let grammar = parser.grammar()?;
+
+        // 提前展开一遍，尽早发现引用了不存在的规则、递归的规则、不支持的非贪婪重复等问题
+        for rule in &grammar.tokens {
+            grammar.expand(&rule.body, &mut Vec::new())?;
+            grammar.check_lazy(&rule.name, &rule.body)?;
+        }
+
+        Ok(grammar)
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    // 规则名对应的 Token 类型
+    pub fn token_type(&self, name: &str) -> Option<TokenType> {
+        self.tokens.iter()
+            .position(|rule| rule.name == name)
+            .map(|index| TokenType::Custom(index as u32 + 1))
+    }
+
+    // Token 类型对应的规则名
+    pub fn token_name(&self, token_type: TokenType) -> Option<&str> {
+        match token_type {
+            TokenType::Custom(id) if id >= 1 => self.tokens.get(id as usize - 1).map(|rule| rule.name.as_str()),
+            _ => None,
+        }
+    }
+
+    // 按定义的顺序返回所有 Token 的名字和编号
+    pub fn vocabulary(&self) -> Vec<(&str, u32)> {
+        self.tokens.iter()
+            .enumerate()
+            .map(|(index, rule)| (rule.name.as_str(), index as u32 + 1))
+            .collect()
+    }
+
+    // 生成 .tokens 文件中的词汇表：只匹配一段固定文本的规则还会写出它的字面量
+    pub fn to_vocabulary(&self) -> Vocabulary {
+        let mut vocabulary = Vocabulary::new();
+        for (name, id) in self.vocabulary() {
+            vocabulary.add_name(name, id);
+        }
+        for (index, rule) in self.tokens.iter().enumerate() {
+            if let Some(literal) = rule.body.as_literal() {
+                vocabulary.add_literal(&literal, index as u32 + 1);
+            }
+        }
+        vocabulary
+    }
+
+    pub fn build_lexer(&self) -> RegexLexer {
+        let rules = self.tokens.iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                let regex = self.expand(&rule.body, &mut Vec::new()).expect("rules are checked when the grammar is parsed");
+                let (non_greedy, _) = self.check_lazy(&rule.name, &rule.body).expect("rules are checked when the grammar is parsed");
+                let token_type = match rule.command {
+                    Command::Skip => None,
+                    _ => Some(TokenType::Custom(index as u32 + 1)),
+                };
+
+                let mut lex_rule = LexRule::from_regex(token_type, regex);
+                if rule.command == Command::Hidden {
+                    lex_rule = lex_rule.hidden();
+                }
+                if non_greedy {
+                    lex_rule = lex_rule.non_greedy();
+                }
+                lex_rule
+            })
+            .collect();
+
+        RegexLexer::new(rules)
+    }
+
+    // 引用的规则，fragment 优先
+    fn find_rule(&self, name: &str) -> Option<&GrammarRule> {
+        self.fragments.get(name).or_else(|| self.tokens.iter().find(|rule| rule.name == name))
+    }
+
+    /*
+    检查规则中的非贪婪重复，返回（是否有非贪婪重复，是否有任何重复）。
+    非贪婪的规则一到达接受状态就停止匹配，只有非贪婪重复之后没有别的重复、它自己也不被重复时，
+    这和 ANTLR 的结果才是一样的，比如 '/*' .*? '*/'。其他的写法报错，而不是悄悄地少匹配一部分。
+    引用的规则已经在 expand 中检查过，这里不会遇到递归或者不存在的规则。
+    */
+    fn check_lazy(&self, rule: &str, element: &Element) -> Result<(bool, bool), io::Error> {
+        let unsupported = |reason: &str| {
+            let msg = format!("non-greedy repeat in lexer rule {} {}", rule, reason);
+            Err(simple_calculator::invalid_input_err(msg.as_str()))
+        };
+
+        match element {
+            Element::Regex(_) | Element::Not(_) => Ok((false, false)),
+            Element::Ref(name) => match self.find_rule(name) {
+                Some(referenced) => self.check_lazy(rule, &referenced.body),
+                None => Ok((false, false)),
+            },
+            Element::Concat(items) => {
+                let (mut lazy, mut repeat) = (false, false);
+                for item in items {
+                    let (item_lazy, item_repeat) = self.check_lazy(rule, item)?;
+                    if lazy && item_repeat {
+                        return unsupported("must not be followed by another repeat");
+                    }
+                    lazy |= item_lazy;
+                    repeat |= item_repeat;
+                }
+                Ok((lazy, repeat))
+            }
+            Element::Alt(items) => {
+                let (mut lazy, mut repeat) = (false, false);
+                for item in items {
+                    let (item_lazy, item_repeat) = self.check_lazy(rule, item)?;
+                    lazy |= item_lazy;
+                    repeat |= item_repeat;
+                }
+                Ok((lazy, repeat))
+            }
+            Element::Repeat(item, _, lazy) => {
+                if self.check_lazy(rule, item)?.0 {
+                    return unsupported("must not be repeated");
+                }
+                Ok((*lazy, true))
+            }
+        }
+    }
+
+    // 把规则的语法树展开成正则表达式，stack 记录正在展开的规则，用来发现递归
+    fn expand(&self, element: &Element, stack: &mut Vec<String>) -> Result<Regex, io::Error> {
+        let regex = match element {
+            Element::Regex(regex) => regex.clone(),
+            Element::Ref(name) => {
+                if stack.contains(name) {
+                    let msg = format!("recursive lexer rule {}", name);
+                    return Err(simple_calculator::invalid_input_err(msg.as_str()));
+                }
+
+                let rule = match self.find_rule(name) {
+                    Some(rule) => rule,
+                    None => {
+                        let msg = format!("undefined lexer rule {}", name);
+                        return Err(simple_calculator::invalid_input_err(msg.as_str()));
+                    }
+                };
+
+                stack.push(name.clone());
+                let regex = self.expand(&rule.body, stack)?;
+                stack.pop();
+                regex
+            }
+            Element::Concat(items) => {
+                let items: Result<Vec<Regex>, io::Error> = items.iter()
+                    .map(|item| self.expand(item, stack))
+                    .collect();
+                Regex::Concat(items?)
+            }
+            Element::Alt(items) => {
+                let items: Result<Vec<Regex>, io::Error> = items.iter()
+                    .map(|item| self.expand(item, stack))
+                    .collect();
+                Regex::Alt(items?)
+            }
+            Element::Not(item) => {
+                let regex = self.expand(item, stack)?;
+                match char_set(&regex) {
+                    Some(set) => Regex::Set(set.negate()),
+                    None => return Err(simple_calculator::invalid_input_err("~ can only be applied to a set of characters")),
+                }
+            }
+            Element::Repeat(item, op, _) => {
+                let item = Box::new(self.expand(item, stack)?);
+                match op {
+                    '*' => Regex::Star(item),
+                    '+' => Regex::Plus(item),
+                    _ => Regex::Optional(item),
+                }
+            }
+        };
+
+        Ok(regex)
+    }
+}
+
+// 只由单个字符组成的正则表达式可以看成一个字符集合，用于 ~ 取反
+fn char_set(regex: &Regex) -> Option<CharSet> {
+    match regex {
+        Regex::Set(set) => Some(set.clone()),
+        Regex::Concat(items) if items.len() == 1 => char_set(&items[0]),
+        Regex::Alt(items) => {
+            let mut set = CharSet::new();
+            for item in items {
+                set.union(&char_set(item)?);
+            }
+            Some(set)
+        }
+        _ => None,
+    }
+}
+
+
+/*
+.g4 文件的递归下降解析：
+    grammar     : 'lexer' 'grammar' NAME ';' (options | rule)*
+    rule        : 'fragment'? NAME ':' alternatives ('->' commands)? ';'
+    alternatives: sequence ('|' sequence)*
+    sequence    : element*
+    element     : atom ('*' | '+' | '?')? '?'?
+    atom        : STRING ('..' STRING)? | '[' set ']' | '~' atom | '.' | NAME | '(' alternatives ')' | action
+*/
+struct GrammarParser {
+    chars: Vec<char>,
+    index: usize,
+    pos: Position,
+}
+
+impl GrammarParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.index += 1;
+        self.pos.advance(ch);
+        Some(ch)
+    }
+
+    fn error(&self, err: &str) -> io::Error {
+        simple_calculator::invalid_input_err_at(err, self.pos)
+    }
+
+    // 跳过空白和注释
+    fn skip_trivia(&mut self) {
+        loop {
+            match (self.peek(), self.chars.get(self.index + 1)) {
+                (Some(ch), _) if ch.is_whitespace() => {
+                    self.next();
+                }
+                (Some('/'), Some('/')) => {
+                    while self.peek().is_some_and(|ch| ch != '\n') {
+                        self.next();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    self.next();
+                    self.next();
+                    while self.peek().is_some() && !(self.peek() == Some('*') && self.chars.get(self.index + 1) == Some(&'/')) {
+                        self.next();
+                    }
+                    self.next();
+                    self.next();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // 跳过空白之后看下一个字符
+    fn lookahead(&mut self) -> Option<char> {
+        self.skip_trivia();
+        self.peek()
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        self.skip_trivia();
+        let matched = text.chars().enumerate().all(|(i, ch)| self.chars.get(self.index + i) == Some(&ch));
+        if matched {
+            text.chars().for_each(|_| {
+                self.next();
+            });
+        }
+        matched
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), io::Error> {
+        if self.eat(text) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expecting '{}'", text).as_str()))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, io::Error> {
+        self.skip_trivia();
+        let mut name = String::new();
+        while let Some(ch) = self.peek() {
+            if !(ch.is_alphanumeric() || ch == '_') {
+                break;
+            }
+            name.push(ch);
+            self.next();
+        }
+
+        if name.is_empty() {
+            return Err(self.error("expecting a name"));
+        }
+        Ok(name)
+    }
+
+    fn grammar(&mut self) -> Result<LexerGrammar, io::Error> {
+        if self.name()? != "lexer" {
+            return Err(self.error("only lexer grammars are supported"));
+        }
+        if self.name()? != "grammar" {
+            return Err(self.error("expecting 'grammar'"));
+        }
+
+        let mut grammar = LexerGrammar {
+            name: self.name()?,
+            tokens: Vec::new(),
+            fragments: HashMap::new(),
+        };
+        self.expect(";")?;
+
+        while self.lookahead().is_some() {
+            let mut name = self.name()?;
+            match name.as_str() {
+                // options { ... } 之类的设置对词法规则没有影响
+                "options" | "channels" | "tokens" => {
+                    self.skip_action()?;
+                    continue;
+                }
+                "mode" | "import" => return Err(self.error(format!("'{}' is not supported", name).as_str())),
+                _ => (),
+            }
+
+            let fragment = name == "fragment";
+            if fragment {
+                name = self.name()?;
+            }
+
+            self.expect(":")?;
+            let body = self.alternatives()?;
+            let command = self.command()?;
+            self.expect(";")?;
+
+            let rule = GrammarRule { name: name.clone(), body, command };
+            if fragment {
+                grammar.fragments.insert(name, rule);
+            } else {
+                grammar.tokens.push(rule);
+            }
+        }
+
+        Ok(grammar)
+    }
+
+    // -> skip、-> channel(HIDDEN)
+    fn command(&mut self) -> Result<Command, io::Error> {
+        if !self.eat("->") {
+            return Ok(Command::Emit);
+        }
+
+        match self.name()?.as_str() {
+            "skip" => Ok(Command::Skip),
+            "channel" => {
+                self.expect("(")?;
+                self.name()?;
+                self.expect(")")?;
+                Ok(Command::Hidden)
+            }
+            other => Err(self.error(format!("unsupported lexer command '{}'", other).as_str())),
+        }
+    }
+
+    fn alternatives(&mut self) -> Result<Element, io::Error> {
+        let mut items = vec![self.sequence()?];
+        while self.eat("|") {
+            items.push(self.sequence()?);
+        }
+
+        Ok(if items.len() == 1 { items.remove(0) } else { Element::Alt(items) })
+    }
+
+    fn sequence(&mut self) -> Result<Element, io::Error> {
+        let mut items = Vec::new();
+        loop {
+            match self.lookahead() {
+                Some('|') | Some(';') | Some(')') | None => break,
+                Some('-') if self.chars.get(self.index + 1) == Some(&'>') => break,
+                // 语义谓词和动作在这里没有意义，直接忽略
+                Some('{') => {
+                    self.skip_action()?;
+                    self.eat("?");
+                }
+                _ => items.push(self.element()?),
+            }
+        }
+
+        Ok(Element::Concat(items))
+    }
+
+    fn element(&mut self) -> Result<Element, io::Error> {
+        let atom = self.atom()?;
+        // 和 atom() 一样跳过空白和注释，'a' + 与 'a'+ 是等价的
+        let op = match self.lookahead() {
+            Some(op) if op == '*' || op == '+' || op == '?' => op,
+            _ => return Ok(atom),
+        };
+        self.next();
+
+        let lazy = self.lookahead() == Some('?');
+        if lazy {
+            self.next();
+        }
+
+        Ok(Element::Repeat(Box::new(atom), op, lazy))
+    }
+
+    fn atom(&mut self) -> Result<Element, io::Error> {
+        match self.lookahead() {
+            Some('\'') => {
+                let text = self.string()?;
+                if !self.eat("..") {
+                    return Ok(Element::Regex(Regex::literal(&text)));
+                }
+
+                let to = self.string()?;
+                let mut from = text.chars();
+                let mut to = to.chars();
+                match (from.next(), from.next(), to.next(), to.next()) {
+                    (Some(lo), None, Some(hi), None) if lo <= hi => Ok(Element::Regex(Regex::Set(CharSet::range(lo, hi)))),
+                    _ => Err(self.error("invalid character range")),
+                }
+            }
+            Some('[') => {
+                self.next();
+                Ok(Element::Regex(Regex::Set(self.set()?)))
+            }
+            Some('~') => {
+                self.next();
+                Ok(Element::Not(Box::new(self.atom()?)))
+            }
+            Some('.') => {
+                self.next();
+                Ok(Element::Regex(Regex::Set(CharSet::all())))
+            }
+            Some('(') => {
+                self.next();
+                let element = self.alternatives()?;
+                self.expect(")")?;
+                Ok(element)
+            }
+            Some(ch) if ch.is_alphabetic() => Ok(Element::Ref(self.name()?)),
+            _ => Err(self.error("unexpected character in lexer rule")),
+        }
+    }
+
+    // '...' 形式的字符串
+    fn string(&mut self) -> Result<String, io::Error> {
+        self.expect("'")?;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => break,
+                Some('\\') => {
+                    let code = self.escape()?;
+                    text.push(char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))?);
+                }
+                Some(ch) => text.push(ch),
+                None => return Err(self.error("unterminated string literal")),
+            }
+        }
+
+        Ok(text)
+    }
+
+    // [...] 形式的字符集合，开头的 [ 已经读过了
+    fn set(&mut self) -> Result<CharSet, io::Error> {
+        let mut set = CharSet::new();
+        loop {
+            let lo = match self.next() {
+                Some(']') => break,
+                Some('\\') => self.escape()?,
+                Some(ch) => ch as u32,
+                None => return Err(self.error("expecting ']'")),
+            };
+
+            let hi = if self.peek() == Some('-') && self.chars.get(self.index + 1) != Some(&']') {
+                self.next();
+                match self.next() {
+                    Some('\\') => self.escape()?,
+                    Some(ch) => ch as u32,
+                    None => return Err(self.error("expecting ']'")),
+                }
+            } else {
+                lo
+            };
+
+            if lo > hi {
+                return Err(self.error("invalid character range"));
+            }
+            set.add_code_range(lo, hi);
+        }
+
+        Ok(set)
+    }
+
+    // \ 之后的转义，返回码点。\uD800 这样的代理码点也原样返回，由字符集合去掉
+    fn escape(&mut self) -> Result<u32, io::Error> {
+        let code = match self.next() {
+            Some('n') => '\n' as u32,
+            Some('t') => '\t' as u32,
+            Some('r') => '\r' as u32,
+            Some('b') => 8,
+            Some('f') => 12,
+            Some('u') => {
+                let mut digits = String::new();
+                if self.peek() == Some('{') {
+                    self.next();
+                    while let Some(ch) = self.next() {
+                        if ch == '}' {
+                            break;
+                        }
+                        digits.push(ch);
+                    }
+                } else {
+                    for _ in 0..4 {
+                        digits.extend(self.next());
+                    }
+                }
+                u32::from_str_radix(&digits, 16).map_err(|_| self.error("invalid unicode escape"))?
+            }
+            Some(ch) => ch as u32,
+            None => return Err(self.error("unexpected end of grammar")),
+        };
+
+        Ok(code)
+    }
+
+    // 跳过 {...}，花括号可以嵌套
+    fn skip_action(&mut self) -> Result<(), io::Error> {
+        self.expect("{")?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some('{') => depth += 1,
+                Some('}') => depth -= 1,
+                Some(_) => (),
+                None => return Err(self.error("expecting '}'")),
+            }
+        }
+
+        Ok(())
+    }
+}
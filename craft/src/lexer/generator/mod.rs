@@ -0,0 +1,9 @@
+//! 词法分析器生成器：用正则表达式描述 Token，自动生成 DFA 来做词法分析。
+//!
+//! 正则表达式 --Thompson 构造--> NFA --子集构造--> DFA --Hopcroft--> 最小 DFA
+
+pub mod regex;
+pub mod nfa;
+pub mod dfa;
+pub mod regex_lexer;
+pub mod g4;
@@ -0,0 +1,182 @@
+use std::collections::BTreeSet;
+
+use crate::lexer::generator::regex::{CharSet, Regex};
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::lexer::generator::regex;
+
+    use super::Nfa;
+
+    // 直接在 NFA 上模拟，返回能整个匹配 text 的优先级最高的规则
+    fn run(nfa: &Nfa, text: &str) -> Option<usize> {
+        let mut current = nfa.epsilon_closure(&BTreeSet::from([nfa.start]));
+        for ch in text.chars() {
+            let next: BTreeSet<usize> = current.iter()
+                .flat_map(|&s| nfa.states[s].transitions.iter())
+                .filter(|(set, _)| set.contains(ch))
+                .map(|&(_, t)| t)
+                .collect();
+            current = nfa.epsilon_closure(&next);
+        }
+        current.iter().filter_map(|&s| nfa.states[s].accept).min()
+    }
+
+    #[test]
+    pub fn test_thompson() {
+        let rules = vec![
+            regex::parse("if").unwrap(),
+            regex::parse("[a-z]+").unwrap(),
+            regex::parse("(ab|c)*d?").unwrap(),
+        ];
+        let nfa = Nfa::from_rules(&rules);
+
+        assert_eq!(run(&nfa, "if"), Some(0));
+        assert_eq!(run(&nfa, "ifx"), Some(1));
+        assert_eq!(run(&nfa, "abcd"), Some(1));
+        assert_eq!(run(&nfa, ""), Some(2));
+        assert_eq!(run(&nfa, "A"), None);
+    }
+}
+
+
+/// NFA 的一个状态：ε 转换、字符集合上的转换，接受的规则编号，以及它属于哪条规则
+#[derive(Debug, Default)]
+pub struct NfaState {
+    pub epsilon: Vec<usize>,
+    pub transitions: Vec<(CharSet, usize)>,
+    pub accept: Option<usize>,
+    pub rule: Option<usize>,
+}
+
+/// 用 Thompson 构造法从正则表达式生成的 NFA
+#[derive(Debug)]
+pub struct Nfa {
+    pub states: Vec<NfaState>,
+    pub start: usize,
+    // 每条规则是否非贪婪
+    non_greedy: Vec<bool>,
+}
+
+impl Nfa {
+    // 把多条规则合并成一个 NFA：新的开始状态用 ε 连到每条规则的开始状态，
+    // 规则的结束状态记下规则的下标，下标越小优先级越高
+    pub fn from_rules(rules: &[Regex]) -> Nfa {
+        let mut nfa = Nfa {
+            states: Vec::new(),
+            start: 0,
+            non_greedy: vec![false; rules.len()],
+        };
+
+        nfa.start = nfa.add_state();
+        for (index, rule) in rules.iter().enumerate() {
+            let first = nfa.states.len();
+            let (start, end) = nfa.build(rule);
+            nfa.states[nfa.start].epsilon.push(start);
+            nfa.states[end].accept = Some(index);
+            for state in &mut nfa.states[first..] {
+                state.rule = Some(index);
+            }
+        }
+
+        nfa
+    }
+
+    // 标出非贪婪的规则
+    pub fn with_non_greedy(mut self, non_greedy: &[bool]) -> Self {
+        self.non_greedy = non_greedy.to_vec();
+        self
+    }
+
+    // 非贪婪的规则到达接受状态之后就不再继续匹配：从状态集合中去掉这条规则的其他状态，别的规则不受影响
+    pub fn stop_after_accept(&self, states: BTreeSet<usize>) -> BTreeSet<usize> {
+        let stopped: Vec<usize> = states.iter()
+            .filter_map(|&state| self.states[state].accept)
+            .filter(|&rule| self.non_greedy.get(rule) == Some(&true))
+            .collect();
+        if stopped.is_empty() {
+            return states;
+        }
+
+        states.into_iter()
+            .filter(|&state| {
+                let state = &self.states[state];
+                state.accept.is_some() || !state.rule.is_some_and(|rule| stopped.contains(&rule))
+            })
+            .collect()
+    }
+
+    fn add_state(&mut self) -> usize {
+        self.states.push(NfaState::default());
+        self.states.len() - 1
+    }
+
+    fn add_epsilon(&mut self, from: usize, to: usize) {
+        self.states[from].epsilon.push(to);
+    }
+
+    // 为 regex 构造一个 NFA 片段，返回片段的开始和结束状态
+    fn build(&mut self, regex: &Regex) -> (usize, usize) {
+        let start = self.add_state();
+        let end = self.add_state();
+
+        match regex {
+            Regex::Empty => self.add_epsilon(start, end),
+            Regex::Set(set) => self.states[start].transitions.push((set.clone(), end)),
+            Regex::Concat(items) => {
+                let mut last = start;
+                for item in items {
+                    let (s, e) = self.build(item);
+                    self.add_epsilon(last, s);
+                    last = e;
+                }
+                self.add_epsilon(last, end);
+            }
+            Regex::Alt(items) => {
+                for item in items {
+                    let (s, e) = self.build(item);
+                    self.add_epsilon(start, s);
+                    self.add_epsilon(e, end);
+                }
+            }
+            Regex::Star(item) => {
+                let (s, e) = self.build(item);
+                self.add_epsilon(start, s);
+                self.add_epsilon(start, end);
+                self.add_epsilon(e, s);
+                self.add_epsilon(e, end);
+            }
+            Regex::Plus(item) => {
+                let (s, e) = self.build(item);
+                self.add_epsilon(start, s);
+                self.add_epsilon(e, s);
+                self.add_epsilon(e, end);
+            }
+            Regex::Optional(item) => {
+                let (s, e) = self.build(item);
+                self.add_epsilon(start, s);
+                self.add_epsilon(start, end);
+                self.add_epsilon(e, end);
+            }
+        }
+
+        (start, end)
+    }
+
+    // 从一组状态出发，沿 ε 转换能到达的所有状态
+    pub fn epsilon_closure(&self, states: &BTreeSet<usize>) -> BTreeSet<usize> {
+        let mut closure = states.clone();
+        let mut stack: Vec<usize> = states.iter().copied().collect();
+        while let Some(state) = stack.pop() {
+            for &next in &self.states[state].epsilon {
+                if closure.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+
+        closure
+    }
+}
@@ -0,0 +1,392 @@
+use std::io;
+
+use crate::lexer::simple_calculator;
+
+#[cfg(test)]
+mod tests {
+    use super::{CharSet, parse, Regex};
+
+    #[test]
+    pub fn test_char_set() {
+        let mut set = CharSet::range('a', 'f');
+        set.add_range('d', 'k');
+        set.add_range('0', '9');
+        set.add_range('l', 'l');
+        assert_eq!(set.ranges(), &[('0', '9'), ('a', 'l')]);
+        assert!(set.contains('k') && !set.contains('m'));
+
+        let negated = set.negate();
+        assert!(!negated.contains('5') && negated.contains('m') && negated.contains('年'));
+        assert_eq!(negated.negate(), set);
+    }
+
+    #[test]
+    pub fn test_parse() {
+        assert_eq!(parse("ab").unwrap(), Regex::Concat(vec![
+            Regex::Set(CharSet::single('a')),
+            Regex::Set(CharSet::single('b')),
+        ]));
+        assert_eq!(parse("a|b*").unwrap(), Regex::Alt(vec![
+            Regex::Set(CharSet::single('a')),
+            Regex::Star(Box::new(Regex::Set(CharSet::single('b')))),
+        ]));
+        assert_eq!(parse("[^a-c_]").unwrap(), Regex::Set({
+            let mut set = CharSet::range('a', 'c');
+            set.add_range('_', '_');
+            set.negate()
+        }));
+        assert_eq!(parse(r"\*\u{5e74}").unwrap(), Regex::literal("*年"));
+
+        for pattern in ["(ab", "a)", "[a-", "*a", r"\u{zz}", "[z-a]"] {
+            assert!(parse(pattern).is_err(), "parse {}", pattern);
+        }
+    }
+}
+
+
+/// 字符集合，由若干个按顺序排列、互不相交也不相邻的闭区间组成。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct CharSet {
+    ranges: Vec<(char, char)>,
+}
+
+impl CharSet {
+    pub fn new() -> Self {
+        CharSet { ranges: Vec::new() }
+    }
+
+    pub fn single(ch: char) -> Self {
+        CharSet::range(ch, ch)
+    }
+
+    pub fn range(lo: char, hi: char) -> Self {
+        let mut set = CharSet::new();
+        set.add_range(lo, hi);
+        set
+    }
+
+    // 所有字符
+    pub fn all() -> Self {
+        CharSet::range('\0', char::MAX)
+    }
+
+    // 除换行以外的所有字符，也就是正则表达式里的 .
+    pub fn any() -> Self {
+        CharSet::single('\n').negate()
+    }
+
+    pub fn add_range(&mut self, lo: char, hi: char) {
+        self.ranges.push((lo, hi));
+        self.normalize();
+    }
+
+    // 按码点加入区间，落在代理码点上的部分会被去掉
+    pub fn add_code_range(&mut self, lo: u32, hi: u32) {
+        push_code_range(&mut self.ranges, lo, hi.min(char::MAX as u32));
+        self.normalize();
+    }
+
+    pub fn union(&mut self, other: &CharSet) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.normalize();
+    }
+
+    // 补集。区间的端点不会落在代理码点（U+D800..U+DFFF）上
+    pub fn negate(&self) -> CharSet {
+        let mut ranges = Vec::new();
+        let mut next = 0u32;
+        for &(lo, hi) in &self.ranges {
+            if (lo as u32) > next {
+                push_code_range(&mut ranges, next, lo as u32 - 1);
+            }
+            next = hi as u32 + 1;
+        }
+        push_code_range(&mut ranges, next, char::MAX as u32);
+
+        CharSet { ranges }
+    }
+
+    pub fn contains(&self, ch: char) -> bool {
+        self.ranges
+            .binary_search_by(|&(lo, hi)| {
+                if hi < ch {
+                    std::cmp::Ordering::Less
+                } else if lo > ch {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    // 排序并合并重叠、相邻的区间
+    fn normalize(&mut self) {
+        self.ranges.sort();
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(self.ranges.len());
+        for &(lo, hi) in &self.ranges {
+            match merged.last_mut() {
+                Some(last) if lo as u32 <= last.1 as u32 + 1 => {
+                    if hi > last.1 {
+                        last.1 = hi;
+                    }
+                }
+                _ => merged.push((lo, hi)),
+            }
+        }
+        self.ranges = merged;
+    }
+}
+
+// 把码点区间 [lo, hi] 去掉代理码点之后加入 ranges
+fn push_code_range(ranges: &mut Vec<(char, char)>, lo: u32, hi: u32) {
+    let lo = if (0xD800..=0xDFFF).contains(&lo) { 0xE000 } else { lo };
+    let hi = if (0xD800..=0xDFFF).contains(&hi) { 0xD7FF } else { hi };
+    if lo > hi {
+        return;
+    }
+
+    ranges.push((char::from_u32(lo).unwrap(), char::from_u32(hi).unwrap()));
+}
+
+
+/// 正则表达式的语法树
+#[derive(Debug, Clone, PartialEq)]
+pub enum Regex {
+    /// 空串 ε
+    Empty,
+    /// 字符集合中的任意一个字符
+    Set(CharSet),
+    /// 连接 ab
+    Concat(Vec<Regex>),
+    /// 选择 a|b
+    Alt(Vec<Regex>),
+    /// 闭包 a*
+    Star(Box<Regex>),
+    /// 正闭包 a+
+    Plus(Box<Regex>),
+    /// 可选 a?
+    Optional(Box<Regex>),
+}
+
+impl Regex {
+    // 按字面匹配一段文本
+    pub fn literal(text: &str) -> Regex {
+        let mut items: Vec<Regex> = text.chars().map(|ch| Regex::Set(CharSet::single(ch))).collect();
+        match items.len() {
+            0 => Regex::Empty,
+            1 => items.remove(0),
+            _ => Regex::Concat(items),
+        }
+    }
+
+    // 只匹配一段固定文本的正则表达式，返回这段文本
+    pub fn as_literal(&self) -> Option<String> {
+        let single = |regex: &Regex| match regex {
+            Regex::Set(set) if set.ranges.len() == 1 && set.ranges[0].0 == set.ranges[0].1 => Some(set.ranges[0].0),
+            _ => None,
+        };
+
+        match self {
+            Regex::Concat(items) => items.iter().map(single).collect(),
+            regex => single(regex).map(String::from),
+        }
+    }
+}
+
+
+/*
+解析正则表达式：
+    alt     : concat ('|' concat)*
+    concat  : repeat*
+    repeat  : atom ('*' | '+' | '?')*
+    atom    : '(' alt ')' | '[' class ']' | '.' | '\' escape | char
+支持的转义：\n \t \r \0 \d \w \s \u{...}，以及用 \ 转义任意标点符号。
+*/
+pub fn parse(pattern: &str) -> Result<Regex, io::Error> {
+    let mut parser = RegexParser {
+        pattern,
+        chars: pattern.chars().collect(),
+        pos: 0,
+    };
+
+    let regex = parser.alt()?;
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected ')'"));
+    }
+
+    Ok(regex)
+}
+
+struct RegexParser<'a> {
+    pattern: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl RegexParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek();
+        if ch.is_some() {
+            self.pos += 1;
+        }
+        ch
+    }
+
+    fn error(&self, err: &str) -> io::Error {
+        let msg = format!("{} at {} in regex {}", err, self.pos, self.pattern);
+        simple_calculator::invalid_input_err(msg.as_str())
+    }
+
+    fn alt(&mut self) -> Result<Regex, io::Error> {
+        let mut items = vec![self.concat()?];
+        while self.peek() == Some('|') {
+            self.next();
+            items.push(self.concat()?);
+        }
+
+        Ok(if items.len() == 1 { items.remove(0) } else { Regex::Alt(items) })
+    }
+
+    fn concat(&mut self) -> Result<Regex, io::Error> {
+        let mut items = Vec::new();
+        while let Some(ch) = self.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+            items.push(self.repeat()?);
+        }
+
+        Ok(match items.len() {
+            0 => Regex::Empty,
+            1 => items.remove(0),
+            _ => Regex::Concat(items),
+        })
+    }
+
+    fn repeat(&mut self) -> Result<Regex, io::Error> {
+        let mut regex = self.atom()?;
+        loop {
+            regex = match self.peek() {
+                Some('*') => Regex::Star(Box::new(regex)),
+                Some('+') => Regex::Plus(Box::new(regex)),
+                Some('?') => Regex::Optional(Box::new(regex)),
+                _ => break,
+            };
+            self.next();
+        }
+
+        Ok(regex)
+    }
+
+    fn atom(&mut self) -> Result<Regex, io::Error> {
+        match self.next() {
+            Some('(') => {
+                let regex = self.alt()?;
+                if self.next() != Some(')') {
+                    return Err(self.error("expecting ')'"));
+                }
+                Ok(regex)
+            }
+            Some('[') => Ok(Regex::Set(self.class()?)),
+            Some('.') => Ok(Regex::Set(CharSet::any())),
+            Some('\\') => Ok(Regex::Set(self.escape()?)),
+            Some('*') | Some('+') | Some('?') => Err(self.error("nothing to repeat")),
+            Some(ch) => Ok(Regex::Set(CharSet::single(ch))),
+            None => Err(self.error("unexpected end of regex")),
+        }
+    }
+
+    // 字符类 [...]，开头的 ^ 表示取补集
+    fn class(&mut self) -> Result<CharSet, io::Error> {
+        let negate = self.peek() == Some('^');
+        if negate {
+            self.next();
+        }
+
+        let mut set = CharSet::new();
+        loop {
+            let lo = match self.next() {
+                Some(']') => break,
+                Some('\\') => self.escape()?,
+                Some(ch) => CharSet::single(ch),
+                None => return Err(self.error("expecting ']'")),
+            };
+
+            // a-z 形式的区间，- 出现在最后时按普通字符处理
+            let is_range = self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&ch| ch != ']');
+            if is_range && lo.ranges().len() == 1 && lo.ranges()[0].0 == lo.ranges()[0].1 {
+                self.next();
+                let hi = match self.next() {
+                    Some('\\') => self.escape()?,
+                    Some(ch) => CharSet::single(ch),
+                    None => return Err(self.error("expecting ']'")),
+                };
+                let (lo, hi) = (lo.ranges()[0].0, hi.ranges()[0].0);
+                if lo > hi {
+                    return Err(self.error("invalid character range"));
+                }
+                set.add_range(lo, hi);
+            } else {
+                set.union(&lo);
+            }
+        }
+
+        Ok(if negate { set.negate() } else { set })
+    }
+
+    // \ 之后的部分
+    fn escape(&mut self) -> Result<CharSet, io::Error> {
+        let set = match self.next() {
+            Some('n') => CharSet::single('\n'),
+            Some('t') => CharSet::single('\t'),
+            Some('r') => CharSet::single('\r'),
+            Some('f') => CharSet::single('\u{c}'),
+            Some('0') => CharSet::single('\0'),
+            Some('d') => CharSet::range('0', '9'),
+            Some('w') => {
+                let mut set = CharSet::range('a', 'z');
+                set.add_range('A', 'Z');
+                set.add_range('0', '9');
+                set.add_range('_', '_');
+                set
+            }
+            Some('s') => {
+                let mut set = CharSet::range('\t', '\r');
+                set.add_range(' ', ' ');
+                set
+            }
+            Some('u') => {
+                if self.next() != Some('{') {
+                    return Err(self.error("expecting '{' after \\u"));
+                }
+                let mut code = String::new();
+                while let Some(ch) = self.next() {
+                    if ch == '}' {
+                        break;
+                    }
+                    code.push(ch);
+                }
+                let ch = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32);
+                CharSet::single(ch.ok_or_else(|| self.error("invalid unicode escape"))?)
+            }
+            Some(ch) if !ch.is_alphanumeric() => CharSet::single(ch),
+            Some(_) => return Err(self.error("unknown escape")),
+            None => return Err(self.error("unexpected end of regex")),
+        };
+
+        Ok(set)
+    }
+}
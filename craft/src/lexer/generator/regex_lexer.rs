@@ -0,0 +1,291 @@
+use std::io;
+
+use crate::lexer::{Lexer, LexError, LexErrorReason, Position, Span, Token, TokenType, TokenValue};
+use crate::lexer::generator::dfa::Dfa;
+use crate::lexer::generator::nfa::Nfa;
+use crate::lexer::generator::regex::{self, Regex};
+use crate::lexer::keyword::KeywordTable;
+use crate::lexer::simple_lexer::{OPERATORS, OwnedTokens, Scanner, SimpleToken, SimpleTokenReader};
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{Lexer, LexError, LexErrorReason, Position, Token, TokenReader, TokenType, TokenValue};
+    use crate::lexer::simple_lexer::SimpleLexer;
+
+    use super::{LexRule, RegexLexer};
+
+    fn tokens(lexer: &dyn Lexer, script: &str) -> Vec<(TokenType, String, Position, Position, Option<TokenValue>)> {
+        let mut token_reader = lexer.tokenize(script).unwrap();
+        let mut tokens = Vec::new();
+        while let Some(token) = token_reader.read() {
+            let span = token.get_span();
+            tokens.push((token.get_type(), token.get_text().to_string(), span.start, span.end, token.get_value().cloned()));
+        }
+        tokens
+    }
+
+    #[test]
+    pub fn test_same_as_simple_lexer() {
+        let lexer = RegexLexer::simple_script();
+        let scripts = [
+            "int age = 45;",
+            "inta age = 45;\nif (age >= 17+8+20) age = age / 2; else age = 0;",
+            "a<b<=c==d!=e>f; /* 注释 */ b = \"str\\\"ing\"; // 行注释\nc = (1+2)*3;",
+            "x = 0x1F + 1_000 * 2.5e-3f - .5 / 0b101L + 0o17 + 1.;",
+            "a += b++ - --c; d >>>= e >> 2 << 1; f = !g && h || ~i & j | k ^ l % 3; m /= 2;",
+            "c = 'x'; d = '\\n'; e = true != false; f = null; truex = nullable; g = '\\u{5e74}'; h = \"\\u{1F600}\";",
+            "outer: for (int i = 0; i < 3; i = i + 1) while (true) { break outer; continue; } forx whiles",
+            "int f(int a, int b) { return a, b; } returns",
+        ];
+
+        for script in scripts {
+            assert_eq!(tokens(&lexer, script), tokens(&SimpleLexer::new(), script), "tokenize {}", script);
+        }
+
+        let token_reader = lexer.tokenize("a; /* x */ // y").unwrap();
+        assert_eq!(token_reader.get_comments().len(), 2);
+
+        // 字面量的值和 SimpleLexer 一样，出错的字面量也报告同样的错误
+        let script = "a = true; c = 'x'; d = \"a\\tb\"; e = 0x1F;";
+        let values: Vec<_> = tokens(&lexer, script).into_iter().filter_map(|t| t.4).collect();
+        assert_eq!(values, vec![TokenValue::Bool(true), TokenValue::Char('x'), TokenValue::Str("a\tb".to_string()), TokenValue::Int(31)]);
+        for script in ["x = 2147483649;", "s = \"\\q\";"] {
+            assert_eq!(lexer.tokenize(script).err(), SimpleLexer::new().tokenize(script).err(), "tokenize {}", script);
+        }
+    }
+
+    #[test]
+    pub fn test_longest_match_and_priority() {
+        let lexer = RegexLexer::new(vec![
+            LexRule::new(TokenType::If, "if").unwrap(),
+            LexRule::new(TokenType::Identifier, "[a-z]+").unwrap(),
+            LexRule::new(TokenType::GT, ">").unwrap(),
+            LexRule::new(TokenType::GE, ">=").unwrap(),
+            LexRule::new(TokenType::IntLiteral, "[0-9]+(\\.[0-9]+)?").unwrap(),
+            LexRule::skip(" +").unwrap(),
+        ]);
+
+        let types: Vec<TokenType> = tokens(&lexer, "if iff >= > 1.5").into_iter().map(|t| t.0).collect();
+        assert_eq!(types, vec![TokenType::If, TokenType::Identifier, TokenType::GE, TokenType::GT, TokenType::IntLiteral]);
+
+        // 1. 后面没有数字，回退到最后一次接受的位置
+        let (mut token_reader, errors) = lexer.tokenize_with_recovery("12.x");
+        assert_eq!(token_reader.read().unwrap().get_text(), "12");
+        assert_eq!(token_reader.read().unwrap().get_text(), "x");
+        assert_eq!(errors, vec![LexError::new('.', Position { line: 1, column: 3, offset: 2 }, LexErrorReason::UnexpectedChar)]);
+
+        assert_eq!(lexer.tokenize("a $ b").err(),
+                   Some(LexError::new('$', Position { line: 1, column: 3, offset: 2 }, LexErrorReason::UnexpectedChar)));
+        let (_, errors) = lexer.tokenize_with_recovery("a $ b #");
+        assert_eq!(errors.len(), 2);
+    }
+}
+
+
+/// 词法规则：一个正则表达式，以及匹配之后产生的 Token 类型。类型为None的规则匹配到的内容直接丢弃
+#[derive(Clone)]
+pub struct LexRule {
+    token_type: Option<TokenType>,
+    regex: Regex,
+    // 放到隐藏通道，也就是和注释一起单独保存，不进入 Token 流
+    hidden: bool,
+    // 非贪婪：匹配成功之后立即结束，不再尝试更长的匹配
+    non_greedy: bool,
+}
+
+impl LexRule {
+    pub fn new(token_type: TokenType, pattern: &str) -> Result<LexRule, io::Error> {
+        Ok(LexRule::from_regex(Some(token_type), regex::parse(pattern)?))
+    }
+
+    // 匹配之后丢弃的规则，比如空白字符
+    pub fn skip(pattern: &str) -> Result<LexRule, io::Error> {
+        Ok(LexRule::from_regex(None, regex::parse(pattern)?))
+    }
+
+    pub fn from_regex(token_type: Option<TokenType>, regex: Regex) -> LexRule {
+        LexRule {
+            token_type,
+            regex,
+            hidden: token_type == Some(TokenType::Comment),
+            non_greedy: false,
+        }
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    pub fn non_greedy(mut self) -> Self {
+        self.non_greedy = true;
+        self
+    }
+}
+
+
+/// 由正则表达式生成的词法分析器：Thompson 构造 NFA，子集构造转成 DFA，再用 Hopcroft 算法最小化。
+/// 分析时取最长匹配，同样长度时取排在前面的规则。
+pub struct RegexLexer {
+    rules: Vec<LexRule>,
+    dfa: Dfa,
+}
+
+impl RegexLexer {
+    pub fn new(rules: Vec<LexRule>) -> Self {
+        let regexes: Vec<Regex> = rules.iter().map(|rule| rule.regex.clone()).collect();
+        let non_greedy: Vec<bool> = rules.iter().map(|rule| rule.non_greedy).collect();
+        let dfa = Dfa::from_nfa(&Nfa::from_rules(&regexes).with_non_greedy(&non_greedy));
+
+        RegexLexer {
+            rules,
+            dfa: dfa.minimize(),
+        }
+    }
+
+    // 和 SimpleLexer 识别相同 Token 的规则。标识符只支持 ASCII 字母，块注释不支持嵌套。
+    // 字符串和字符支持 \n 这样的转义和 \u{...}，它们的值、转义是否合法以及数字的范围都由 SimpleLexer 的 Scanner 检查和计算
+    pub fn simple_script() -> Self {
+        let digits = "[0-9]([0-9_]*[0-9])?";
+        let exponent = format!("[eE][+-]?{}", digits);
+        let float = format!(r"({d}\.({d})?({e})?|\.{d}({e})?|{d}{e})[fFdD]?|{d}[fFdD]", d = digits, e = exponent);
+        let int = format!("{}[lL]?", digits);
+
+        let rules = [
+            (Some(TokenType::If), "if"),
+            (Some(TokenType::Else), "else"),
+            (Some(TokenType::Int), "int"),
+            (Some(TokenType::While), "while"),
+            (Some(TokenType::For), "for"),
+            (Some(TokenType::Break), "break"),
+            (Some(TokenType::Continue), "continue"),
+            (Some(TokenType::Return), "return"),
+            (Some(TokenType::BooleanLiteral), "true|false"),
+            (Some(TokenType::NullLiteral), "null"),
+            (Some(TokenType::Identifier), "[a-zA-Z_][a-zA-Z0-9_]*"),
+            (Some(TokenType::IntLiteral), int.as_str()),
+            (Some(TokenType::HexLiteral), "0[xX][0-9a-fA-F]([0-9a-fA-F_]*[0-9a-fA-F])?[lL]?"),
+            (Some(TokenType::BinaryLiteral), "0[bB][01]([01_]*[01])?[lL]?"),
+            (Some(TokenType::OctalLiteral), "0[oO][0-7]([0-7_]*[0-7])?[lL]?"),
+            (Some(TokenType::FloatLiteral), float.as_str()),
+            (Some(TokenType::StringLiteral), r#""([^"\\\n\r]|\\.)*""#),
+            (Some(TokenType::CharLiteral), r"'([^'\\\n\r]|\\[^u\n\r]|\\u\{[0-9a-fA-F]*\})'"),
+            (Some(TokenType::Comment), r"//[^\n\r]*"),
+            (Some(TokenType::Comment), r"/\*([^*]|\*+[^*/])*\*+/"),
+            (Some(TokenType::SemiColon), ";"),
+            (Some(TokenType::LeftParen), r"\("),
+            (Some(TokenType::RightParen), r"\)"),
+            (Some(TokenType::Colon), ":"),
+            (Some(TokenType::Comma), ","),
+            (Some(TokenType::LeftBrace), r"\{"),
+            (Some(TokenType::RightBrace), r"\}"),
+            (None, r"\s+"),
+        ];
+
+        let mut rules: Vec<LexRule> = rules.iter()
+            .map(|&(token_type, pattern)| LexRule::from_regex(token_type, regex::parse(pattern).unwrap()))
+            .collect();
+        // 运算符和 SimpleLexer 用同一张表
+        rules.extend(OPERATORS.iter().map(|&(op, token_type)| LexRule::from_regex(Some(token_type), Regex::literal(op))));
+        RegexLexer::new(rules)
+    }
+
+    pub fn get_dfa(&self) -> &Dfa {
+        &self.dfa
+    }
+
+    // 容错模式：没有任何规则能匹配时报告当前字符，跳过它继续分析
+    pub fn tokenize_with_recovery(&self, script: &str) -> (SimpleTokenReader, Vec<LexError>) {
+        let mut tokens: Vec<Box<dyn Token>> = Vec::new();
+        let mut comments: Vec<Box<dyn Token>> = Vec::new();
+        let mut errors = Vec::new();
+        let mut pos = Position::new();
+
+        while pos.offset < script.len() {
+            // 一直走到死状态为止，记下最后一次经过接受状态的位置
+            let mut state = self.dfa.start();
+            let mut end = pos;
+            let mut last_accept: Option<(usize, Position)> = None;
+            for ch in script[pos.offset..].chars() {
+                match self.dfa.next(state, ch) {
+                    Some(next) => {
+                        state = next;
+                        end.advance(ch);
+                        if let Some(rule) = self.dfa.accept(state) {
+                            last_accept = Some((rule, end));
+                        }
+                    }
+                    None => break,
+                }
+            }
+
+            match last_accept {
+                Some((rule, end)) => {
+                    let rule = &self.rules[rule];
+                    if let Some(token_type) = rule.token_type {
+                        let text = &script[pos.offset..end.offset];
+                        let mut token = Box::new(SimpleToken::with_span(token_type, text.to_string(), Span::new(pos, end)));
+                        match literal_value(token_type, text, pos) {
+                            Ok(value) => token.value = value,
+                            // 出错的字面量整个跳过
+                            Err(err) => {
+                                errors.push(err);
+                                pos = end;
+                                continue;
+                            }
+                        }
+                        if rule.hidden {
+                            comments.push(token);
+                        } else {
+                            tokens.push(token);
+                        }
+                    }
+                    pos = end;
+                }
+                None => {
+                    let ch = script[pos.offset..].chars().next().unwrap();
+                    errors.push(LexError::new(ch, pos, LexErrorReason::UnexpectedChar));
+                    pos.advance(ch);
+                }
+            }
+        }
+
+        (SimpleTokenReader::new(tokens, comments), errors)
+    }
+}
+
+// 字面量的值：用 SimpleLexer 的有限自动机重新分析 Token 的文本，数字的范围、转义的处理都和 SimpleLexer 一致。
+// start 是 Token 在脚本中的位置，出错时报告的位置相对于整个脚本
+fn literal_value(token_type: TokenType, text: &str, start: Position) -> Result<Option<TokenValue>, LexError> {
+    let literal = matches!(token_type,
+        TokenType::IntLiteral | TokenType::HexLiteral | TokenType::BinaryLiteral | TokenType::OctalLiteral
+        | TokenType::FloatLiteral | TokenType::StringLiteral | TokenType::CharLiteral | TokenType::BooleanLiteral);
+    if !literal {
+        return Ok(None);
+    }
+
+    let mut scanner = Scanner::new(OwnedTokens::new(), KeywordTable::english()).with_position(start);
+    for ch in text.chars() {
+        scanner.feed(ch);
+    }
+    scanner.finish();
+
+    if !scanner.errors.is_empty() {
+        return Err(scanner.errors.remove(0));
+    }
+    match scanner.sink.tokens.as_slice() {
+        [token] => Ok(token.get_value().cloned()),
+        _ => Ok(None),
+    }
+}
+
+impl Lexer for RegexLexer {
+    fn tokenize(&self, script: &str) -> Result<SimpleTokenReader, LexError> {
+        let (token_reader, mut errors) = self.tokenize_with_recovery(script);
+        if !errors.is_empty() {
+            return Err(errors.remove(0));
+        }
+
+        Ok(token_reader)
+    }
+}
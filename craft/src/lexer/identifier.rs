@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::confusable_detection::skeleton;
+use unicode_security::MixedScript;
+
+use crate::lexer::{LexWarning, LexWarningReason, Position};
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{LexWarningReason, Position};
+
+    use super::{ConfusableDetector, is_id_continue, is_id_start, normalize};
+
+    #[test]
+    pub fn test_identifier_chars() {
+        for ch in ['a', 'Z', '_', '年', 'é', 'Ω'] {
+            assert!(is_id_start(ch), "start {}", ch);
+        }
+        for ch in ['1', '$', '·', '\u{301}', '٣'] {
+            assert!(!is_id_start(ch), "start {}", ch);
+        }
+        for ch in ['1', '_', '\u{301}', '٣', '·'] {
+            assert!(is_id_continue(ch), "continue {}", ch);
+        }
+
+        // e + 组合用的尖音符 和 é 是同一个名字
+        assert_eq!(normalize("cafe\u{301}"), normalize("caf\u{e9}"));
+    }
+
+    #[test]
+    pub fn test_confusable() {
+        let mut detector = ConfusableDetector::new();
+        let pos = Position::new();
+        assert_eq!(detector.check("scope", pos), None);
+        assert_eq!(detector.check("scope", pos), None);
+        assert_eq!(detector.check("年龄", pos), None);
+
+        // 第一个字母是西里尔字母 ѕ
+        let warning = detector.check("\u{455}cope", pos).unwrap();
+        assert_eq!(warning.reason, LexWarningReason::Confusable("scope".to_string()));
+        assert_eq!(warning.to_string(), "identifier '\u{455}cope' at 1:1 is confusable with 'scope'");
+
+        let warning = detector.check("\u{455}ize", pos).unwrap();
+        assert_eq!(warning.reason, LexWarningReason::MixedScript);
+
+        // 再次出现时，混用文字的不再提示，容易混淆的仍然提示
+        assert_eq!(detector.check("\u{455}ize", pos), None);
+        assert!(detector.check("\u{455}cope", pos).is_some());
+
+        // 记满之后新的标识符仍然和记住的比较
+        let mut detector = ConfusableDetector::with_limit(1);
+        assert_eq!(detector.check("scope", pos), None);
+        assert_eq!(detector.check("sky", pos), None);
+        assert!(detector.check("\u{455}cope", pos).is_some());
+        assert_eq!(detector.check("\u{455}ky", pos).unwrap().reason, LexWarningReason::MixedScript);
+        assert_eq!((detector.seen.len(), detector.checked.len()), (1, 1));
+    }
+}
+
+
+// 标识符的第一个字符：XID_Start 或者 _
+pub fn is_id_start(ch: char) -> bool {
+    ch == '_' || unicode_ident::is_xid_start(ch)
+}
+
+// 标识符后面的字符：XID_Continue，其中包括了数字和 _
+pub fn is_id_continue(ch: char) -> bool {
+    unicode_ident::is_xid_continue(ch)
+}
+
+// 标识符按 NFC 规范化之后再比较，这样看起来一样、编码不同的名字是同一个标识符
+pub fn normalize(name: &str) -> String {
+    name.nfc().collect()
+}
+
+
+/// 检查容易混淆的标识符：混用了多种文字的（比如拉丁字母中夹着西里尔字母），
+/// 以及和之前出现过的另一个标识符看起来一样的（UTS #39 中的 skeleton 相同）。
+pub struct ConfusableDetector {
+    // skeleton -> 第一次出现的标识符（NFC）
+    seen: HashMap<String, String>,
+    // 检查过的标识符再次出现时的结果。第一次出现之后结果就不会再变，不用重新计算 skeleton
+    checked: HashMap<String, Option<LexWarningReason>>,
+    // 最多记住多少个不同的标识符
+    limit: usize,
+}
+
+impl ConfusableDetector {
+    pub fn new() -> Self {
+        ConfusableDetector::with_limit(usize::MAX)
+    }
+
+    // 只记住前 limit 个不同的标识符，之后出现的新标识符仍然和它们比较，但不再记下来，内存占用不会一直增长
+    pub fn with_limit(limit: usize) -> Self {
+        ConfusableDetector {
+            seen: HashMap::new(),
+            checked: HashMap::new(),
+            limit,
+        }
+    }
+
+    // 记下检查的结果，超过 limit 之后不再记
+    fn remember(&mut self, text: &str, reason: Option<LexWarningReason>) {
+        if self.checked.len() < self.limit {
+            self.checked.insert(text.to_string(), reason);
+        }
+    }
+
+    pub fn check(&mut self, text: &str, position: Position) -> Option<LexWarning> {
+        if let Some(reason) = self.checked.get(text) {
+            return reason.clone().map(|reason| LexWarning::new(text, position, reason));
+        }
+
+        let name = normalize(text);
+        let key: String = skeleton(&name).collect();
+        let reason = match self.seen.get(&key) {
+            Some(other) if *other != name => {
+                let reason = LexWarningReason::Confusable(other.clone());
+                self.remember(text, Some(reason.clone()));
+                Some(reason)
+            }
+            Some(_) => {
+                self.remember(text, None);
+                None
+            }
+            // 混用多种文字的标识符只在第一次出现时提示
+            None => {
+                let single_script = name.as_str().is_single_script();
+                if self.seen.len() < self.limit {
+                    self.seen.insert(key, name);
+                }
+                self.remember(text, None);
+                if single_script { None } else { Some(LexWarningReason::MixedScript) }
+            }
+        };
+
+        reason.map(|reason| LexWarning::new(text, position, reason))
+    }
+}
@@ -0,0 +1,229 @@
+use std::io;
+use std::ops::Range;
+
+use crate::lexer::{LexError, Position, Span, Token, TokenType, TokenValue};
+use crate::lexer::keyword::KeywordTable;
+use crate::lexer::simple_calculator;
+use crate::lexer::simple_lexer::{Scanner, SimpleToken, SimpleTokenReader, TokenSink};
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{Token, TokenReader};
+    use crate::lexer::simple_lexer::SimpleLexer;
+
+    use super::IncrementalTokens;
+
+    // 增量分析的结果必须和重新分析整个脚本完全一样
+    fn assert_same(tokens: &IncrementalTokens) {
+        let (expected, errors) = IncrementalTokens::new(tokens.get_source(), Default::default()).into_parts();
+        let actual: Vec<_> = tokens.tokens().iter()
+            .map(|t| (t.get_type(), t.get_text(), t.get_span(), t.get_value()))
+            .collect();
+        let expected: Vec<_> = expected.iter()
+            .map(|t| (t.get_type(), t.get_text(), t.get_span(), t.get_value()))
+            .collect();
+        assert_eq!(actual, expected, "source {:?}", tokens.get_source());
+        assert_eq!(tokens.get_errors(), errors.as_slice(), "source {:?}", tokens.get_source());
+    }
+
+    #[test]
+    pub fn test_edit() {
+        let lexer = SimpleLexer::new();
+        let mut tokens = lexer.tokenize_incremental("int a = 4;\nif (a > 3) b = \"x\";\nc = a / 2; // end\n");
+        assert_eq!(tokens.tokens().len(), 22);
+
+        // 把 4 改成 45：只有一个 Token 变化
+        assert_eq!(tokens.edit(8..9, "45").unwrap(), 3..4);
+        assert_eq!(tokens.tokens()[3].get_text(), "45");
+        assert_same(&tokens);
+
+        // > 后面插入 =，和前一个 Token 合并成 >=
+        let gt = tokens.get_source().find('>').unwrap();
+        assert_eq!(tokens.edit(gt + 1..gt + 1, "=").unwrap(), 8..9);
+        assert_eq!(tokens.tokens()[8].get_text(), ">=");
+        assert_same(&tokens);
+
+        // 插入换行，后面的 Token 行号都要变
+        assert_eq!(tokens.edit(0..0, "\n\n").unwrap(), 0..0);
+        assert_eq!(tokens.tokens()[0].get_span().start.line, 3);
+        assert_same(&tokens);
+
+        // 块注释的开始会影响到后面所有的内容
+        let slash = tokens.get_source().find('/').unwrap();
+        tokens.edit(slash..slash, "/*").unwrap();
+        assert_same(&tokens);
+        tokens.edit(slash..slash + 2, "").unwrap();
+        assert_same(&tokens);
+
+        // 删掉字符串的结束引号，出错的部分也和整体分析一致
+        let quote = tokens.get_source().rfind('"').unwrap();
+        tokens.edit(quote..quote + 1, "").unwrap();
+        assert_same(&tokens);
+        tokens.edit(tokens.get_source().len()..tokens.get_source().len(), "年龄 = 1").unwrap();
+        assert_same(&tokens);
+
+        assert!(tokens.edit(3..100, "").is_err());
+        let (start, end) = (4, 3);
+        assert!(tokens.edit(start..end, "").is_err());
+
+        let mut token_reader = tokens.token_reader();
+        assert_eq!(token_reader.read().unwrap().get_text(), "int");
+        assert_eq!(token_reader.get_comments().len(), 1);
+    }
+}
+
+
+// 按顺序保存 Token 和注释
+struct TokenList {
+    tokens: Vec<SimpleToken>,
+}
+
+impl TokenSink for TokenList {
+    fn push(&mut self, token_type: TokenType, text: &str, span: Span, value: Option<TokenValue>) {
+        let mut token = SimpleToken::with_span(token_type, text.to_string(), span);
+        token.value = value;
+        self.tokens.push(token);
+    }
+}
+
+
+/// 支持增量分析的 Token 序列，注释也按顺序放在其中。修改脚本之后，从编辑位置之前最近的 Token 边界开始
+/// 重新运行有限自动机，新的 Token 和原来的 Token 在编辑位置之后重新对齐时就停下，后面的 Token 只需要平移位置。
+///
+/// 容易混淆的标识符需要看整个脚本，增量分析时不做检查。
+pub struct IncrementalTokens {
+    keywords: KeywordTable,
+    source: String,
+    tokens: Vec<SimpleToken>,
+    errors: Vec<LexError>,
+}
+
+impl IncrementalTokens {
+    pub(crate) fn new(script: &str, keywords: KeywordTable) -> Self {
+        let mut scanner = Scanner::new(TokenList { tokens: Vec::new() }, keywords.clone());
+        script.chars().for_each(|ch| scanner.feed(ch));
+        scanner.finish();
+
+        IncrementalTokens {
+            keywords,
+            source: script.to_string(),
+            tokens: scanner.sink.tokens,
+            errors: scanner.errors,
+        }
+    }
+
+    pub fn get_source(&self) -> &str {
+        &self.source
+    }
+
+    // 所有的 Token，包括注释
+    pub fn tokens(&self) -> &[SimpleToken] {
+        &self.tokens
+    }
+
+    pub fn get_errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    // 供语法分析使用的 Token 流，注释单独放
+    pub fn token_reader(&self) -> SimpleTokenReader {
+        let (comments, tokens): (Vec<_>, Vec<_>) = self.tokens.iter()
+            .map(|token| Box::new(token.clone()) as Box<dyn Token>)
+            .partition(|token| token.get_type() == TokenType::Comment);
+        SimpleTokenReader::new(tokens, comments)
+    }
+
+    /*
+    把脚本中 range 这段字节替换成 text，并更新 Token 序列。返回新的 Token 序列中变化了的那一段，
+    它替换掉了原来序列中从同一个下标开始的若干个 Token。
+
+    一个 Token 在读到它后面的一个字符时才结束，所以在编辑位置之前结束、并且后面的字符没有被修改的 Token 不会受影响，
+    从最后一个这样的 Token 的末尾开始重新分析。新的 Token 在编辑位置之后的某个位置开始，原来的序列中也有 Token
+    从这里开始时，两边的有限自动机都从初始状态读同样的内容，之后的 Token 一定相同，不需要再分析。
+    */
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Result<Range<usize>, io::Error> {
+        let valid = range.start <= range.end
+            && range.end <= self.source.len()
+            && self.source.is_char_boundary(range.start)
+            && self.source.is_char_boundary(range.end);
+        if !valid {
+            let msg = format!("invalid edit range {:?}", range);
+            return Err(simple_calculator::invalid_input_err(msg.as_str()));
+        }
+
+        let first = self.tokens.partition_point(|token| token.get_span().end.offset < range.start);
+        let restart = if first == 0 { Position::new() } else { self.tokens[first - 1].get_span().end };
+        self.source.replace_range(range.clone(), text);
+        let edit_end = range.start + text.len();
+
+        let mut scanner = Scanner::new(TokenList { tokens: Vec::new() }, self.keywords.clone()).with_position(restart);
+        // 重新对齐的位置：原来序列中的下标，以及这个 Token 原来的起始位置和现在的起始位置
+        let mut resync: Option<(usize, Position, Position)> = None;
+        for ch in self.source[restart.offset..].chars() {
+            let count = scanner.sink.tokens.len();
+            scanner.feed(ch);
+            if scanner.sink.tokens.len() == count {
+                continue;
+            }
+
+            let start = scanner.sink.tokens[count].get_span().start;
+            if start.offset < edit_end {
+                continue;
+            }
+            let old_offset = start.offset + range.len() - text.len();
+            let old = &self.tokens[first..];
+            if let Ok(index) = old.binary_search_by_key(&old_offset, |token| token.get_span().start.offset) {
+                resync = Some((first + index, old[index].get_span().start, start));
+                scanner.sink.tokens.pop();
+                break;
+            }
+        }
+
+        // 一直没有对齐，说明直到脚本末尾都受到了影响
+        if resync.is_none() {
+            scanner.finish();
+        }
+
+        let mut new_tokens = scanner.sink.tokens;
+        let mut new_errors = scanner.errors;
+        let old_tail = match resync {
+            Some((index, old_start, new_start)) => {
+                // 对齐之前读到的下一个字符上的错误，在原来的序列中也有
+                new_errors.retain(|err| err.position.offset < new_start.offset);
+                let mut tail = self.tokens.split_off(index);
+                for token in tail.iter_mut() {
+                    token.span = Span::new(shift(token.span.start, old_start, new_start), shift(token.span.end, old_start, new_start));
+                }
+                let errors = self.errors.iter()
+                    .filter(|err| err.position.offset >= old_start.offset)
+                    .map(|err| LexError::new(err.ch, shift(err.position, old_start, new_start), err.reason));
+                new_errors.extend(errors);
+                tail
+            }
+            None => Vec::new(),
+        };
+
+        let changed = first..first + new_tokens.len();
+        self.tokens.truncate(first);
+        self.tokens.append(&mut new_tokens);
+        self.tokens.extend(old_tail);
+        self.errors.retain(|err| err.position.offset < restart.offset);
+        self.errors.append(&mut new_errors);
+
+        Ok(changed)
+    }
+
+    #[cfg(test)]
+    fn into_parts(self) -> (Vec<SimpleToken>, Vec<LexError>) {
+        (self.tokens, self.errors)
+    }
+}
+
+// 对齐点之后的位置整体平移：字节偏移和行号按对齐点的变化平移，和对齐点在同一行的还要平移列号
+fn shift(pos: Position, old: Position, new: Position) -> Position {
+    Position {
+        line: pos.line - old.line + new.line,
+        column: if pos.line == old.line { pos.column - old.column + new.column } else { pos.column },
+        offset: pos.offset - old.offset + new.offset,
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::lexer::{identifier, TokenType, TokenValue};
+use crate::lexer::simple_calculator;
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{TokenType, TokenValue};
+
+    use super::KeywordTable;
+
+    #[test]
+    pub fn test_profile() {
+        let english = KeywordTable::profile("en").unwrap();
+        assert_eq!(english.lookup("if"), Some(TokenType::If));
+        assert_eq!(english.lookup("如果"), None);
+
+        let chinese = KeywordTable::profile("zh").unwrap();
+        assert_eq!(chinese.lookup("如果"), Some(TokenType::If));
+        assert_eq!(chinese.lookup("否则"), Some(TokenType::Else));
+        assert_eq!(chinese.lookup("整数"), Some(TokenType::Int));
+        assert_eq!(chinese.lookup("int"), Some(TokenType::Int));
+
+        let french = KeywordTable::profile("si=if, sinon=else\nentier=int, vrai=true").unwrap();
+        assert_eq!(french.lookup("sinon"), Some(TokenType::Else));
+        assert_eq!(french.lookup("else"), Some(TokenType::Else));
+        assert_eq!(french.lookup("vrai"), Some(TokenType::BooleanLiteral));
+        assert_eq!(french.lookup_value("vrai"), Some(&TokenValue::Bool(true)));
+        assert_eq!(french.lookup_value("si"), None);
+
+        for profile in ["fr", "si=when", "1a=if", "si"] {
+            assert!(KeywordTable::profile(profile).is_err(), "profile {}", profile);
+        }
+    }
+}
+
+
+/// 关键字表：关键字的文本到 Token 类型的映射。词法分析器先把关键字当作标识符识别出来，再查这张表。
+/// 同一个 Token 类型可以有多个关键字，比如 if 和 如果。true、false 这样的字面量关键字还带着它的值。
+#[derive(Debug, Clone)]
+pub struct KeywordTable {
+    keywords: HashMap<String, (TokenType, Option<TokenValue>)>,
+}
+
+impl KeywordTable {
+    // 空的关键字表，所有的单词都是标识符
+    pub fn new() -> Self {
+        KeywordTable {
+            keywords: HashMap::new(),
+        }
+    }
+
+    pub fn english() -> Self {
+        let mut table = KeywordTable::new();
+        table.add("if", TokenType::If);
+        table.add("else", TokenType::Else);
+        table.add("int", TokenType::Int);
+        table.add("while", TokenType::While);
+        table.add("for", TokenType::For);
+        table.add("break", TokenType::Break);
+        table.add("continue", TokenType::Continue);
+        table.add("return", TokenType::Return);
+        table.add_literal("true", TokenType::BooleanLiteral, TokenValue::Bool(true));
+        table.add_literal("false", TokenType::BooleanLiteral, TokenValue::Bool(false));
+        table.add("null", TokenType::NullLiteral);
+        table
+    }
+
+    // 简体中文：在英文关键字之外再加上中文的别名
+    pub fn chinese() -> Self {
+        let mut table = KeywordTable::english();
+        table.add("如果", TokenType::If);
+        table.add("否则", TokenType::Else);
+        table.add("整数", TokenType::Int);
+        table.add("当", TokenType::While);
+        table.add("对于", TokenType::For);
+        table.add("跳出", TokenType::Break);
+        table.add("继续", TokenType::Continue);
+        table.add("返回", TokenType::Return);
+        table.add_literal("真", TokenType::BooleanLiteral, TokenValue::Bool(true));
+        table.add_literal("假", TokenType::BooleanLiteral, TokenValue::Bool(false));
+        table.add("空", TokenType::NullLiteral);
+        table
+    }
+
+    /*
+    按名字选择关键字表：
+        en          英文
+        zh、zh-CN   简体中文
+        别名=关键字   用户自定义的别名，多个别名之间用逗号或者换行分开，比如 si=if, sinon=else
+    */
+    pub fn profile(name: &str) -> Result<KeywordTable, io::Error> {
+        match name.trim() {
+            "en" => Ok(KeywordTable::english()),
+            "zh" | "zh-CN" => Ok(KeywordTable::chinese()),
+            mapping if mapping.contains('=') => KeywordTable::from_mapping(mapping),
+            other => {
+                let msg = format!("unknown keyword profile {}", other);
+                Err(simple_calculator::invalid_input_err(msg.as_str()))
+            }
+        }
+    }
+
+    // 在英文关键字的基础上加入用户自定义的别名
+    pub fn from_mapping(mapping: &str) -> Result<KeywordTable, io::Error> {
+        let english = KeywordTable::english();
+        let mut table = KeywordTable::english();
+        for entry in mapping.split([',', '\n']).map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (alias, keyword) = match entry.split_once('=') {
+                Some((alias, keyword)) => (alias.trim(), keyword.trim()),
+                None => {
+                    let msg = format!("invalid keyword alias {}, expecting alias=keyword", entry);
+                    return Err(simple_calculator::invalid_input_err(msg.as_str()));
+                }
+            };
+
+            let entry = match english.keywords.get(&identifier::normalize(keyword)) {
+                Some(entry) => entry.clone(),
+                None => {
+                    let msg = format!("{} is not a keyword", keyword);
+                    return Err(simple_calculator::invalid_input_err(msg.as_str()));
+                }
+            };
+
+            // 别名必须能被识别成一个标识符
+            let mut chars = alias.chars();
+            let is_identifier = chars.next().is_some_and(identifier::is_id_start) && chars.all(identifier::is_id_continue);
+            if !is_identifier {
+                let msg = format!("keyword alias {} is not an identifier", alias);
+                return Err(simple_calculator::invalid_input_err(msg.as_str()));
+            }
+
+            table.keywords.insert(identifier::normalize(alias), entry);
+        }
+
+        Ok(table)
+    }
+
+    pub fn add(&mut self, keyword: &str, token_type: TokenType) {
+        self.keywords.insert(identifier::normalize(keyword), (token_type, None));
+    }
+
+    // 字面量关键字，比如 true 的值是 Bool(true)
+    pub fn add_literal(&mut self, keyword: &str, token_type: TokenType, value: TokenValue) {
+        self.keywords.insert(identifier::normalize(keyword), (token_type, Some(value)));
+    }
+
+    // 查找关键字，不是关键字时返回None
+    pub fn lookup(&self, text: &str) -> Option<TokenType> {
+        self.get(text).map(|&(token_type, _)| token_type)
+    }
+
+    // 字面量关键字的值，不是字面量关键字时返回None
+    pub fn lookup_value(&self, text: &str) -> Option<&TokenValue> {
+        self.get(text).and_then(|(_, value)| value.as_ref())
+    }
+
+    fn get(&self, text: &str) -> Option<&(TokenType, Option<TokenValue>)> {
+        if text.is_ascii() {
+            self.keywords.get(text)
+        } else {
+            self.keywords.get(&identifier::normalize(text))
+        }
+    }
+}
+
+impl Default for KeywordTable {
+    fn default() -> Self {
+        KeywordTable::english()
+    }
+}
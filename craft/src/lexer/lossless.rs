@@ -0,0 +1,283 @@
+use crate::lexer::{Position, Span, Token, TokenReader, TokenType, TokenValue};
+use crate::lexer::simple_lexer::SimpleTokenReader;
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{Token, TokenReader, TokenType};
+    use crate::lexer::simple_lexer::SimpleLexer;
+
+    use super::TriviaKind;
+
+    #[test]
+    pub fn test_round_trip() {
+        let lexer = SimpleLexer::new();
+        let scripts = [
+            "int age = 45;",
+            "  int a = 4; // 年龄\r\n/* 外层 /* 嵌套 */ **/\ta = a / 2;//\n\n",
+            "\n\n// 只有注释\n",
+            "",
+            "a = 1 $ 2;\n12abc \"x\nb = !3;",
+        ];
+
+        for script in scripts {
+            let (token_reader, _) = lexer.tokenize_lossless(script);
+            assert_eq!(token_reader.to_source(), script);
+        }
+    }
+
+    #[test]
+    pub fn test_trivia() {
+        let lexer = SimpleLexer::new();
+        let (mut token_reader, errors) = lexer.tokenize_lossless("a = 1; // 注释\n  /* b */ b $;\n");
+        assert_eq!(errors.len(), 1);
+
+        let kinds = |trivia: &[super::Trivia]| trivia.iter().map(|t| (t.kind, t.text.clone())).collect::<Vec<_>>();
+
+        // 同一行中 Token 后面的空白和注释属于这个 Token，换行以及之后的属于下一个 Token
+        let semi = token_reader.get_token(3).unwrap();
+        assert_eq!(semi.get_type(), TokenType::SemiColon);
+        assert_eq!(kinds(semi.get_trailing_trivia()), vec![
+            (TriviaKind::Whitespace, " ".to_string()),
+            (TriviaKind::LineComment, "// 注释".to_string()),
+        ]);
+
+        let b = token_reader.get_token(4).unwrap();
+        assert_eq!(b.get_text(), "b");
+        assert_eq!(kinds(b.get_leading_trivia()), vec![
+            (TriviaKind::Newline, "\n".to_string()),
+            (TriviaKind::Whitespace, "  ".to_string()),
+            (TriviaKind::BlockComment, "/* b */".to_string()),
+            (TriviaKind::Whitespace, " ".to_string()),
+        ]);
+        assert_eq!(b.get_leading_trivia()[2].span.start.column, 3);
+
+        // 出错时跳过的字符也保留下来
+        assert_eq!(kinds(b.get_trailing_trivia()), vec![
+            (TriviaKind::Whitespace, " ".to_string()),
+            (TriviaKind::Skipped, "$".to_string()),
+        ]);
+        assert_eq!(kinds(token_reader.get_eof_trivia()), vec![(TriviaKind::Newline, "\n".to_string())]);
+
+        token_reader.set_position(3);
+        assert_eq!(token_reader.read().unwrap().get_text(), ";");
+        assert_eq!(token_reader.read().unwrap().get_text(), "b");
+    }
+}
+
+
+/// 琐碎内容（trivia）的种类：不参与语法分析，但是重新生成源码时需要原样保留
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TriviaKind {
+    // 空格、制表符等不换行的空白
+    Whitespace,
+    // 一个换行：\n、\r\n 或者 \r
+    Newline,
+    LineComment,
+    BlockComment,
+    // 有词法错误被跳过的内容
+    Skipped,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+
+/// 带着前后 trivia 的 Token。Token 后面同一行之内的空白和注释是它的 trailing trivia，
+/// 从换行开始一直到下一个 Token 之前的内容是下一个 Token 的 leading trivia。
+#[derive(Debug, Clone)]
+pub struct LosslessToken {
+    token_type: TokenType,
+    text: String,
+    span: Span,
+    value: Option<TokenValue>,
+    leading: Vec<Trivia>,
+    trailing: Vec<Trivia>,
+}
+
+impl LosslessToken {
+    pub fn get_leading_trivia(&self) -> &[Trivia] {
+        &self.leading
+    }
+
+    pub fn get_trailing_trivia(&self) -> &[Trivia] {
+        &self.trailing
+    }
+}
+
+impl Token for LosslessToken {
+    fn get_type(&self) -> TokenType {
+        self.token_type
+    }
+
+    fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    fn get_span(&self) -> Span {
+        self.span
+    }
+
+    fn get_value(&self) -> Option<&TokenValue> {
+        self.value.as_ref()
+    }
+}
+
+
+/// 无损的 Token 流：把所有 Token 和 trivia 按顺序拼起来，就是原来的脚本，一个字节都不差
+pub struct LosslessTokenReader {
+    tokens: Vec<LosslessToken>,
+    // 最后一个 Token 之后、脚本结尾之前的 trivia
+    eof_trivia: Vec<Trivia>,
+    pos: usize,
+}
+
+impl LosslessTokenReader {
+    // 由普通的 Token 流和脚本生成：Token 之间的空隙按注释、空白、换行切分成 trivia
+    pub(crate) fn new(script: &str, mut token_reader: SimpleTokenReader) -> Self {
+        let comments: Vec<Span> = token_reader.get_comments().iter().map(|c| c.get_span()).collect();
+        let mut comments = comments.into_iter().peekable();
+        let mut tokens: Vec<LosslessToken> = Vec::new();
+        let mut pos = Position::new();
+
+        while let Some(token) = token_reader.read() {
+            let span = token.get_span();
+            let mut gap = split_gap(script, pos, span.start.offset, &mut comments);
+
+            if let Some(last) = tokens.last_mut() {
+                let leading = gap.split_off(trailing_len(&gap));
+                last.trailing = gap;
+                gap = leading;
+            }
+
+            tokens.push(LosslessToken {
+                token_type: token.get_type(),
+                text: token.get_text().to_string(),
+                span,
+                value: token.get_value().cloned(),
+                leading: gap,
+                trailing: Vec::new(),
+            });
+            pos = span.end;
+        }
+
+        let mut eof_trivia = split_gap(script, pos, script.len(), &mut comments);
+        if let Some(last) = tokens.last_mut() {
+            let rest = eof_trivia.split_off(trailing_len(&eof_trivia));
+            last.trailing = eof_trivia;
+            eof_trivia = rest;
+        }
+
+        LosslessTokenReader {
+            tokens,
+            eof_trivia,
+            pos: 0,
+        }
+    }
+
+    pub fn get_token(&self, index: usize) -> Option<&LosslessToken> {
+        self.tokens.get(index)
+    }
+
+    pub fn get_eof_trivia(&self) -> &[Trivia] {
+        &self.eof_trivia
+    }
+
+    // 按顺序拼接所有的 Token 和 trivia，得到原来的脚本
+    pub fn to_source(&self) -> String {
+        let mut source = String::new();
+        for token in &self.tokens {
+            token.leading.iter().for_each(|trivia| source.push_str(&trivia.text));
+            source.push_str(&token.text);
+            token.trailing.iter().for_each(|trivia| source.push_str(&trivia.text));
+        }
+        self.eof_trivia.iter().for_each(|trivia| source.push_str(&trivia.text));
+        source
+    }
+}
+
+impl TokenReader for LosslessTokenReader {
+    fn read(&mut self) -> Option<&dyn Token> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn peek(&self) -> Option<&dyn Token> {
+        self.tokens.get(self.pos).map(|token| token as &dyn Token)
+    }
+
+    fn peek_nth(&mut self, k: usize) -> Option<&dyn Token> {
+        self.tokens.get(self.pos + k).map(|token| token as &dyn Token)
+    }
+
+    fn unread(&mut self) {
+        if self.pos > 0 {
+            self.pos -= 1;
+        }
+    }
+
+    fn get_position(&self) -> usize {
+        self.pos
+    }
+
+    fn set_position(&mut self, position: usize) -> bool {
+        if position > self.tokens.len() {
+            return false;
+        }
+
+        self.pos = position;
+        true
+    }
+}
+
+// trailing trivia 的长度：第一个换行之前的部分
+fn trailing_len(gap: &[Trivia]) -> usize {
+    gap.iter().position(|trivia| trivia.kind == TriviaKind::Newline).unwrap_or(gap.len())
+}
+
+// 把 script[pos.offset..end] 切分成 trivia。comments 是按顺序排列的注释的位置
+fn split_gap<I: Iterator<Item = Span>>(script: &str, mut pos: Position, end: usize, comments: &mut std::iter::Peekable<I>) -> Vec<Trivia> {
+    let mut trivia: Vec<Trivia> = Vec::new();
+    while pos.offset < end {
+        let start = pos;
+        let rest = &script[pos.offset..end];
+        let first = rest.chars().next().unwrap();
+
+        let kind = match comments.peek() {
+            Some(comment) if comment.start.offset == pos.offset => {
+                let comment = comments.next().unwrap();
+                pos = comment.end;
+                if rest.starts_with("//") { TriviaKind::LineComment } else { TriviaKind::BlockComment }
+            }
+            _ if first == '\n' || first == '\r' => {
+                let len = if rest.starts_with("\r\n") { 2 } else { 1 };
+                rest[..len].chars().for_each(|ch| pos.advance(ch));
+                TriviaKind::Newline
+            }
+            _ => {
+                // 连续的空白，或者连续的被跳过的内容
+                let whitespace = first.is_whitespace();
+                let next_comment = comments.peek().map_or(end, |comment| comment.start.offset.clamp(pos.offset, end));
+                for ch in script[pos.offset..next_comment].chars() {
+                    if ch == '\n' || ch == '\r' || ch.is_whitespace() != whitespace {
+                        break;
+                    }
+                    pos.advance(ch);
+                }
+                if whitespace { TriviaKind::Whitespace } else { TriviaKind::Skipped }
+            }
+        };
+
+        trivia.push(Trivia {
+            kind,
+            text: script[start.offset..pos.offset].to_string(),
+            span: Span::new(start, pos),
+        });
+    }
+
+    trivia
+}
@@ -1,15 +1,30 @@
 #![allow(unused)]
 
 use std::cell::{Ref, RefCell, RefMut};
+use std::error::Error;
 use std::fmt;
+use std::io;
 use std::rc::{Rc, Weak};
 
+use checkpoint::Checkpoint;
 use simple_calculator::SimpleASTNode;
+use simple_lexer::SimpleTokenReader;
 
+pub mod borrowed_token;
+pub mod checkpoint;
+pub mod export;
+pub mod fast_lexer;
+pub mod generator;
+pub mod identifier;
+pub mod incremental;
+pub mod keyword;
+pub mod lossless;
+pub mod number;
 pub mod simple_lexer;
 pub mod simple_calculator;
 pub mod simple_parser;
 pub mod simple_script;
+pub mod stream_lexer;
 
 
 pub trait Token {
@@ -17,19 +32,256 @@ pub trait Token {
     fn get_type(&self) -> TokenType;
     // Token的文本值
     fn get_text(&self) -> &str;
+    // Token在源码中的位置
+    fn get_span(&self) -> Span;
+    // 字面量的值，比如去掉引号、处理完转义之后的字符串。不是字面量时返回None
+    fn get_value(&self) -> Option<&TokenValue> {
+        None
+    }
+}
+
+/// 字面量 Token 携带的值
+#[derive(Debug, PartialEq, Clone)]
+pub enum TokenValue {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Char(char),
+    Bool(bool),
+}
+
+/// 词法分析器：把脚本切分成 Token 流。手写的 SimpleLexer 和由正则表达式生成的 RegexLexer 都实现了它
+pub trait Lexer {
+    fn tokenize(&self, script: &str) -> Result<SimpleTokenReader, LexError>;
 }
 
 pub trait TokenReader {
     // 返回Token流中下一个Token，并从流中取出。 如果流已经为空，返回null;
-    fn read(&mut self) -> Option<&Box<dyn Token>>;
+    fn read(&mut self) -> Option<&dyn Token>;
     // 返回Token流中下一个Token，但不从流中取出。 如果流已经为空，返回null;
-    fn peek(&self) -> Option<&Box<dyn Token>>;
+    fn peek(&self) -> Option<&dyn Token>;
+    // 向前看第 k 个Token，peek_nth(0) 就是 peek。不改变读取位置，超出Token流时返回null;
+    fn peek_nth(&mut self, k: usize) -> Option<&dyn Token>;
     // Token流回退一步。恢复原来的Token。
     fn unread(&mut self);
     // 获取Token流当前的读取位置。
     fn get_position(&self) -> usize;
-    // 设置Token流当前的读取位置
-    fn set_position(&mut self, position: usize);
+    // 设置Token流当前的读取位置，可以设置到最后一个Token之后（即流的末尾）。超出范围时不变，并返回 false
+    fn set_position(&mut self, position: usize) -> bool;
+
+    // 下一个Token必须是 token_type 类型：是的话取出它，否则报错并且不移动读取位置
+    fn expect(&mut self, token_type: TokenType) -> Result<&dyn Token, UnexpectedToken> {
+        let found = self.peek().map(|token| (token.get_type(), token.get_text().to_string(), token.get_span()));
+        match found {
+            Some((found, _, _)) if found == token_type => Ok(self.read().unwrap()),
+            Some((found, text, span)) => Err(UnexpectedToken::new(token_type, Some((found, text, span)))),
+            None => Err(UnexpectedToken::new(token_type, None)),
+        }
+    }
+
+    // 记下当前的读取位置，返回的 Checkpoint 被丢弃时回到这个位置，除非调用了 commit
+    fn checkpoint(&mut self) -> Checkpoint<'_, Self> where Self: Sized {
+        Checkpoint::new(self)
+    }
+}
+
+/// 语法错误：期望某种类型的Token，实际读到了别的Token，或者已经到了末尾
+#[derive(Debug, PartialEq, Clone)]
+pub struct UnexpectedToken {
+    pub expected: TokenType,
+    // 实际读到的Token的类型、文本和位置，到了末尾时为None
+    pub found: Option<(TokenType, String, Span)>,
+}
+
+impl UnexpectedToken {
+    pub fn new(expected: TokenType, found: Option<(TokenType, String, Span)>) -> Self {
+        UnexpectedToken { expected, found }
+    }
+}
+
+impl fmt::Display for UnexpectedToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.found {
+            Some((_, text, span)) => write!(f, "unexpected '{}' at {}, expecting {:?}", text, span.start, self.expected),
+            None => write!(f, "unexpected end of script, expecting {:?}", self.expected),
+        }
+    }
+}
+
+impl Error for UnexpectedToken {}
+
+impl From<UnexpectedToken> for io::Error {
+    fn from(err: UnexpectedToken) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// 源码中的一个位置。行号、列号从 1 开始，列号按字符计；offset 是从脚本开头算起的字节偏移。
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+impl Position {
+    pub fn new() -> Self {
+        Position {
+            line: 1,
+            column: 1,
+            offset: 0,
+        }
+    }
+
+    // 越过一个字符，遇到换行则换到下一行的行首
+    pub fn advance(&mut self, ch: char) {
+        self.offset += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::new()
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// 源码中的一段区间，start 包含在内，end 不包含。
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+
+    // 合并两个区间，得到从 self 开始到 other 结束的区间
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+/// 词法错误的原因
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LexErrorReason {
+    // 字符串没有以 " 结束就遇到了换行或者脚本末尾
+    UnterminatedString,
+    // 字符字面量没有以 ' 结束
+    UnterminatedChar,
+    // 字符字面量中不是正好一个字符，比如 '' 和 'ab'
+    InvalidCharLiteral,
+    // 不认识的转义字符，比如 \q
+    InvalidEscape,
+    // \u{...} 的格式不对，或者不是合法的 Unicode 码点
+    InvalidUnicodeEscape,
+    // 块注释没有以 */ 结束
+    UnterminatedComment,
+    // 不能作为任何 Token 开头的字符，比如 $、#、@
+    UnexpectedChar,
+    // 格式不对的数字，比如 0x、1e、1_、0b12
+    MalformedNumber,
+    // 数字超出了能表示的范围
+    NumberOutOfRange,
+}
+
+impl fmt::Display for LexErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexErrorReason::UnterminatedString => write!(f, "unterminated string literal"),
+            LexErrorReason::UnterminatedChar => write!(f, "unterminated character literal"),
+            LexErrorReason::InvalidCharLiteral => write!(f, "invalid character literal"),
+            LexErrorReason::InvalidEscape => write!(f, "invalid escape sequence"),
+            LexErrorReason::InvalidUnicodeEscape => write!(f, "invalid unicode escape"),
+            LexErrorReason::UnterminatedComment => write!(f, "unterminated block comment"),
+            LexErrorReason::UnexpectedChar => write!(f, "unexpected character"),
+            LexErrorReason::MalformedNumber => write!(f, "malformed number literal"),
+            LexErrorReason::NumberOutOfRange => write!(f, "number literal out of range"),
+        }
+    }
+}
+
+/// 词法错误：出错的字符、位置和原因。text 是报告给用户的文本，通常就是出错的字符，
+/// 数字字面量出错时是整个字面量
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub ch: char,
+    pub text: String,
+    pub position: Position,
+    pub reason: LexErrorReason,
+}
+
+impl LexError {
+    pub fn new(ch: char, position: Position, reason: LexErrorReason) -> Self {
+        LexError { ch, text: ch.to_string(), position, reason }
+    }
+
+    // 整个 Token 出错，position 是它的开头，ch 是它的第一个字符
+    pub fn with_text(text: &str, position: Position, reason: LexErrorReason) -> Self {
+        let ch = text.chars().next().unwrap_or_default();
+        LexError { ch, text: text.to_string(), position, reason }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} '{}' at {}", self.reason, self.text.escape_debug(), self.position)
+    }
+}
+
+impl Error for LexError {}
+
+/// 词法警告的原因
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexWarningReason {
+    // 标识符中混用了多种文字，比如拉丁字母中夹着西里尔字母
+    MixedScript,
+    // 和另一个标识符看起来一样，但不是同一个名字
+    Confusable(String),
+}
+
+/// 词法警告：不影响分析结果，但很可能是笔误，比如容易混淆的标识符
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexWarning {
+    pub text: String,
+    pub position: Position,
+    pub reason: LexWarningReason,
+}
+
+impl LexWarning {
+    pub fn new(text: &str, position: Position, reason: LexWarningReason) -> Self {
+        LexWarning { text: text.to_string(), position, reason }
+    }
+}
+
+impl fmt::Display for LexWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "identifier '{}' at {}", self.text, self.position)?;
+        match &self.reason {
+            LexWarningReason::MixedScript => write!(f, " mixes characters from different scripts"),
+            LexWarningReason::Confusable(other) => write!(f, " is confusable with '{}'", other),
+        }
+    }
+}
+
+// 语法分析器统一使用 io::Error，词法错误可以直接用 ? 向上传递
+impl From<LexError> for io::Error {
+    fn from(err: LexError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
 }
 
 // impl PartialEq for TokenType {
@@ -47,10 +299,12 @@ pub enum TokenType {
     Slash,
     // >=
     GE,
-    // >=
+    // >
     GT,
     // ==
     EQ,
+    // !=
+    NE,
     // <=
     LE,
     // <
@@ -61,47 +315,129 @@ pub enum TokenType {
     LeftParen,
     // )
     RightParen,
+    // { }
+    LeftBrace,
+    RightBrace,
+    // :，标签后面的冒号
+    Colon,
+    // ,，参数之间的逗号
+    Comma,
     // =
     Assignment,
+    // %
+    Percent,
+    // ++ --
+    Inc,
+    Dec,
+    // && || !
+    And,
+    Or,
+    Not,
+    // & | ^ ~
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    // << >> >>>
+    LeftShift,
+    RightShift,
+    UnsignedRightShift,
+    // 复合赋值 += -= *= /= %=
+    PlusAssign,
+    MinusAssign,
+    StarAssign,
+    SlashAssign,
+    PercentAssign,
+    // <<= >>= >>>=
+    LeftShiftAssign,
+    RightShiftAssign,
+    UnsignedRightShiftAssign,
+    // &= |= ^=
+    BitAndAssign,
+    BitOrAssign,
+    BitXorAssign,
     If,
     Else,
     Int,
+    While,
+    For,
+    Break,
+    Continue,
+    Return,
     //标识符
     Identifier,
     //整型字面量
     IntLiteral,
+    //十六进制、二进制、八进制整型字面量，0x1F、0b101、0o17
+    HexLiteral,
+    BinaryLiteral,
+    OctalLiteral,
+    //浮点数字面量
+    FloatLiteral,
     //字符串字面量
     StringLiteral,
+    //字符字面量 'a'、'\n'
+    CharLiteral,
+    //布尔字面量 true、false
+    BooleanLiteral,
+    //null
+    NullLiteral,
+    //注释，不进入Token流，单独保存
+    Comment,
+    //从 ANTLR 语法文件加载的 Token 类型，编号和 .tokens 文件中的一致
+    Custom(u32),
 }
 
 
-#[derive(Debug, PartialEq)]
-enum DfaState {
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum DfaState {
     Initial,
-    If,
-    IdIf1,
-    IdIf2,
-    Else,
-    IdElse1,
-    IdElse2,
-    IdElse3,
-    IdElse4,
-    Int,
-    IdInt1,
-    IdInt2,
-    IdInt3,
+    // 标识符和关键字，关键字在 Token 结束时查表区分
     Id,
-    GT,
-    GE,
-    Assignment,
-    Plus,
-    Minus,
-    Star,
+    // 运算符，按最长匹配一直读到不能再组成更长的运算符为止，比如 >、>>、>>>、>>>=
+    Operator,
+    // 读到 /，可能是除号、/=，也可能是注释的开始
     Slash,
     SemiColon,
     LeftParen,
     RightParen,
+    LeftBrace,
+    RightBrace,
+    Colon,
+    Comma,
+    // 十进制数字，以及开头的 0（后面可能是 x、b、o）
     IntLiteral,
+    NumberZero,
+    // 0x、0b、0o 之后的数字
+    RadixDigits,
+    // 小数点之后的数字，单独的 . 之后必须是数字
+    Fraction,
+    Dot,
+    // 指数部分：e、e 之后的正负号、指数的数字
+    Exponent,
+    ExponentSign,
+    ExponentDigits,
+    // 数字的后缀 l、f、d
+    NumberSuffix,
+    // 数字后面紧跟着不合法的字符，一直读到数字结束再报错
+    BadNumber,
+    // 字符串和字符字面量内部，以及转义序列 \x、\u{ 和 \u{...} 的内部
+    StringChars,
+    StringEscape,
+    StringUnicodeStart,
+    StringUnicode,
+    // 读到了结束的 "
+    StringLiteral,
+    // 读到了结束的 '
+    CharLiteral,
+    // 行注释 //...
+    LineComment,
+    // 块注释 /*...*/ 内部，以及刚读到 * 或 /（可能是结束或者嵌套）
+    BlockComment,
+    BlockCommentStar,
+    BlockCommentSlash,
+    // 读到了最外层的 */
+    BlockCommentEnd,
 }
 
 // pub trait ASTNode<T: ASTNode<T> + Sized> {
@@ -120,33 +456,76 @@ pub trait ASTNode {
     // // 父节点
     // fn get_parent(&self) -> Option<Rc<Self>>;
     // 子节点
-    fn get_children(&self) -> RefMut<Vec<Rc<Self>>>;
+    fn get_children(&self) -> RefMut<'_, Vec<Rc<Self>>>;
     // AST 类型
     fn get_type(&self) -> ASTNodeType;
     // 文本值
     fn get_text(&self) -> &str;
+    // 节点对应的源码区间
+    fn get_span(&self) -> Span;
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ASTNodeType {
     /// 程序入口，根节点
     Program,
     /// 整型变量声明
     IntDeclaration,
+    /// 函数声明，文本是函数名，子节点依次是各个参数和函数体
+    FunctionDeclaration,
+    /// 函数的参数，文本是参数名
+    FormalParameter,
     /// 表达式语句，即表达式后面跟个分号
     ExpressionStmt,
     /// 赋值语句
     AssignmentStmt,
+    /// 块语句 { ... }，子节点是其中的语句，块中声明的变量只在块中可见
+    Block,
+    /// if 语句，子节点依次是条件、then 分支，以及可选的 else 分支
+    IfStmt,
+    /// while 循环，子节点是条件和循环体，文本是循环的标签，没有标签时为空
+    WhileStmt,
+    /// for 循环，子节点依次是初始化、条件、步进和循环体，省略的部分是空语句
+    ForStmt,
+    /// break 和 continue，文本是要跳出或者继续的循环的标签，没有标签时为空
+    BreakStmt,
+    ContinueStmt,
+    /// return 语句，子节点是可选的返回值
+    ReturnStmt,
+    /// 空语句，只有一个分号
+    EmptyStmt,
     /// 基础表达式
     Primary,
+    /// 逻辑运算 && ||
+    Logical,
+    /// 位运算 & | ^
+    Bitwise,
+    /// 移位 << >> >>>
+    Shift,
+    /// 前缀运算 - ! ~ ++ --，子节点是操作数
+    Unary,
+    /// 后缀运算 ++ --，子节点是操作数
+    Postfix,
+    /// 相等比较 == !=
+    Equality,
+    /// 大小比较 > >= < <=
+    Relational,
     /// 乘法表达式
     Multiplicative,
     /// 加法表达式
     Additive,
     /// 标识符
     Identifier,
+    /// 函数调用，文本是函数名，子节点是各个实参
+    FunctionCall,
     /// 整型字面量
     IntLiteral,
+    /// 布尔字面量，文本是 true 或 false
+    BooleanLiteral,
+    /// 字符字面量，文本是处理完转义之后的字符
+    CharLiteral,
+    /// null
+    NullLiteral,
 }
 
 impl fmt::Display for ASTNodeType {
@@ -154,13 +533,34 @@ impl fmt::Display for ASTNodeType {
         match self {
             ASTNodeType::Program => write!(f, "Program"),
             ASTNodeType::IntDeclaration => write!(f, "IntDeclaration"),
+            ASTNodeType::FunctionDeclaration => write!(f, "FunctionDeclaration"),
+            ASTNodeType::FormalParameter => write!(f, "FormalParameter"),
             ASTNodeType::ExpressionStmt => write!(f, "ExpressionStmt"),
             ASTNodeType::AssignmentStmt => write!(f, "AssignmentStmt"),
+            ASTNodeType::Block => write!(f, "Block"),
+            ASTNodeType::IfStmt => write!(f, "IfStmt"),
+            ASTNodeType::WhileStmt => write!(f, "WhileStmt"),
+            ASTNodeType::ForStmt => write!(f, "ForStmt"),
+            ASTNodeType::BreakStmt => write!(f, "BreakStmt"),
+            ASTNodeType::ContinueStmt => write!(f, "ContinueStmt"),
+            ASTNodeType::ReturnStmt => write!(f, "ReturnStmt"),
+            ASTNodeType::EmptyStmt => write!(f, "EmptyStmt"),
             ASTNodeType::Primary => write!(f, "Primary"),
+            ASTNodeType::Logical => write!(f, "Logical"),
+            ASTNodeType::Bitwise => write!(f, "Bitwise"),
+            ASTNodeType::Shift => write!(f, "Shift"),
+            ASTNodeType::Unary => write!(f, "Unary"),
+            ASTNodeType::Postfix => write!(f, "Postfix"),
+            ASTNodeType::Equality => write!(f, "Equality"),
+            ASTNodeType::Relational => write!(f, "Relational"),
             ASTNodeType::Multiplicative => write!(f, "Multiplicative"),
             ASTNodeType::Additive => write!(f, "Additive"),
             ASTNodeType::Identifier => write!(f, "Identifier"),
+            ASTNodeType::FunctionCall => write!(f, "FunctionCall"),
             ASTNodeType::IntLiteral => write!(f, "IntLiteral"),
+            ASTNodeType::BooleanLiteral => write!(f, "BooleanLiteral"),
+            ASTNodeType::CharLiteral => write!(f, "CharLiteral"),
+            ASTNodeType::NullLiteral => write!(f, "NullLiteral"),
             _ => write!(f, "unknown AST node type"),
         }
     }
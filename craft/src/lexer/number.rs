@@ -0,0 +1,157 @@
+use crate::lexer::{LexErrorReason, TokenType, TokenValue};
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{LexErrorReason, TokenType, TokenValue};
+
+    use super::parse_number;
+
+    #[test]
+    pub fn test_parse_number() {
+        let cases = [
+            ("45", TokenType::IntLiteral, TokenValue::Int(45)),
+            ("1_000_000", TokenType::IntLiteral, TokenValue::Int(1_000_000)),
+            ("007", TokenType::IntLiteral, TokenValue::Int(7)),
+            ("2147483648", TokenType::IntLiteral, TokenValue::Int(2_147_483_648)),
+            ("3000000000L", TokenType::IntLiteral, TokenValue::Int(3_000_000_000)),
+            ("0x1F", TokenType::HexLiteral, TokenValue::Int(31)),
+            ("0XdEaD_bEeFl", TokenType::HexLiteral, TokenValue::Int(0xDEAD_BEEF)),
+            ("0b1010", TokenType::BinaryLiteral, TokenValue::Int(10)),
+            ("0o777", TokenType::OctalLiteral, TokenValue::Int(511)),
+            ("1.5", TokenType::FloatLiteral, TokenValue::Float(1.5)),
+            ("1.", TokenType::FloatLiteral, TokenValue::Float(1.0)),
+            (".25", TokenType::FloatLiteral, TokenValue::Float(0.25)),
+            ("1e3", TokenType::FloatLiteral, TokenValue::Float(1000.0)),
+            ("2.5E-1_0f", TokenType::FloatLiteral, TokenValue::Float(2.5e-10)),
+            ("3d", TokenType::FloatLiteral, TokenValue::Float(3.0)),
+        ];
+        for (text, token_type, value) in cases {
+            assert_eq!(parse_number(text), Ok((token_type, value)), "parse {}", text);
+        }
+
+        let errors = [
+            ("0x", LexErrorReason::MalformedNumber),
+            ("0b", LexErrorReason::MalformedNumber),
+            ("1e", LexErrorReason::MalformedNumber),
+            ("1e+", LexErrorReason::MalformedNumber),
+            ("1_", LexErrorReason::MalformedNumber),
+            ("0x_1", LexErrorReason::MalformedNumber),
+            ("1_.5", LexErrorReason::MalformedNumber),
+            ("2147483649", LexErrorReason::NumberOutOfRange),
+            ("0x80000000", LexErrorReason::NumberOutOfRange),
+            ("0xFFFFFFFFFFFFFFFFF", LexErrorReason::NumberOutOfRange),
+            ("1e39f", LexErrorReason::NumberOutOfRange),
+            ("1e309", LexErrorReason::NumberOutOfRange),
+        ];
+        for (text, reason) in errors {
+            assert_eq!(parse_number(text), Err(reason), "parse {}", text);
+        }
+    }
+}
+
+
+/// 校验数字字面量并计算它的值。支持：
+/// - 十进制整数，以及 0x、0b、0o 开头的十六进制、二进制、八进制整数，可以带 l/L 后缀表示长整数
+/// - 带小数点或者指数的十进制浮点数，可以带 f/F、d/D 后缀，十进制整数带 f/d 后缀时也是浮点数
+/// - 数字之间可以用 _ 分隔，但 _ 不能出现在一组数字的开头或者结尾
+///
+/// 没有后缀的整数必须在 i32 的范围之内，带 L 后缀的在 i64 之内；带 f 后缀的浮点数必须能用 f32 表示。
+/// 字面量本身没有符号，为了能写出 -2147483648，十进制的 2147483648 也是合法的，由语法分析检查它是否跟在负号后面。
+pub fn parse_number(text: &str) -> Result<(TokenType, TokenValue), LexErrorReason> {
+    let (token_type, radix, body) = match text.get(..2) {
+        Some("0x") | Some("0X") => (TokenType::HexLiteral, 16, &text[2..]),
+        Some("0b") | Some("0B") => (TokenType::BinaryLiteral, 2, &text[2..]),
+        Some("0o") | Some("0O") => (TokenType::OctalLiteral, 8, &text[2..]),
+        _ => (TokenType::IntLiteral, 10, text),
+    };
+
+    // 十六进制中 d、f 是数字，不是后缀
+    let suffix = body.chars().last().filter(|ch| match ch {
+        'l' | 'L' => true,
+        'f' | 'F' | 'd' | 'D' => radix == 10,
+        _ => false,
+    });
+    let body = match suffix {
+        Some(_) => &body[..body.len() - 1],
+        None => body,
+    };
+
+    let is_float = radix == 10 && (body.contains(['.', 'e', 'E']) || matches!(suffix, Some('f' | 'F' | 'd' | 'D')));
+    if is_float {
+        if matches!(suffix, Some('l' | 'L')) {
+            return Err(LexErrorReason::MalformedNumber);
+        }
+        return parse_float(body, matches!(suffix, Some('f' | 'F')));
+    }
+
+    check_digit_group(body, radix)?;
+    let value = u64::from_str_radix(&body.replace('_', ""), radix).map_err(|_| LexErrorReason::NumberOutOfRange)?;
+    let max = match suffix {
+        Some(_) => i64::MAX as u64,
+        None if radix == 10 => i32::MAX as u64 + 1,
+        None => i32::MAX as u64,
+    };
+    if value > max {
+        return Err(LexErrorReason::NumberOutOfRange);
+    }
+
+    Ok((token_type, TokenValue::Int(value as i64)))
+}
+
+// 整型字面量在脚本中的值，可以带负号，不是整数或者超出了 i32 的范围时返回None
+pub fn int_value(text: &str) -> Option<i32> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    match parse_number(text) {
+        Ok((_, TokenValue::Int(value))) => i32::try_from(if negative { -value } else { value }).ok(),
+        _ => None,
+    }
+}
+
+// 十进制浮点数：整数部分.小数部分 e 指数，整数部分和小数部分不能同时为空
+fn parse_float(body: &str, single: bool) -> Result<(TokenType, TokenValue), LexErrorReason> {
+    let (mantissa, exponent) = match body.find(['e', 'E']) {
+        Some(index) => (&body[..index], Some(&body[index + 1..])),
+        None => (body, None),
+    };
+    let (integer, fraction) = match mantissa.find('.') {
+        Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+        None => (mantissa, ""),
+    };
+
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(LexErrorReason::MalformedNumber);
+    }
+    for group in [integer, fraction] {
+        if !group.is_empty() {
+            check_digit_group(group, 10)?;
+        }
+    }
+    if let Some(exponent) = exponent {
+        check_digit_group(exponent.strip_prefix(['+', '-']).unwrap_or(exponent), 10)?;
+    }
+
+    let value: f64 = body.replace('_', "").parse().map_err(|_| LexErrorReason::MalformedNumber)?;
+    let finite = if single { (value as f32).is_finite() } else { value.is_finite() };
+    if !finite {
+        return Err(LexErrorReason::NumberOutOfRange);
+    }
+
+    Ok((TokenType::FloatLiteral, TokenValue::Float(value)))
+}
+
+// 一组数字：至少有一个数字，_ 只能出现在数字之间
+fn check_digit_group(group: &str, radix: u32) -> Result<(), LexErrorReason> {
+    let well_formed = !group.is_empty()
+        && !group.starts_with('_')
+        && !group.ends_with('_')
+        && group.chars().all(|ch| ch == '_' || ch.is_digit(radix));
+
+    if well_formed {
+        Ok(())
+    } else {
+        Err(LexErrorReason::MalformedNumber)
+    }
+}
@@ -5,7 +5,7 @@ use std::ops::Deref;
 use std::rc::{Rc, Weak};
 use std::str::FromStr;
 
-use crate::lexer::{ASTNode, ASTNodeType, simple_lexer, TokenReader, TokenType};
+use crate::lexer::{ASTNode, ASTNodeType, LexError, LexErrorReason, number, Position, simple_lexer, Span, TokenReader, TokenType};
 use crate::simple_calculator;
 
 #[cfg(test)]
@@ -15,12 +15,13 @@ mod tests {
     use crate::lexer::simple_lexer::SimpleLexer;
 
     #[test]
+    #[allow(clippy::unnecessary_unwrap)]
     pub fn test() {
         let script = "int a = b+3;";
         println!("解析变量甚么语句: {}", script);
 
         let lexer = SimpleLexer::new();
-        let mut token_reader = lexer.tokenize(script);
+        let mut token_reader = lexer.tokenize(script).unwrap();
         // lexer.dump(&mut token_reader);
 
         let calculator = SimpleCalculator::new();
@@ -76,13 +77,19 @@ impl SimpleCalculator {
 
     fn demo() -> Result<i32, io::Error> {
         let e = io::Error::new(io::ErrorKind::InvalidInput, "variable name expected");
-        return Err(e);
+        Err(e)
     }
 
     // 打印 AST Tree
     // 打印 计算结果
     fn evaluate(&self, code: &str) {
-        let tree = self.parse(code);
+        let tree = match self.parse(code) {
+            Ok(tree) => tree,
+            Err(err) => {
+                println!("tokenize failed: {}", err);
+                return;
+            }
+        };
         println!("dump ASTTree :");
         tree.dump_ast("");
         println!(" ");
@@ -101,7 +108,7 @@ impl SimpleCalculator {
             }
             ASTNodeType::Additive | ASTNodeType::Multiplicative => {
                 let children = node.get_children();
-                let child1 = children.get(0).expect("child 1 not found");
+                let child1 = children.first().expect("child 1 not found");
                 let child2 = children.get(1).expect("child 2 not found");
 
                 let num1 = self.calculate_and_print(child1, format!("{}\t", indent).as_str());
@@ -116,8 +123,8 @@ impl SimpleCalculator {
                 }
             }
             ASTNodeType::IntLiteral => {
-                result = i32::from_str(node.get_text()).unwrap_or_else(|e| {
-                    panic!("parse {} failed {}", node.get_text(), e);
+                result = number::int_value(node.get_text()).unwrap_or_else(|| {
+                    panic!("parse {} failed", node.get_text());
                 });
             }
             _ => { println!("found unhandled node: {}", node.get_type()) }
@@ -128,20 +135,20 @@ impl SimpleCalculator {
     }
 
     // 解析脚本，并返回根节点
-    fn parse(&self, code: &str) -> SimpleASTNode {
+    fn parse(&self, code: &str) -> Result<SimpleASTNode, LexError> {
         let lexer = simple_lexer::SimpleLexer::new();
-        let mut tokens = lexer.tokenize(code);
-        self.get_root(&mut tokens)
+        let mut tokens = lexer.tokenize(code)?;
+        Ok(self.get_root(&mut tokens))
     }
 
     // 语法解析：根节点
     fn get_root<T: TokenReader>(&self, tokens: &mut T) -> SimpleASTNode {
-        let node = SimpleASTNode::new(ASTNodeType::Program, "program");
+        let node = SimpleASTNode::new(ASTNodeType::Program, "program", Span::default());
         let child = self.additive(tokens);
         match child {
             Ok(child) => {
-                if !child.is_none() {
-                    node.add_child(RefCell::new(Rc::new(child.unwrap())));
+                if let Some(child) = child {
+                    node.add_child(RefCell::new(Rc::new(child)));
                 }
             }
             Err(err) => { println!("get root child failed: {} ", err) }
@@ -159,7 +166,7 @@ impl SimpleCalculator {
 
         //  token.Type = TokenType::Int
 
-        let _ = tokens.read(); // 消耗掉int
+        let start = tokens.read().unwrap().get_span(); // 消耗掉int
         let e = io::Error::new(io::ErrorKind::InvalidInput, "variable name expected");
         let token = tokens.peek().ok_or(e)?;
 
@@ -172,10 +179,10 @@ impl SimpleCalculator {
 
         let token = tokens.read().unwrap(); // 消耗掉 Identifier
         // 创建当前节点，并把变量名记到AST节点的文本值中，这里新建一个变量子节点也是可以的
-        let mut node = SimpleASTNode::new(ASTNodeType::IntDeclaration, token.get_text());
+        let mut node = SimpleASTNode::new(ASTNodeType::IntDeclaration, token.get_text(), start);
 
         let token = tokens.peek();
-        if !token.is_none() && token.unwrap().get_type() == TokenType::Assignment {
+        if token.is_some() && token.unwrap().get_type() == TokenType::Assignment {
             let _ = tokens.read(); // 消耗掉 =
 
             let e = io::Error::new(io::ErrorKind::InvalidInput,
@@ -186,11 +193,11 @@ impl SimpleCalculator {
 
         let token = tokens.peek();
         if token.is_none() || token.unwrap().get_type() != TokenType::SemiColon {
-            let e = io::Error::new(io::ErrorKind::InvalidInput, "invalid statement, expecting semicolon");
-            return Err(e);
+            return Err(error_at(tokens, "invalid statement, expecting semicolon"));
         }
 
-        let _ = tokens.read(); // 消耗掉 ;
+        let end = tokens.read().unwrap().get_span(); // 消耗掉 ;
+        node.span = start.to(end);
 
 
         Ok(Some(node))
@@ -218,9 +225,11 @@ impl SimpleCalculator {
         }
 
         let e = io::Error::new(io::ErrorKind::InvalidInput, "invalid additive expression, expecting the right part.");
-        let node = SimpleASTNode::new(ASTNodeType::Multiplicative, tokens.read().unwrap().get_text());
+        let token = tokens.read().unwrap();
+        let mut node = SimpleASTNode::new(ASTNodeType::Multiplicative, token.get_text(), token.get_span());
         let child1 = child1.unwrap();
         let child2 = self.additive(tokens)?.ok_or(e)?;
+        node.span = child1.span.to(child2.span);
 
         node.add_child(RefCell::new(Rc::new(child1)));
         node.add_child(RefCell::new(Rc::new(child2)));
@@ -250,10 +259,12 @@ impl SimpleCalculator {
         }
 
 
-        let node = SimpleASTNode::new(ASTNodeType::Multiplicative, tokens.read().unwrap().get_text());
+        let token = tokens.read().unwrap();
+        let mut node = SimpleASTNode::new(ASTNodeType::Multiplicative, token.get_text(), token.get_span());
         let e = io::Error::new(io::ErrorKind::InvalidInput, "invalid additive expression, expecting the right part.");
         let child1 = child1.unwrap();
         let child2 = self.multiplicative(tokens)?.ok_or(e)?;
+        node.span = child1.span.to(child2.span);
 
         node.add_child(RefCell::new(Rc::new(child1)));
         node.add_child(RefCell::new(Rc::new(child2)));
@@ -272,13 +283,12 @@ impl SimpleCalculator {
         let token = token.unwrap();
 
         match token.get_type() {
-            TokenType::IntLiteral => { // 整型字面量
-                let token = tokens.read().unwrap();
-                Ok(Some(SimpleASTNode::new(ASTNodeType::IntLiteral, token.get_text())))
+            TokenType::IntLiteral | TokenType::HexLiteral | TokenType::BinaryLiteral | TokenType::OctalLiteral => { // 整型字面量
+                simple_calculator::int_literal(tokens).map(Some)
             }
             TokenType::Identifier => { // 变量名
                 let token = tokens.read().unwrap();
-                Ok(Some(SimpleASTNode::new(ASTNodeType::Identifier, token.get_text())))
+                Ok(Some(SimpleASTNode::new(ASTNodeType::Identifier, token.get_text(), token.get_span())))
             }
             TokenType::LeftParen => { // (
                 let token = tokens.read().unwrap(); // 消耗掉 (
@@ -299,11 +309,11 @@ impl SimpleCalculator {
                     return Ok(node);
                 }
 
-                Err(simple_calculator::invalid_input_err("expecting right parenthesis"))
+                Err(simple_calculator::invalid_input_err_at("expecting right parenthesis", token.get_span().start))
             }
             _ => {
                 // invalid_input_err("unknown token type")
-                Err(simple_calculator::invalid_input_err("unknown token type"))
+                Err(simple_calculator::invalid_input_err_at("unknown token type", token.get_span().start))
             }
         }
     }
@@ -314,21 +324,46 @@ pub fn invalid_input_err(err: &str) -> io::Error {
     io::Error::new(io::ErrorKind::InvalidInput, err)
 }
 
+// 带上出错的位置，方便定位到脚本中的具体地方
+pub fn invalid_input_err_at(err: &str, position: Position) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("{} at {}", err, position))
+}
+
+// 在下一个 Token 的位置上报错，Token 流已经读完时报在脚本末尾
+pub fn error_at<T: TokenReader>(tokens: &T, err: &str) -> io::Error {
+    match tokens.peek() {
+        Some(token) => invalid_input_err_at(err, token.get_span().start),
+        None => invalid_input_err(format!("{} at end of script", err).as_str()),
+    }
+}
+
+// 整型字面量节点，值超出 int 范围的字面量在这里报错，比如没有跟在负号后面的 2147483648
+pub(crate) fn int_literal<T: TokenReader>(tokens: &mut T) -> Result<SimpleASTNode, io::Error> {
+    let token = tokens.read().unwrap();
+    if number::int_value(token.get_text()).is_none() {
+        let msg = format!("{} '{}'", LexErrorReason::NumberOutOfRange, token.get_text());
+        return Err(invalid_input_err_at(&msg, token.get_span().start));
+    }
+    Ok(SimpleASTNode::new(ASTNodeType::IntLiteral, token.get_text(), token.get_span()))
+}
+
 
 pub struct SimpleASTNode {
     // parent: RefCell<Weak<SimpleASTNode>>,
     children: RefCell<Vec<Rc<SimpleASTNode>>>,
     node_type: ASTNodeType,
     text: String,
+    pub span: Span,
 }
 
 impl SimpleASTNode {
-    pub fn new(node_type: ASTNodeType, text: &str) -> Self {
+    pub fn new(node_type: ASTNodeType, text: &str, span: Span) -> Self {
         SimpleASTNode {
             // parent: RefCell::new(Weak::new()),
             children: RefCell::new(Vec::new()),
             node_type,
             text: text.to_string(),
+            span,
         }
     }
 
@@ -356,7 +391,7 @@ impl ASTNode for SimpleASTNode {
     //     self.parent.borrow().upgrade()
     // }
 
-    fn get_children(&self) -> RefMut<Vec<Rc<SimpleASTNode>>> {
+    fn get_children(&self) -> RefMut<'_, Vec<Rc<SimpleASTNode>>> {
         self.children.borrow_mut()
     }
 
@@ -367,6 +402,10 @@ impl ASTNode for SimpleASTNode {
     fn get_text(&self) -> &str {
         &self.text
     }
+
+    fn get_span(&self) -> Span {
+        self.span
+    }
 }
 
 // fn dump_ast<T: ASTNode>(node: &T, indent: &str) {
@@ -1,7 +1,19 @@
-use crate::lexer::{DfaState, Token, TokenReader, TokenType};
+use std::io::{BufReader, Read};
+
+use crate::lexer::{DfaState, Lexer, LexError, LexErrorReason, LexWarning, Position, Span, Token, TokenReader, TokenType, TokenValue};
+use crate::lexer::identifier::{self, ConfusableDetector};
+use crate::lexer::incremental::IncrementalTokens;
+use crate::lexer::keyword::KeywordTable;
+use crate::lexer::lossless::LosslessTokenReader;
+use crate::lexer::number;
+use crate::lexer::borrowed_token::{BorrowedTokenReader, BorrowedTokens};
+use crate::lexer::stream_lexer::{DEFAULT_REPLAY, StreamTokenReader};
 
 #[cfg(test)]
 mod tests {
+    use crate::lexer::{LexError, LexErrorReason, Position, TokenReader, TokenType, TokenValue};
+    use crate::lexer::keyword::KeywordTable;
+
     use super::SimpleLexer;
 
     #[test]
@@ -9,39 +21,39 @@ mod tests {
         let lexer = SimpleLexer::new();
         let script = "int age = 45;";
         println!("parse = {}", script);
-        let mut token_reader = lexer.tokenize(script);
+        let mut token_reader = lexer.tokenize(script).unwrap();
         lexer.dump(&mut token_reader);
 
         let lexer = SimpleLexer::new();
         let script = "inta age = 45;";
         println!("parse = {}", script);
-        let mut token_reader = lexer.tokenize(script);
+        let mut token_reader = lexer.tokenize(script).unwrap();
         lexer.dump(&mut token_reader);
 
         let lexer = SimpleLexer::new();
         let script = "in age = 45;";
         println!("parse = {}", script);
-        let mut token_reader = lexer.tokenize(script);
+        let mut token_reader = lexer.tokenize(script).unwrap();
         lexer.dump(&mut token_reader);
 
 
         let lexer = SimpleLexer::new();
         let script = "age >= 45;";
         println!("parse = {}", script);
-        let mut token_reader = lexer.tokenize(script);
+        let mut token_reader = lexer.tokenize(script).unwrap();
         lexer.dump(&mut token_reader);
 
 
         let lexer = SimpleLexer::new();
         let script = "age > 45;";
         println!("parse = {}", script);
-        let mut token_reader = lexer.tokenize(script);
+        let mut token_reader = lexer.tokenize(script).unwrap();
         lexer.dump(&mut token_reader);
 
         let lexer = SimpleLexer::new();
         let script = "2+3*5";
         println!("parse = {}", script);
-        let mut token_reader = lexer.tokenize(script);
+        let mut token_reader = lexer.tokenize(script).unwrap();
         lexer.dump(&mut token_reader);
 
 
@@ -58,6 +70,292 @@ mod tests {
         // println!("token_text = {:?}", token_text);
         // println!("token.text = {:?}", token.get_text());
     }
+
+    #[test]
+    pub fn test_span() {
+        let lexer = SimpleLexer::new();
+        let mut token_reader = lexer.tokenize("int a = 4;\n年龄 >= 45;").unwrap();
+
+        let token = token_reader.read().unwrap();
+        assert_eq!(token.get_text(), "int");
+        assert_eq!(token.get_span().start, Position { line: 1, column: 1, offset: 0 });
+        assert_eq!(token.get_span().end, Position { line: 1, column: 4, offset: 3 });
+
+        token_reader.set_position(4);
+        let token = token_reader.read().unwrap();
+        assert_eq!(token.get_text(), ";");
+        assert_eq!(token.get_span().start, Position { line: 1, column: 10, offset: 9 });
+
+        // 中文字符占 3 个字节，但只算 1 列
+        let token = token_reader.read().unwrap();
+        assert_eq!(token.get_text(), "年龄");
+        assert_eq!(token.get_span().start, Position { line: 2, column: 1, offset: 11 });
+        assert_eq!(token.get_span().end, Position { line: 2, column: 3, offset: 17 });
+
+        let token = token_reader.read().unwrap();
+        assert_eq!(token.get_text(), ">=");
+        assert_eq!(token.get_span().start, Position { line: 2, column: 4, offset: 18 });
+        assert_eq!(token.get_span().end, Position { line: 2, column: 6, offset: 20 });
+    }
+
+    #[test]
+    pub fn test_keyword() {
+        let lexer = SimpleLexer::new();
+        let cases = [
+            ("int", vec![TokenType::Int]),
+            ("int;", vec![TokenType::Int, TokenType::SemiColon]),
+            ("if(a)", vec![TokenType::If, TokenType::LeftParen, TokenType::Identifier, TokenType::RightParen]),
+            ("ifx elsewhere integer", vec![TokenType::Identifier, TokenType::Identifier, TokenType::Identifier]),
+            ("in i el", vec![TokenType::Identifier, TokenType::Identifier, TokenType::Identifier]),
+            ("else if", vec![TokenType::Else, TokenType::If]),
+            ("int a=else", vec![TokenType::Int, TokenType::Identifier, TokenType::Assignment, TokenType::Else]),
+            ("{a}", vec![TokenType::LeftBrace, TokenType::Identifier, TokenType::RightBrace]),
+            ("outer:while for break continue", vec![TokenType::Identifier, TokenType::Colon, TokenType::While, TokenType::For,
+                                                    TokenType::Break, TokenType::Continue]),
+            ("return f(a,b)", vec![TokenType::Return, TokenType::Identifier, TokenType::LeftParen, TokenType::Identifier,
+                                   TokenType::Comma, TokenType::Identifier, TokenType::RightParen]),
+        ];
+
+        for (script, expected) in cases {
+            assert_eq!(token_types(&lexer, script), expected, "tokenize {}", script);
+        }
+
+        // 中文关键字，英文关键字仍然可用
+        let lexer = SimpleLexer::with_keywords(KeywordTable::chinese());
+        assert_eq!(token_types(&lexer, "整数 年龄; 如果 (年龄) 否则 int 如果x"), vec![
+            TokenType::Int, TokenType::Identifier, TokenType::SemiColon, TokenType::If, TokenType::LeftParen,
+            TokenType::Identifier, TokenType::RightParen, TokenType::Else, TokenType::Int, TokenType::Identifier,
+        ]);
+    }
+
+    #[test]
+    pub fn test_identifier() {
+        let lexer = SimpleLexer::new();
+        let mut token_reader = lexer.tokenize("_tmp a_1 年龄2 café").unwrap();
+        let mut texts = Vec::new();
+        while let Some(token) = token_reader.read() {
+            assert_eq!(token.get_type(), TokenType::Identifier);
+            texts.push(token.get_text().to_string());
+        }
+        assert_eq!(texts, vec!["_tmp", "a_1", "年龄2", "café"]);
+        assert!(token_reader.get_warnings().is_empty());
+
+        // 第二个 scope 的第一个字母是西里尔字母
+        let token_reader = lexer.tokenize("int scope = 1; \u{455}cope = 2;").unwrap();
+        let warnings: Vec<String> = token_reader.get_warnings().iter().map(|w| w.to_string()).collect();
+        assert_eq!(warnings, vec!["identifier '\u{455}cope' at 1:16 is confusable with 'scope'"]);
+    }
+
+    #[test]
+    pub fn test_relational() {
+        let lexer = SimpleLexer::new();
+        let cases = [
+            ("age >= 17+8+20", vec![TokenType::Identifier, TokenType::GE, TokenType::IntLiteral, TokenType::Plus,
+                                    TokenType::IntLiteral, TokenType::Plus, TokenType::IntLiteral]),
+            ("a<b<=c", vec![TokenType::Identifier, TokenType::LT, TokenType::Identifier, TokenType::LE, TokenType::Identifier]),
+            ("a==b", vec![TokenType::Identifier, TokenType::EQ, TokenType::Identifier]),
+            ("a = =b", vec![TokenType::Identifier, TokenType::Assignment, TokenType::Assignment, TokenType::Identifier]),
+            ("a!=b", vec![TokenType::Identifier, TokenType::NE, TokenType::Identifier]),
+            ("a>b>", vec![TokenType::Identifier, TokenType::GT, TokenType::Identifier, TokenType::GT]),
+            ("<", vec![TokenType::LT]),
+            ("===", vec![TokenType::EQ, TokenType::Assignment]),
+        ];
+
+        for (script, expected) in cases {
+            assert_eq!(token_types(&lexer, script), expected, "tokenize {}", script);
+        }
+    }
+
+    #[test]
+    pub fn test_operator() {
+        let lexer = SimpleLexer::new();
+        let cases = [
+            ("a+=b++", vec![TokenType::Identifier, TokenType::PlusAssign, TokenType::Identifier, TokenType::Inc]),
+            ("a---b", vec![TokenType::Identifier, TokenType::Dec, TokenType::Minus, TokenType::Identifier]),
+            ("a>>>=b>>>c>>d", vec![TokenType::Identifier, TokenType::UnsignedRightShiftAssign, TokenType::Identifier,
+                                   TokenType::UnsignedRightShift, TokenType::Identifier, TokenType::RightShift, TokenType::Identifier]),
+            ("<<= << >>=", vec![TokenType::LeftShiftAssign, TokenType::LeftShift, TokenType::RightShiftAssign]),
+            ("!a&&b||~c", vec![TokenType::Not, TokenType::Identifier, TokenType::And, TokenType::Identifier, TokenType::Or,
+                               TokenType::BitNot, TokenType::Identifier]),
+            ("&=&|=|^=^%=%", vec![TokenType::BitAndAssign, TokenType::BitAnd, TokenType::BitOrAssign, TokenType::BitOr,
+                                  TokenType::BitXorAssign, TokenType::BitXor, TokenType::PercentAssign, TokenType::Percent]),
+            ("a*=2/=3", vec![TokenType::Identifier, TokenType::StarAssign, TokenType::IntLiteral, TokenType::SlashAssign,
+                             TokenType::IntLiteral]),
+            ("!!=!", vec![TokenType::Not, TokenType::NE, TokenType::Not]),
+            ("a/=/*x*/b//=", vec![TokenType::Identifier, TokenType::SlashAssign, TokenType::Identifier]),
+        ];
+
+        for (script, expected) in cases {
+            assert_eq!(token_types(&lexer, script), expected, "tokenize {}", script);
+        }
+    }
+
+    #[test]
+    pub fn test_string_literal() {
+        let lexer = SimpleLexer::new();
+        let mut token_reader = lexer.tokenize(r#"printf("Hello old man!\n", "a\t\"b\"\\", "\u{5e74}\u{1F600}", "");"#).unwrap();
+
+        let expected = [
+            (TokenType::Identifier, r#"printf"#, None),
+            (TokenType::LeftParen, "(", None),
+            (TokenType::StringLiteral, r#""Hello old man!\n""#, Some("Hello old man!\n")),
+            (TokenType::Comma, ",", None),
+            (TokenType::StringLiteral, r#""a\t\"b\"\\""#, Some("a\t\"b\"\\")),
+            (TokenType::Comma, ",", None),
+            (TokenType::StringLiteral, r#""\u{5e74}\u{1F600}""#, Some("年😀")),
+            (TokenType::Comma, ",", None),
+            (TokenType::StringLiteral, r#""""#, Some("")),
+            (TokenType::RightParen, ")", None),
+            (TokenType::SemiColon, ";", None),
+        ];
+        for (token_type, text, value) in expected {
+            let token = token_reader.read().unwrap();
+            assert_eq!(token.get_type(), token_type);
+            assert_eq!(token.get_text(), text);
+            assert_eq!(token.get_value(), value.map(|v| TokenValue::Str(v.to_string())).as_ref());
+        }
+        assert!(token_reader.read().is_none());
+    }
+
+    #[test]
+    pub fn test_string_error() {
+        let lexer = SimpleLexer::new();
+        let cases = [
+            ("a = \"abc", LexError::new('"', Position { line: 1, column: 5, offset: 4 }, LexErrorReason::UnterminatedString)),
+            ("\"abc\nd\"", LexError::new('"', Position { line: 1, column: 1, offset: 0 }, LexErrorReason::UnterminatedString)),
+            ("\"a\\qb\"", LexError::new('q', Position { line: 1, column: 3, offset: 2 }, LexErrorReason::InvalidEscape)),
+            ("\"\\u{110000}\"", LexError::new('}', Position { line: 1, column: 2, offset: 1 }, LexErrorReason::InvalidUnicodeEscape)),
+            ("\"\\u41\"", LexError::new('4', Position { line: 1, column: 2, offset: 1 }, LexErrorReason::InvalidUnicodeEscape)),
+        ];
+
+        for (script, expected) in cases {
+            assert_eq!(lexer.tokenize(script).err(), Some(expected), "tokenize {}", script);
+        }
+    }
+
+    #[test]
+    pub fn test_char_and_bool_literal() {
+        let lexer = SimpleLexer::new();
+        let mut token_reader = lexer.tokenize(r"'a' '\'' '\u{5e74}' true false null trueish").unwrap();
+
+        let expected = [
+            (TokenType::CharLiteral, Some(TokenValue::Char('a'))),
+            (TokenType::CharLiteral, Some(TokenValue::Char('\''))),
+            (TokenType::CharLiteral, Some(TokenValue::Char('年'))),
+            (TokenType::BooleanLiteral, Some(TokenValue::Bool(true))),
+            (TokenType::BooleanLiteral, Some(TokenValue::Bool(false))),
+            (TokenType::NullLiteral, None),
+            (TokenType::Identifier, None),
+        ];
+        for (token_type, value) in expected {
+            let token = token_reader.read().unwrap();
+            assert_eq!(token.get_type(), token_type, "token {}", token.get_text());
+            assert_eq!(token.get_value(), value.as_ref());
+        }
+
+        let cases = [
+            ("a = ''", LexError::new('\'', Position { line: 1, column: 5, offset: 4 }, LexErrorReason::InvalidCharLiteral)),
+            ("'ab'", LexError::new('\'', Position { line: 1, column: 1, offset: 0 }, LexErrorReason::InvalidCharLiteral)),
+            ("'a", LexError::new('\'', Position { line: 1, column: 1, offset: 0 }, LexErrorReason::UnterminatedChar)),
+        ];
+        for (script, expected) in cases {
+            assert_eq!(lexer.tokenize(script).err(), Some(expected), "tokenize {}", script);
+        }
+    }
+
+    #[test]
+    pub fn test_comment() {
+        let lexer = SimpleLexer::new();
+        let script = "int a = 4; // 年龄\n/* 外层 /* 嵌套 */ **/ a = a / 2;//";
+        assert_eq!(token_types(&lexer, script), vec![
+            TokenType::Int, TokenType::Identifier, TokenType::Assignment, TokenType::IntLiteral, TokenType::SemiColon,
+            TokenType::Identifier, TokenType::Assignment, TokenType::Identifier, TokenType::Slash, TokenType::IntLiteral,
+            TokenType::SemiColon,
+        ]);
+
+        let token_reader = lexer.tokenize(script).unwrap();
+        let comments: Vec<&str> = token_reader.get_comments().iter().map(|c| c.get_text()).collect();
+        assert_eq!(comments, vec!["// 年龄", "/* 外层 /* 嵌套 */ **/", "//"]);
+        assert_eq!(token_reader.get_comments()[1].get_span().start, Position { line: 2, column: 1, offset: 21 });
+
+        let err = lexer.tokenize("a = 1; /* /* */ b").err();
+        assert_eq!(err, Some(LexError::new('/', Position { line: 1, column: 8, offset: 7 }, LexErrorReason::UnterminatedComment)));
+    }
+
+    #[test]
+    pub fn test_unexpected_char() {
+        let lexer = SimpleLexer::new();
+        let err = lexer.tokenize("int a = 4 $ 5;").err();
+        assert_eq!(err, Some(LexError::new('$', Position { line: 1, column: 11, offset: 10 }, LexErrorReason::UnexpectedChar)));
+        assert_eq!(err.unwrap().to_string(), "unexpected character '$' at 1:11");
+
+        let (mut token_reader, errors) = lexer.tokenize_with_recovery("a = 1 @ 2;\n#b = !3 ! \"x");
+        let errors: Vec<(char, usize, LexErrorReason)> = errors.iter()
+            .map(|e| (e.ch, e.position.offset, e.reason))
+            .collect();
+        assert_eq!(errors, vec![
+            ('@', 6, LexErrorReason::UnexpectedChar),
+            ('#', 11, LexErrorReason::UnexpectedChar),
+            ('"', 21, LexErrorReason::UnterminatedString),
+        ]);
+
+        let mut texts = Vec::new();
+        while let Some(token) = token_reader.read() {
+            texts.push(token.get_text().to_string());
+        }
+        assert_eq!(texts, vec!["a", "=", "1", "2", ";", "b", "=", "!", "3", "!"]);
+    }
+
+    #[test]
+    pub fn test_number() {
+        let lexer = SimpleLexer::new();
+        let mut token_reader = lexer.tokenize("a = 0x1F+1_000*2.5e-3f-.5/0b101L;12 0o17").unwrap();
+        let expected = [
+            (TokenType::Identifier, "a", None),
+            (TokenType::Assignment, "=", None),
+            (TokenType::HexLiteral, "0x1F", Some(TokenValue::Int(31))),
+            (TokenType::Plus, "+", None),
+            (TokenType::IntLiteral, "1_000", Some(TokenValue::Int(1000))),
+            (TokenType::Star, "*", None),
+            (TokenType::FloatLiteral, "2.5e-3f", Some(TokenValue::Float(2.5e-3))),
+            (TokenType::Minus, "-", None),
+            (TokenType::FloatLiteral, ".5", Some(TokenValue::Float(0.5))),
+            (TokenType::Slash, "/", None),
+            (TokenType::BinaryLiteral, "0b101L", Some(TokenValue::Int(5))),
+            (TokenType::SemiColon, ";", None),
+            (TokenType::IntLiteral, "12", Some(TokenValue::Int(12))),
+            (TokenType::OctalLiteral, "0o17", Some(TokenValue::Int(15))),
+        ];
+        for (token_type, text, value) in expected {
+            let token = token_reader.read().unwrap();
+            assert_eq!((token.get_type(), token.get_text(), token.get_value()), (token_type, text, value.as_ref()));
+        }
+
+        let cases = [
+            ("a = 0x;", LexError::with_text("0x", Position { line: 1, column: 5, offset: 4 }, LexErrorReason::MalformedNumber)),
+            ("1e+", LexError::with_text("1e+", Position { line: 1, column: 1, offset: 0 }, LexErrorReason::MalformedNumber)),
+            ("12abc", LexError::with_text("12abc", Position { line: 1, column: 1, offset: 0 }, LexErrorReason::MalformedNumber)),
+            ("0b102", LexError::with_text("0b102", Position { line: 1, column: 1, offset: 0 }, LexErrorReason::MalformedNumber)),
+            ("x = 2147483649", LexError::with_text("2147483649", Position { line: 1, column: 5, offset: 4 }, LexErrorReason::NumberOutOfRange)),
+            ("a.b", LexError::new('.', Position { line: 1, column: 2, offset: 1 }, LexErrorReason::UnexpectedChar)),
+        ];
+        for (script, expected) in cases {
+            assert_eq!(lexer.tokenize(script).err(), Some(expected), "tokenize {}", script);
+        }
+
+        // 出错的数字整个跳过
+        let (mut token_reader, _) = lexer.tokenize_with_recovery("12abc + 1");
+        assert_eq!(token_reader.read().unwrap().get_type(), TokenType::Plus);
+    }
+
+    fn token_types(lexer: &SimpleLexer, script: &str) -> Vec<TokenType> {
+        let mut token_reader = lexer.tokenize(script).unwrap();
+        let mut types = Vec::new();
+        while let Some(token) = token_reader.read() {
+            types.push(token.get_type());
+        }
+        types
+    }
 }
 
 
@@ -67,178 +365,673 @@ pub struct SimpleLexer {
     // username: String,
     // email: String,
     // sign_in_count: u64,
+    keywords: KeywordTable,
 }
 
 
 impl SimpleLexer {
     pub fn new() -> Self {
-        SimpleLexer {}
+        SimpleLexer::with_keywords(KeywordTable::english())
+    }
+
+    // 使用指定的关键字表，比如中文关键字 如果、否则、整数
+    pub fn with_keywords(keywords: KeywordTable) -> Self {
+        SimpleLexer { keywords }
+    }
+
+    // 词法分析，遇到词法错误时返回第一个错误
+    pub fn tokenize(&self, script: &str) -> Result<SimpleTokenReader, LexError> {
+        let (token_reader, mut errors) = self.tokenize_with_recovery(script);
+        if !errors.is_empty() {
+            return Err(errors.remove(0));
+        }
+
+        Ok(token_reader)
+    }
+
+    // 容错模式的词法分析：跳过出错的字符继续往下分析，返回尽力得到的 Token 流以及所有的词法错误
+    pub fn tokenize_with_recovery(&self, script: &str) -> (SimpleTokenReader, Vec<LexError>) {
+        let mut scanner = Scanner::new(OwnedTokens::new(), self.keywords.clone());
+        for ch in script.chars() {
+            scanner.feed(ch);
+        }
+
+        scanner.finish();
+        let token_reader = SimpleTokenReader::new(scanner.sink.tokens, scanner.sink.comments).with_warnings(scanner.warnings);
+        (token_reader, scanner.errors)
+    }
+
+    // 增量的词法分析：修改脚本之后只重新分析受影响的部分
+    pub fn tokenize_incremental(&self, script: &str) -> IncrementalTokens {
+        IncrementalTokens::new(script, self.keywords.clone())
     }
 
-    pub fn tokenize(&self, script: &str) -> SimpleTokenReader {
-        // let chars: Vec<char> = script.chars().collect();
+    // 无损的词法分析：每个 Token 都带着前后的空白和注释，拼起来就是原来的脚本。出错的内容也会保留下来
+    pub fn tokenize_lossless(&self, script: &str) -> (LosslessTokenReader, Vec<LexError>) {
+        let (token_reader, errors) = self.tokenize_with_recovery(script);
+        (LosslessTokenReader::new(script, token_reader), errors)
+    }
 
-        let mut state: DfaState = DfaState::Initial;
-        let mut token_text = String::new();
-        let mut chars = script.chars().peekable();
-        let mut tokens: Vec<Box<dyn Token>> = Vec::new();
-        let mut token = SimpleToken::new();
+    // 不复制文本的词法分析：Token 直接借用 script 中的文本
+    pub fn tokenize_borrowed<'src>(&self, script: &'src str) -> Result<BorrowedTokenReader<'src>, LexError> {
+        let mut scanner = Scanner::new(BorrowedTokens::new(script), self.keywords.clone());
+        for ch in script.chars() {
+            scanner.feed(ch);
+        }
+
+        scanner.finish();
+        if !scanner.errors.is_empty() {
+            return Err(scanner.errors.remove(0));
+        }
+        Ok(scanner.sink.into_reader())
+    }
+
+    // 流式的词法分析：从 reader 中按需读入脚本，边读边识别 Token
+    pub fn tokenize_stream<R: Read>(&self, reader: R) -> StreamTokenReader<BufReader<R>> {
+        StreamTokenReader::with_keywords(BufReader::new(reader), self.keywords.clone(), DEFAULT_REPLAY)
+    }
+
+    pub fn dump(&self, token_reader: &mut SimpleTokenReader) {
+        println!("text\ttype\tposition");
+        while let Some(token) = token_reader.read() {
+            println!("{}\t\t{:?}\t{}", token.get_text(), token.get_type(), token.get_span().start);
+        }
+
+        println!(" ")
+    }
+}
+
+impl Lexer for SimpleLexer {
+    fn tokenize(&self, script: &str) -> Result<SimpleTokenReader, LexError> {
+        SimpleLexer::tokenize(self, script)
+    }
+}
+
+
+// 保存有限自动机识别出来的 Token。text 是 Token 在源码中的原文，注释也会交给它
+pub(crate) trait TokenSink {
+    fn push(&mut self, token_type: TokenType, text: &str, span: Span, value: Option<TokenValue>);
+}
+
+// 把 Token 复制成 SimpleToken 保存，注释单独放
+pub(crate) struct OwnedTokens {
+    pub(crate) tokens: Vec<Box<dyn Token>>,
+    pub(crate) comments: Vec<Box<dyn Token>>,
+}
+
+impl OwnedTokens {
+    pub(crate) fn new() -> Self {
+        OwnedTokens {
+            tokens: Vec::new(),
+            comments: Vec::new(),
+        }
+    }
+}
+
+impl TokenSink for OwnedTokens {
+    fn push(&mut self, token_type: TokenType, text: &str, span: Span, value: Option<TokenValue>) {
+        let mut token = SimpleToken::with_span(token_type, text.to_string(), span);
+        token.value = value;
+        if token_type == TokenType::Comment {
+            self.comments.push(Box::new(token));
+        } else {
+            self.tokens.push(Box::new(token));
+        }
+    }
+}
 
-        while let Some(ch) = chars.next() {
-            state = match state {
-                DfaState::Initial => {
-                    init_token(ch, &mut token_text, &mut tokens, &mut token)
+// 有限自动机的运行现场：当前状态、正在拼接的 Token，以及下一个字符在源码中的位置
+pub(crate) struct Scanner<S: TokenSink> {
+    state: DfaState,
+    token_text: String,
+    token: SimpleToken,
+    pub(crate) sink: S,
+    pub(crate) errors: Vec<LexError>,
+    pub(crate) warnings: Vec<LexWarning>,
+    // 关键字先按标识符识别，结束时再查表
+    keywords: KeywordTable,
+    identifiers: ConfusableDetector,
+    pub(crate) pos: Position,
+    // 字符串、字符字面量处理完转义之后的值，以及开始的引号 " 或 '
+    literal_value: String,
+    quote: char,
+    // 正在读的转义序列从哪里开始，以及 \u{...} 里的十六进制数字
+    escape_start: Position,
+    escape_code: String,
+    // 块注释嵌套的层数
+    comment_depth: usize,
+    // 0x、0b、0o 对应的进制
+    number_radix: u32,
+}
+
+impl<S: TokenSink> Scanner<S> {
+    pub(crate) fn new(sink: S, keywords: KeywordTable) -> Self {
+        Scanner {
+            state: DfaState::Initial,
+            token_text: String::new(),
+            token: SimpleToken::new(),
+            sink,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            keywords,
+            identifiers: ConfusableDetector::new(),
+            pos: Position::new(),
+            literal_value: String::new(),
+            quote: '"',
+            escape_start: Position::new(),
+            escape_code: String::new(),
+            comment_depth: 0,
+            number_radix: 10,
+        }
+    }
+
+    // 当前的状态和正在读的 Token 的文本，导出状态图时用
+    pub(crate) fn current(&self) -> (DfaState, &str) {
+        (self.state, &self.token_text)
+    }
+
+    // 从脚本中间的某个位置开始分析，这个位置必须是 Token 的边界
+    // 只记住前 limit 个不同的标识符，用于检查容易混淆的标识符
+    pub(crate) fn with_identifier_limit(mut self, limit: usize) -> Self {
+        self.identifiers = ConfusableDetector::with_limit(limit);
+        self
+    }
+
+    pub(crate) fn with_position(mut self, pos: Position) -> Self {
+        self.pos = pos;
+        self
+    }
+
+    // 读入一个字符，驱动状态迁移
+    pub(crate) fn feed(&mut self, ch: char) {
+        self.state = match self.state {
+            DfaState::Initial => self.init_token(ch),
+            DfaState::Id => {
+                if identifier::is_id_continue(ch) {
+                    self.token_text.push(ch);
+                    DfaState::Id
+                } else {
+                    self.init_token(ch)
                 }
-                DfaState::Id => {
-                    if ch.is_alphabetic() || ch.is_ascii_digit() {
-                        token_text.push(ch);
-                        DfaState::Id
-                    } else {
-                        init_token(ch, &mut token_text, &mut tokens, &mut token)
+            }
+            DfaState::Operator => {
+                // 加上这个字符之后还能组成更长的运算符，就继续读
+                self.token_text.push(ch);
+                match operator_type(&self.token_text) {
+                    Some(token_type) => {
+                        self.token.token_type = Some(token_type);
+                        DfaState::Operator
+                    }
+                    None => {
+                        self.token_text.pop();
+                        self.init_token(ch)
                     }
                 }
-                DfaState::GT => {
-                    if ch == '=' {
-                        token.token_type = Some(TokenType::GE);
-                        token_text.push(ch);
-                        DfaState::GE
-                    } else {
-                        init_token(ch, &mut token_text, &mut tokens, &mut token)
+            }
+            DfaState::Slash => {
+                match ch {
+                    '/' => {
+                        self.token_text.push(ch);
+                        self.token.token_type = Some(TokenType::Comment);
+                        DfaState::LineComment
+                    }
+                    '*' => {
+                        self.token_text.push(ch);
+                        self.token.token_type = Some(TokenType::Comment);
+                        self.comment_depth = 1;
+                        DfaState::BlockComment
+                    }
+                    '=' => {
+                        self.token_text.push(ch);
+                        self.token.token_type = Some(TokenType::SlashAssign);
+                        DfaState::Operator
                     }
+                    _ => self.init_token(ch),
                 }
-                DfaState::GE | DfaState::Assignment | DfaState::Plus | DfaState::Minus | DfaState::Star |
-                DfaState::Slash | DfaState::SemiColon | DfaState::LeftParen | DfaState::RightParen => {
-                    init_token(ch, &mut token_text, &mut tokens, &mut token)
+            }
+            DfaState::LineComment => {
+                if ch == '\n' || ch == '\r' {
+                    self.init_token(ch)
+                } else {
+                    self.token_text.push(ch);
+                    DfaState::LineComment
                 }
-                DfaState::IntLiteral => {
-                    if ch.is_ascii_digit() {
-                        token_text.push(ch);
-                        DfaState::IntLiteral
-                    } else {
-                        init_token(ch, &mut token_text, &mut tokens, &mut token)
+            }
+            DfaState::BlockComment | DfaState::BlockCommentStar | DfaState::BlockCommentSlash => {
+                self.token_text.push(ch);
+                match (&self.state, ch) {
+                    (DfaState::BlockCommentStar, '/') => {
+                        self.comment_depth -= 1;
+                        if self.comment_depth == 0 {
+                            DfaState::BlockCommentEnd
+                        } else {
+                            DfaState::BlockComment
+                        }
                     }
-                }
-                DfaState::IdInt1 => {
-                    if ch == 'n' {
-                        token_text.push(ch);
-                        DfaState::IdInt2
-                    } else {
-                        init_token(ch, &mut token_text, &mut tokens, &mut token)
+                    // 块注释可以嵌套
+                    (DfaState::BlockCommentSlash, '*') => {
+                        self.comment_depth += 1;
+                        DfaState::BlockComment
                     }
+                    (_, '*') => DfaState::BlockCommentStar,
+                    (_, '/') => DfaState::BlockCommentSlash,
+                    _ => DfaState::BlockComment,
                 }
-                DfaState::IdInt2 => {
-                    if ch == 't' {
-                        token_text.push(ch);
-                        DfaState::IdInt3
-                    } else {
-                        init_token(ch, &mut token_text, &mut tokens, &mut token)
+            }
+            DfaState::StringChars => self.string_char(ch),
+            DfaState::StringEscape => {
+                let escaped = match ch {
+                    'n' => Some('\n'),
+                    't' => Some('\t'),
+                    'r' => Some('\r'),
+                    '"' => Some('"'),
+                    '\'' => Some('\''),
+                    '\\' => Some('\\'),
+                    _ => None,
+                };
+
+                match escaped {
+                    Some(escaped) => {
+                        self.token_text.push(ch);
+                        self.literal_value.push(escaped);
+                        DfaState::StringChars
+                    }
+                    None if ch == 'u' => {
+                        self.token_text.push(ch);
+                        self.escape_code.clear();
+                        DfaState::StringUnicodeStart
                     }
+                    None if ch == '\n' || ch == '\r' => self.string_char(ch),
+                    None => {
+                        self.errors.push(LexError::new(ch, self.escape_start, LexErrorReason::InvalidEscape));
+                        self.token_text.push(ch);
+                        DfaState::StringChars
+                    }
+                }
+            }
+            DfaState::StringUnicodeStart => {
+                if ch == '{' {
+                    self.token_text.push(ch);
+                    DfaState::StringUnicode
+                } else {
+                    self.errors.push(LexError::new(ch, self.escape_start, LexErrorReason::InvalidUnicodeEscape));
+                    self.string_char(ch)
                 }
-                DfaState::IdInt3 => {
-                    if ch.is_ascii_whitespace() {
-                        token.token_type = Some(TokenType::Int);
-                        init_token(ch, &mut token_text, &mut tokens, &mut token)
-                    } else {
-                        token_text.push(ch);
-                        DfaState::Id
+            }
+            DfaState::StringUnicode => {
+                if ch.is_ascii_hexdigit() && self.escape_code.len() < 6 {
+                    self.token_text.push(ch);
+                    self.escape_code.push(ch);
+                    DfaState::StringUnicode
+                } else if ch == '}' {
+                    self.token_text.push(ch);
+                    let code = u32::from_str_radix(&self.escape_code, 16).ok().and_then(char::from_u32);
+                    match code {
+                        Some(code) => self.literal_value.push(code),
+                        None => self.errors.push(LexError::new(ch, self.escape_start, LexErrorReason::InvalidUnicodeEscape)),
                     }
+                    DfaState::StringChars
+                } else {
+                    self.errors.push(LexError::new(ch, self.escape_start, LexErrorReason::InvalidUnicodeEscape));
+                    self.string_char(ch)
+                }
+            }
+            DfaState::SemiColon | DfaState::LeftParen | DfaState::RightParen | DfaState::LeftBrace | DfaState::RightBrace | DfaState::Colon
+            | DfaState::Comma | DfaState::StringLiteral | DfaState::CharLiteral | DfaState::BlockCommentEnd => {
+                self.init_token(ch)
+            }
+            DfaState::NumberZero if matches!(ch, 'x' | 'X' | 'b' | 'B' | 'o' | 'O') => {
+                self.token_text.push(ch);
+                self.number_radix = match ch {
+                    'x' | 'X' => 16,
+                    'b' | 'B' => 2,
+                    _ => 8,
+                };
+                DfaState::RadixDigits
+            }
+            DfaState::IntLiteral | DfaState::NumberZero => {
+                match ch {
+                    ch if ch.is_ascii_digit() || ch == '_' => self.number_char(ch, DfaState::IntLiteral),
+                    '.' => self.number_char(ch, DfaState::Fraction),
+                    'e' | 'E' => self.number_char(ch, DfaState::Exponent),
+                    'l' | 'L' | 'f' | 'F' | 'd' | 'D' => self.number_char(ch, DfaState::NumberSuffix),
+                    _ => self.end_number(ch),
+                }
+            }
+            DfaState::RadixDigits => {
+                match ch {
+                    ch if ch.is_digit(self.number_radix) || ch == '_' => self.number_char(ch, DfaState::RadixDigits),
+                    'l' | 'L' => self.number_char(ch, DfaState::NumberSuffix),
+                    _ => self.end_number(ch),
                 }
-                _ => panic!("Unhandled State!"),
             }
+            DfaState::Dot => {
+                if ch.is_ascii_digit() {
+                    self.token.token_type = Some(TokenType::IntLiteral);
+                    self.number_char(ch, DfaState::Fraction)
+                } else {
+                    // 单独的 . 不是合法的 Token
+                    self.unexpected('.');
+                    self.init_token(ch)
+                }
+            }
+            DfaState::Fraction => {
+                match ch {
+                    ch if ch.is_ascii_digit() || ch == '_' => self.number_char(ch, DfaState::Fraction),
+                    'e' | 'E' => self.number_char(ch, DfaState::Exponent),
+                    'f' | 'F' | 'd' | 'D' => self.number_char(ch, DfaState::NumberSuffix),
+                    _ => self.end_number(ch),
+                }
+            }
+            DfaState::Exponent if ch == '+' || ch == '-' => self.number_char(ch, DfaState::ExponentSign),
+            DfaState::Exponent | DfaState::ExponentSign | DfaState::ExponentDigits => {
+                match ch {
+                    ch if ch.is_ascii_digit() || ch == '_' => self.number_char(ch, DfaState::ExponentDigits),
+                    'f' | 'F' | 'd' | 'D' if self.state == DfaState::ExponentDigits => self.number_char(ch, DfaState::NumberSuffix),
+                    _ => self.end_number(ch),
+                }
+            }
+            DfaState::NumberSuffix => self.end_number(ch),
+            DfaState::BadNumber => {
+                if identifier::is_id_continue(ch) {
+                    self.token_text.push(ch);
+                    DfaState::BadNumber
+                } else {
+                    self.bad_number();
+                    self.init_token(ch)
+                }
+            }
+        };
+
+        self.pos.advance(ch);
+    }
+
+    // 输入结束，把最后一个 Token 也放进去
+    pub(crate) fn finish(&mut self) {
+        if let DfaState::StringChars | DfaState::StringEscape | DfaState::StringUnicodeStart | DfaState::StringUnicode = self.state {
+            self.unterminated_string();
         }
 
-        if !token_text.is_empty() {
-            init_token('_', &mut token_text, &mut tokens, &mut token);
+        if let DfaState::BlockComment | DfaState::BlockCommentStar | DfaState::BlockCommentSlash = self.state {
+            self.errors.push(LexError::new('/', self.token.span.start, LexErrorReason::UnterminatedComment));
+            self.token_text.clear();
+            self.token = SimpleToken::new();
         }
 
-        SimpleTokenReader::new(tokens)
-    }
+        if self.state == DfaState::Dot {
+            self.unexpected('.');
+        }
 
-    pub fn dump(&self, token_reader: &mut SimpleTokenReader) {
-        println!("text\ttype");
-        while let Some(token) = token_reader.read() {
-            println!("{}\t\t{:?}", token.get_text(), token.get_type());
+        if self.state == DfaState::BadNumber {
+            self.bad_number();
         }
 
-        println!(" ")
+        self.flush_token();
     }
-}
-
 
-fn init_token(ch: char,
-              token_text: &mut String,
-              tokens: &mut Vec<Box<dyn Token>>,
-              token: &mut SimpleToken) -> DfaState {
-    if !token_text.is_empty() {
-        token.text = token_text.clone();
-        tokens.push(Box::new(token.clone()));
-
-        token_text.clear();
-        *token = SimpleToken::new()
+    // 报告不能单独成为 Token 的字符，比如单独的 .，并丢弃它
+    fn unexpected(&mut self, ch: char) {
+        self.errors.push(LexError::new(ch, self.token.span.start, LexErrorReason::UnexpectedChar));
+        self.token_text.clear();
+        self.token = SimpleToken::new();
     }
 
-    let mut new_state = DfaState::Initial;
+    // 数字中的一个字符
+    fn number_char(&mut self, ch: char, state: DfaState) -> DfaState {
+        self.token_text.push(ch);
+        state
+    }
 
+    // 数字之后的字符：紧跟着字母、数字或者 _ 说明数字的格式不对，比如 12abc、0b12，
+    // 读完整个单词之后报错并跳过它；其他字符开始一个新的 Token
+    fn end_number(&mut self, ch: char) -> DfaState {
+        if identifier::is_id_continue(ch) {
+            self.token_text.push(ch);
+            self.token.token_type = None;
+            DfaState::BadNumber
+        } else {
+            self.init_token(ch)
+        }
+    }
 
-    token_text.push(ch);
+    // 报告格式不对的数字，和 parse_number 报告的错误一样，位置是数字的开头，文本是整个单词
+    fn bad_number(&mut self) {
+        self.errors.push(LexError::with_text(&self.token_text, self.token.span.start, LexErrorReason::MalformedNumber));
+        self.token_text.clear();
+        self.token = SimpleToken::new();
+    }
 
-    match ch {
-        ch if ch.is_alphabetic() => {
-            if ch == 'i' {
-                new_state = DfaState::IdInt1;
-            } else {
-                new_state = DfaState::Id;
+    // 字符串或字符字面量内部的一个普通字符
+    fn string_char(&mut self, ch: char) -> DfaState {
+        match ch {
+            '"' if self.quote == '"' => {
+                self.token_text.push(ch);
+                self.token.token_type = Some(TokenType::StringLiteral);
+                self.token.value = Some(TokenValue::Str(self.literal_value.clone()));
+                DfaState::StringLiteral
+            }
+            '\'' if self.quote == '\'' => {
+                self.token_text.push(ch);
+                let mut chars = self.literal_value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(value), None) => {
+                        self.token.token_type = Some(TokenType::CharLiteral);
+                        self.token.value = Some(TokenValue::Char(value));
+                        DfaState::CharLiteral
+                    }
+                    // 字符字面量中必须正好有一个字符
+                    _ => {
+                        self.errors.push(LexError::new('\'', self.token.span.start, LexErrorReason::InvalidCharLiteral));
+                        self.token_text.clear();
+                        self.token = SimpleToken::new();
+                        DfaState::Initial
+                    }
+                }
+            }
+            '\\' => {
+                self.token_text.push(ch);
+                self.escape_start = self.pos;
+                DfaState::StringEscape
+            }
+            // 字符串和字符字面量都不能跨行
+            '\n' | '\r' => {
+                self.unterminated_string();
+                DfaState::Initial
+            }
+            _ => {
+                self.token_text.push(ch);
+                self.literal_value.push(ch);
+                DfaState::StringChars
             }
-            token.token_type = Some(TokenType::Identifier);
-        }
-        ch if ch.is_ascii_digit() => {
-            new_state = DfaState::Id;
-            token.token_type = Some(TokenType::IntLiteral);
-        }
-        '>' => {
-            new_state = DfaState::GT;
-            token.token_type = Some(TokenType::GT);
-        }
-        '+' => {
-            new_state = DfaState::Plus;
-            token.token_type = Some(TokenType::Plus);
-        }
-        '-' => {
-            new_state = DfaState::Minus;
-            token.token_type = Some(TokenType::Minus);
-        }
-        '*' => {
-            new_state = DfaState::Star;
-            token.token_type = Some(TokenType::Star);
-        }
-        '/' => {
-            new_state = DfaState::Slash;
-            token.token_type = Some(TokenType::Slash);
         }
-        ';' => {
-            new_state = DfaState::SemiColon;
-            token.token_type = Some(TokenType::SemiColon);
+    }
+
+    // 报告没有结束的字符串或字符字面量，并丢弃已经读到的部分
+    fn unterminated_string(&mut self) {
+        let reason = if self.quote == '"' { LexErrorReason::UnterminatedString } else { LexErrorReason::UnterminatedChar };
+        self.errors.push(LexError::new(self.quote, self.token.span.start, reason));
+        self.token_text.clear();
+        self.token = SimpleToken::new();
+    }
+
+    // 是否正处在两个 Token 之间：之前的 Token 都已经保存，下一个字符会开始一个新的 Token。
+    // 分号、括号、字符串这些读完就结束的 Token 在这里直接保存
+    pub(crate) fn at_boundary(&mut self) -> bool {
+        if let DfaState::SemiColon | DfaState::LeftParen | DfaState::RightParen | DfaState::LeftBrace | DfaState::RightBrace | DfaState::Colon
+            | DfaState::Comma | DfaState::StringLiteral | DfaState::CharLiteral | DfaState::BlockCommentEnd = self.state {
+            self.flush_token();
+            self.state = DfaState::Initial;
         }
-        '(' => {
-            new_state = DfaState::LeftParen;
-            token.token_type = Some(TokenType::LeftParen);
+        self.state == DfaState::Initial && self.token_text.is_empty()
+    }
+
+    // 当前 Token 到此结束，记下结束位置并保存
+    fn flush_token(&mut self) {
+        if !self.token_text.is_empty() && self.token.token_type.is_some() {
+            let text = std::mem::take(&mut self.token_text);
+            let token = std::mem::replace(&mut self.token, SimpleToken::new());
+            self.emit(token.token_type.unwrap(), &text, Span::new(token.span.start, self.pos), token.value);
+
+            // 留着 token_text 的内存给下一个 Token 用
+            self.token_text = text;
+            self.token_text.clear();
         }
-        ')' => {
-            new_state = DfaState::RightParen;
-            token.token_type = Some(TokenType::RightParen);
+    }
+
+    // 保存一个完整的 Token。标识符在这里查关键字表，这样 `ifx`、`int;`、脚本末尾的 `else` 都能和其他 Token 一样正确地结束。
+    pub(crate) fn emit(&mut self, mut token_type: TokenType, text: &str, span: Span, mut value: Option<TokenValue>) {
+        if token_type == TokenType::Identifier {
+            if let Some(keyword) = self.keywords.lookup(text) {
+                token_type = keyword;
+                value = self.keywords.lookup_value(text).cloned();
+            } else if let Some(warning) = self.identifiers.check(text, span.start) {
+                self.warnings.push(warning);
+            }
         }
-        '=' => {
-            new_state = DfaState::Assignment;
-            token.token_type = Some(TokenType::Assignment);
+
+        // 数字在这里校验格式、计算值，并确定是哪一种数字
+        if token_type == TokenType::IntLiteral {
+            match number::parse_number(text) {
+                Ok((number_type, number_value)) => {
+                    token_type = number_type;
+                    value = Some(number_value);
+                }
+                Err(reason) => {
+                    self.errors.push(LexError::with_text(text, span.start, reason));
+                    return;
+                }
+            }
         }
-        _ => {
-            new_state = DfaState::Initial;
-            token_text.pop();
+
+        self.sink.push(token_type, text, span, value);
+    }
+
+    // 保存上一个 Token，并以 ch 开始一个新的 Token，返回新的状态
+    fn init_token(&mut self, ch: char) -> DfaState {
+        self.flush_token();
+
+        self.token.span.start = self.pos;
+        self.token_text.push(ch);
+
+        match ch {
+            ch if identifier::is_id_start(ch) => {
+                self.token.token_type = Some(TokenType::Identifier);
+                DfaState::Id
+            }
+            // 各种数字先都按 IntLiteral 识别，结束时再确定具体的类型
+            '0' => {
+                self.token.token_type = Some(TokenType::IntLiteral);
+                DfaState::NumberZero
+            }
+            ch if ch.is_ascii_digit() => {
+                self.token.token_type = Some(TokenType::IntLiteral);
+                DfaState::IntLiteral
+            }
+            '.' => {
+                DfaState::Dot
+            }
+            '"' | '\'' => {
+                // 类型等读到结束的引号时才确定
+                self.literal_value.clear();
+                self.quote = ch;
+                DfaState::StringChars
+            }
+            '/' => {
+                self.token.token_type = Some(TokenType::Slash);
+                DfaState::Slash
+            }
+            ';' => {
+                self.token.token_type = Some(TokenType::SemiColon);
+                DfaState::SemiColon
+            }
+            '(' => {
+                self.token.token_type = Some(TokenType::LeftParen);
+                DfaState::LeftParen
+            }
+            ')' => {
+                self.token.token_type = Some(TokenType::RightParen);
+                DfaState::RightParen
+            }
+            '{' => {
+                self.token.token_type = Some(TokenType::LeftBrace);
+                DfaState::LeftBrace
+            }
+            '}' => {
+                self.token.token_type = Some(TokenType::RightBrace);
+                DfaState::RightBrace
+            }
+            ':' => {
+                self.token.token_type = Some(TokenType::Colon);
+                DfaState::Colon
+            }
+            ',' => {
+                self.token.token_type = Some(TokenType::Comma);
+                DfaState::Comma
+            }
+            _ if operator_type(&self.token_text).is_some() => {
+                self.token.token_type = operator_type(&self.token_text);
+                DfaState::Operator
+            }
+            ch if ch.is_whitespace() => {
+                self.token_text.pop();
+                DfaState::Initial
+            }
+            _ => {
+                // 报告错误之后跳过这个字符，从下一个字符开始继续识别
+                self.errors.push(LexError::new(ch, self.pos, LexErrorReason::UnexpectedChar));
+                self.token_text.pop();
+                DfaState::Initial
+            }
         }
     }
+}
+
 
+/*
+运算符表。运算符按最长匹配识别：读到的字符加上下一个字符还是表中的运算符，就继续读下去。
+表中每个运算符去掉最后一个字符之后也都是运算符，所以不需要回退。/ 开头的还可能是注释，单独处理。
+*/
+pub(crate) const OPERATORS: [(&str, TokenType); 35] = [
+    ("+", TokenType::Plus),
+    ("++", TokenType::Inc),
+    ("+=", TokenType::PlusAssign),
+    ("-", TokenType::Minus),
+    ("--", TokenType::Dec),
+    ("-=", TokenType::MinusAssign),
+    ("*", TokenType::Star),
+    ("*=", TokenType::StarAssign),
+    ("/", TokenType::Slash),
+    ("/=", TokenType::SlashAssign),
+    ("%", TokenType::Percent),
+    ("%=", TokenType::PercentAssign),
+    ("=", TokenType::Assignment),
+    ("==", TokenType::EQ),
+    ("!", TokenType::Not),
+    ("!=", TokenType::NE),
+    ("<", TokenType::LT),
+    ("<=", TokenType::LE),
+    ("<<", TokenType::LeftShift),
+    ("<<=", TokenType::LeftShiftAssign),
+    (">", TokenType::GT),
+    (">=", TokenType::GE),
+    (">>", TokenType::RightShift),
+    (">>=", TokenType::RightShiftAssign),
+    (">>>", TokenType::UnsignedRightShift),
+    (">>>=", TokenType::UnsignedRightShiftAssign),
+    ("&", TokenType::BitAnd),
+    ("&&", TokenType::And),
+    ("&=", TokenType::BitAndAssign),
+    ("|", TokenType::BitOr),
+    ("||", TokenType::Or),
+    ("|=", TokenType::BitOrAssign),
+    ("^", TokenType::BitXor),
+    ("^=", TokenType::BitXorAssign),
+    ("~", TokenType::BitNot),
+];
 
-    new_state
+// 运算符对应的 Token 类型，不是运算符时返回None
+pub(crate) fn operator_type(text: &str) -> Option<TokenType> {
+    OPERATORS.iter().find(|(op, _)| *op == text).map(|&(_, token_type)| token_type)
 }
 
 
@@ -247,6 +1040,8 @@ fn init_token(ch: char,
 pub struct SimpleToken {
     token_type: Option<TokenType>,
     text: String,
+    pub(crate) span: Span,
+    pub(crate) value: Option<TokenValue>,
 }
 
 impl SimpleToken {
@@ -254,6 +1049,8 @@ impl SimpleToken {
         SimpleToken {
             token_type: None,
             text: String::new(),
+            span: Span::default(),
+            value: None,
         }
     }
 
@@ -261,6 +1058,17 @@ impl SimpleToken {
         SimpleToken {
             token_type: Some(tt),
             text: txt,
+            span: Span::default(),
+            value: None,
+        }
+    }
+
+    pub fn with_span(tt: TokenType, txt: String, span: Span) -> Self {
+        SimpleToken {
+            token_type: Some(tt),
+            text: txt,
+            span,
+            value: None,
         }
     }
 }
@@ -275,6 +1083,14 @@ impl Token for SimpleToken {
     fn get_text(&self) -> &str {
         &self.text
     }
+
+    fn get_span(&self) -> Span {
+        self.span
+    }
+
+    fn get_value(&self) -> Option<&TokenValue> {
+        self.value.as_ref()
+    }
 }
 // ------------------------- SimpleToken -------------------------
 
@@ -283,36 +1099,60 @@ impl Token for SimpleToken {
 
 pub struct SimpleTokenReader {
     tokens: Vec<Box<dyn Token>>,
+    // 注释不参与语法分析，按出现的顺序单独保存
+    comments: Vec<Box<dyn Token>>,
+    warnings: Vec<LexWarning>,
     pos: usize,
 }
 
 impl SimpleTokenReader {
-    fn new(tokens: Vec<Box<dyn Token>>) -> SimpleTokenReader {
+    pub(crate) fn new(tokens: Vec<Box<dyn Token>>, comments: Vec<Box<dyn Token>>) -> SimpleTokenReader {
         SimpleTokenReader {
             tokens,
+            comments,
+            warnings: Vec::new(),
             pos: 0,
         }
     }
+
+    pub(crate) fn with_warnings(mut self, warnings: Vec<LexWarning>) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    // 词法警告，比如容易混淆的标识符
+    pub fn get_warnings(&self) -> &[LexWarning] {
+        &self.warnings
+    }
+
+    // 脚本中的所有注释
+    pub fn get_comments(&self) -> &[Box<dyn Token>] {
+        &self.comments
+    }
 }
 
 impl TokenReader for SimpleTokenReader {
-    fn read(&mut self) -> Option<&Box<dyn Token>> {
+    fn read(&mut self) -> Option<&dyn Token> {
         if self.pos < self.tokens.len() {
             self.pos += 1;
-            self.tokens.get(self.pos - 1)
+            self.tokens.get(self.pos - 1).map(|token| token.as_ref())
         } else {
             None
         }
     }
 
-    fn peek(&self) -> Option<&Box<dyn Token>> {
+    fn peek(&self) -> Option<&dyn Token> {
         if self.pos < self.tokens.len() {
-            self.tokens.get(self.pos)
+            self.tokens.get(self.pos).map(|token| token.as_ref())
         } else {
             None
         }
     }
 
+    fn peek_nth(&mut self, k: usize) -> Option<&dyn Token> {
+        self.tokens.get(self.pos + k).map(|token| token.as_ref())
+    }
+
     fn unread(&mut self) {
         if self.pos > 0 {
             self.pos -= 1;
@@ -323,10 +1163,13 @@ impl TokenReader for SimpleTokenReader {
         self.pos
     }
 
-    fn set_position(&mut self, position: usize) {
-        if position < self.tokens.len() {
-            self.pos = position;
+    fn set_position(&mut self, position: usize) -> bool {
+        if position > self.tokens.len() {
+            return false;
         }
+
+        self.pos = position;
+        true
     }
 }
 
@@ -1,15 +1,16 @@
-use std::cell::{Ref, RefCell, RefMut};
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::collections::HashSet;
 use std::io;
 use std::rc::Rc;
 use std::str::FromStr;
 
-use crate::lexer::{ASTNodeType, simple_lexer, TokenReader, TokenType};
+use crate::lexer::{ASTNode, ASTNodeType, identifier, Lexer, number, simple_lexer, Span, TokenReader, TokenType, TokenValue};
 use crate::simple_calculator;
 use crate::simple_calculator::SimpleASTNode;
 
 #[cfg(test)]
 mod tests {
-    use crate::lexer::{simple_lexer, TokenReader};
+    use crate::lexer::{ASTNode, ASTNodeType, simple_lexer, TokenReader};
     use crate::lexer::simple_lexer::SimpleLexer;
     use crate::lexer::simple_parser::SimpleParser;
 
@@ -50,106 +51,646 @@ mod tests {
             Err(e) => println!("parse failed : {}", e),
         }
     }
+
+    #[test]
+    pub fn test_error_position() {
+        let parser = SimpleParser::new();
+
+        let err = parser.parse("int a = 1;\nint b = 2 c;").err().unwrap();
+        assert_eq!(err.to_string(), "invalid statement, expecting semicolon at 2:11");
+
+        let err = parser.parse("int a = 1").err().unwrap();
+        assert_eq!(err.to_string(), "invalid statement, expecting semicolon at end of script");
+
+        // 数字字面量的错误报在字面量的开头
+        let err = parser.parse("int a = 2147483648;").err().unwrap();
+        assert_eq!(err.to_string(), "number literal out of range '2147483648' at 1:9");
+        let err = parser.parse("int a = 12abc;").err().unwrap();
+        assert_eq!(err.to_string(), "malformed number literal '12abc' at 1:9");
+        // 2147483648 只能紧跟在负号后面
+        let err = parser.parse("int a = -(2147483648);").err().unwrap();
+        assert_eq!(err.to_string(), "number literal out of range '2147483648' at 1:11");
+        let err = parser.parse("int a = -2147483648 - 1;\na = 1 + 1.5;").err().unwrap();
+        assert_eq!(err.to_string(), "floating-point literals are not supported at 2:9");
+    }
+
+    #[test]
+    pub fn test_if_else() {
+        let parser = SimpleParser::new();
+        let root = parser.parse("int a = 1; if (a >= 1 == true) a = 2; else if (a < 0) a = 3; else a = 4;").unwrap();
+        let stmt = root.get_children()[1].clone();
+        assert_eq!(stmt.get_type(), ASTNodeType::IfStmt);
+        assert_eq!(stmt.get_children().len(), 3);
+
+        // 条件：(a >= 1) == true
+        let condition = stmt.get_children()[0].clone();
+        assert_eq!((condition.get_type(), condition.get_text()), (ASTNodeType::Equality, "=="));
+        assert_eq!(condition.get_children()[0].get_type(), ASTNodeType::Relational);
+        assert_eq!(stmt.get_children()[2].get_type(), ASTNodeType::IfStmt);
+        assert_eq!(stmt.get_span().end.offset, 72);
+
+        // 悬空的 else 属于内层的 if
+        let root = parser.parse("if (a) if (b) x = 1; else x = 2;").unwrap();
+        let outer = root.get_children()[0].clone();
+        assert_eq!(outer.get_children().len(), 2);
+        assert_eq!(outer.get_children()[1].get_children().len(), 3);
+
+        let err = parser.parse("if a > 1) a = 2;").err().unwrap();
+        assert_eq!(err.to_string(), "invalid if statement, expecting left parenthesis at 1:4");
+        let err = parser.parse("if (a > 1) else a = 2;").err().unwrap();
+        assert_eq!(err.to_string(), "invalid if statement, expecting a statement at 1:12");
+        let err = parser.parse("if (a) a = 1; else").err().unwrap();
+        assert_eq!(err.to_string(), "invalid else branch, expecting a statement at end of script");
+        let err = parser.parse("else a = 1;").err().unwrap();
+        assert_eq!(err.to_string(), "unknown statement at 1:1");
+    }
+
+    #[test]
+    pub fn test_loops() {
+        let parser = SimpleParser::new();
+        let root = parser.parse("outer: while (i < 3) for (;;) if (i > 1) break outer; else continue;\nfor (int j = 0; j < 3; j = j + 1) ;").unwrap();
+        let stmt = root.get_children()[0].clone();
+        assert_eq!((stmt.get_type(), stmt.get_text()), (ASTNodeType::WhileStmt, "outer"));
+        assert_eq!(stmt.get_span().start.column, 1);
+
+        // 省略的部分都是空语句
+        let inner = stmt.get_children()[1].clone();
+        let types: Vec<_> = inner.get_children().iter().map(|child| child.get_type()).collect();
+        assert_eq!(types, vec![ASTNodeType::EmptyStmt, ASTNodeType::EmptyStmt, ASTNodeType::EmptyStmt, ASTNodeType::IfStmt]);
+        let jump = inner.get_children()[3].get_children()[1].clone();
+        assert_eq!((jump.get_type(), jump.get_text()), (ASTNodeType::BreakStmt, "outer"));
+
+        let stmt = root.get_children()[1].clone();
+        let types: Vec<_> = stmt.get_children().iter().map(|child| child.get_type()).collect();
+        assert_eq!(types, vec![ASTNodeType::IntDeclaration, ASTNodeType::Relational, ASTNodeType::AssignmentStmt, ASTNodeType::EmptyStmt]);
+        assert_eq!(stmt.get_text(), "");
+
+        for (code, message) in [
+            ("outer: a = 1;", "invalid labeled statement, expecting a loop at 1:8"),
+            ("while (a) break", "invalid statement, expecting semicolon at end of script"),
+            ("for (i = 0; i < 3) a = 1;", "invalid for statement, expecting semicolon at 1:18"),
+            ("for (i = 0; i < 3; i = i + 1 a = 1;", "expecting right parenthesis at 1:30"),
+            ("while a < 3) a = 1;", "invalid while statement, expecting left parenthesis at 1:7"),
+        ] {
+            assert_eq!(parser.parse(code).err().unwrap().to_string(), message, "parse {}", code);
+        }
+    }
+
+    #[test]
+    pub fn test_block() {
+        let parser = SimpleParser::new();
+        let root = parser.parse("{ int a = 1; { } if (a > 0) { a = 2; } else { } }\n{}").unwrap();
+        assert_eq!(root.get_children().len(), 2);
+        let block = root.get_children()[0].clone();
+        assert_eq!(block.get_type(), ASTNodeType::Block);
+        let types: Vec<_> = block.get_children().iter().map(|child| child.get_type()).collect();
+        assert_eq!(types, vec![ASTNodeType::IntDeclaration, ASTNodeType::Block, ASTNodeType::IfStmt]);
+        assert_eq!(block.get_span().end.offset, 49);
+        assert!(root.get_children()[1].get_children().is_empty());
+
+        let err = parser.parse("{ int a = 1;").err().unwrap();
+        assert_eq!(err.to_string(), "invalid block, expecting right brace at end of script");
+        let err = parser.parse("{ a = 1; )").err().unwrap();
+        assert_eq!(err.to_string(), "unknown statement at 1:10");
+        let err = parser.parse("a = 1; }").err().unwrap();
+        assert_eq!(err.to_string(), "unknown statement at 1:8");
+    }
+
+    #[test]
+    pub fn test_functions() {
+        let parser = SimpleParser::new();
+        let root = parser.parse("int add(int a, int b) { return a + b; }\nint f() { return; }\nint c = add(1, f());").unwrap();
+        let add = root.get_children()[0].clone();
+        assert_eq!((add.get_type(), add.get_text()), (ASTNodeType::FunctionDeclaration, "add"));
+        let types: Vec<_> = add.get_children().iter().map(|child| (child.get_type(), child.get_text().to_string())).collect();
+        assert_eq!(types, vec![(ASTNodeType::FormalParameter, "a".to_string()), (ASTNodeType::FormalParameter, "b".to_string()),
+                               (ASTNodeType::Block, String::new())]);
+        assert_eq!(add.get_span().end.offset, 39);
+
+        let ret = root.get_children()[1].get_children()[0].get_children()[0].clone();
+        assert_eq!(ret.get_type(), ASTNodeType::ReturnStmt);
+        assert!(ret.get_children().is_empty());
+
+        let call = root.get_children()[2].get_children()[0].clone();
+        assert_eq!((call.get_type(), call.get_text()), (ASTNodeType::FunctionCall, "add"));
+        assert_eq!(call.get_children().len(), 2);
+        assert_eq!(call.get_children()[1].get_type(), ASTNodeType::FunctionCall);
+        assert_eq!((call.get_span().start.column, call.get_span().end.column), (9, 20));
+
+        for (script, message) in [
+            ("int f(a) { }", "invalid function declaration, expecting a parameter at 1:7"),
+            ("int f(int) { }", "parameter name expected at 1:10"),
+            ("int f(int a b) { }", "expecting right parenthesis at 1:13"),
+            ("int f() return 1;", "invalid function declaration, expecting a body at 1:9"),
+            ("int f(int a, int b, int a) { }", "duplicate parameter a at 1:25"),
+            ("int f() { }\nint g() { }\nint f(int a) { }", "duplicate function f at 3:5"),
+            ("a = f(1,);", "invalid function call, expecting an argument at 1:9"),
+            ("a = f(1;", "expecting right parenthesis at 1:8"),
+            ("return 1", "invalid statement, expecting semicolon at end of script"),
+            ("{ int f() { } }", "invalid statement, expecting semicolon at 1:8"),
+        ] {
+            let err = parser.parse(script).err().unwrap();
+            assert_eq!(err.to_string(), message, "parse {}", script);
+        }
+    }
+
+    // 把表达式写成前缀形式，方便比较结构
+    fn sexp<T: ASTNode>(node: &T) -> String {
+        let children = node.get_children();
+        if children.is_empty() {
+            return node.get_text().to_string();
+        }
+        let children: Vec<_> = children.iter().map(|child| sexp(child.as_ref())).collect();
+        format!("({} {})", node.get_text(), children.join(" "))
+    }
+
+    #[test]
+    pub fn test_pratt() {
+        let parser = SimpleParser::new();
+        for (script, expected) in [
+            ("1 + 2 * 3 - 4;", "(- (+ 1 (* 2 3)) 4)"),
+            ("(1 + 2) * 3 % 4 / 5;", "(/ (% (* (+ 1 2) 3) 4) 5)"),
+            ("a = b = 1 + 2;", "(a (b (+ 1 2)))"),
+            ("a += b -= 2 * 3;", "(a (+ a (b (- b (* 2 3)))))"),
+            ("a >>>= 1 | 2;", "(a (>>> a (| 1 2)))"),
+            ("-a * b;", "(* (- a) b)"),
+            ("-a++ - --b;", "(- (- (++ a)) (-- b))"),
+            ("!a && b || c == 1 < 2;", "(|| (&& (! a) b) (== c (< 1 2)))"),
+            ("1 << 2 + 3 & 4 | 5 ^ ~6;", "(| (& (<< 1 (+ 2 3)) 4) (^ 5 (~ 6)))"),
+            ("f(x + 1, -y) * 2;", "(* (f (+ x 1) (- y)) 2)"),
+        ] {
+            let root = parser.parse(script).unwrap();
+            assert_eq!(sexp(root.get_children()[0].as_ref()), expected, "parse {}", script);
+        }
+
+        // + - * / 的节点和原来一样：文本是运算符，两个子节点，覆盖两个操作数
+        let root = parser.parse("a - b * 2;").unwrap();
+        let node = root.get_children()[0].clone();
+        assert_eq!((node.get_type(), node.get_text()), (ASTNodeType::Additive, "-"));
+        assert_eq!(node.get_children()[1].get_type(), ASTNodeType::Multiplicative);
+        assert_eq!((node.get_span().start.offset, node.get_span().end.offset), (0, 10));
+        assert_eq!(node.get_children()[1].get_span().start.offset, 4);
+        let root = parser.parse("(a + b) * 2;").unwrap();
+        let node = root.get_children()[0].get_children()[0].clone();
+        assert_eq!((node.get_span().start.offset, node.get_span().end.offset), (0, 7));
+
+        for (script, message) in [
+            ("a + ;", "invalid expression, expecting the right part of + at 1:5"),
+            ("1 + 2 = 3;", "invalid assignment, expecting a variable on the left at 1:7"),
+            ("a + b *= 3;", "invalid assignment, expecting a variable on the left at 1:7"),
+            ("(a) = 2;", "invalid assignment, expecting a variable on the left at 1:5"),
+            ("(a)++;", "invalid operand of ++, expecting a variable at 1:4"),
+            ("--(a);", "invalid operand of --, expecting a variable at 1:1"),
+            ("-;", "invalid expression, expecting an operand of - at 1:2"),
+            ("++1;", "invalid operand of ++, expecting a variable at 1:1"),
+            ("f(x)++;", "invalid operand of ++, expecting a variable at 1:5"),
+            ("a = 1", "invalid statement, expecting semicolon at end of script"),
+            ("f(1) + 2 3;", "invalid statement, expecting semicolon at 1:10"),
+        ] {
+            let err = parser.parse(script).err().unwrap();
+            assert_eq!(err.to_string(), message, "parse {}", script);
+        }
+
+        // 嵌套太深时报错，而不是栈溢出；出错之后层数恢复，同一个解析器还能继续用
+        let err = parser.parse(&format!("a = {}1{};", "(".repeat(20_000), ")".repeat(20_000))).err().unwrap();
+        assert_eq!(err.to_string(), "code is nested too deeply at 1:131");
+        let err = parser.parse(&format!("{}{}", "{".repeat(20_000), "}".repeat(20_000))).err().unwrap();
+        assert_eq!(err.to_string(), "code is nested too deeply at 1:65");
+        let root = parser.parse(&format!("a = {}1{};", "(".repeat(100), ")".repeat(100))).unwrap();
+        assert_eq!(root.get_children().len(), 1);
+    }
+
+    #[test]
+    pub fn test_parse_reader() {
+        let parser = SimpleParser::new();
+        let root = parser.parse_reader("int age = 45+2; age= 20; age+10*2;".as_bytes()).unwrap();
+        assert_eq!(root.get_children().len(), 3);
+
+        let err = parser.parse_reader("int a = 1 $ 2;".as_bytes()).err().unwrap();
+        assert_eq!(err.to_string(), "unexpected character '$' at 1:11");
+
+        // 很长的语句不需要回溯，和 parse 报告同样的错误
+        let script = format!("a = {}1 )", "1 + ".repeat(300));
+        let err = parser.parse_reader(script.as_bytes()).err().unwrap();
+        assert_eq!(err.to_string(), "invalid statement, expecting semicolon at 1:1207");
+        assert_eq!(parser.parse(&script).err().unwrap().to_string(), err.to_string());
+    }
 }
 
 
-pub struct SimpleParser {}
+// 语句、块和表达式最多嵌套多少层，超过时报错，而不是让解析器的递归把栈用完
+const MAX_NESTING: usize = 128;
+
+pub struct SimpleParser {
+    // 当前所在的语句、块和表达式的嵌套层数
+    depth: Cell<usize>,
+}
+
+// 离开一层嵌套时把层数减回去，出错提前返回时也一样
+struct Nesting<'a> {
+    depth: &'a Cell<usize>,
+}
+
+impl Drop for Nesting<'_> {
+    fn drop(&mut self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+}
 
 impl SimpleParser {
     pub fn new() -> SimpleParser {
-        SimpleParser {}
+        SimpleParser { depth: Cell::new(0) }
+    }
+
+    // 进入一层嵌套，在返回的 Nesting 被丢掉之前都算在这一层里
+    fn enter<T: TokenReader>(&self, tokens: &T) -> Result<Nesting<'_>, io::Error> {
+        if self.depth.get() >= MAX_NESTING {
+            return Err(simple_calculator::error_at(tokens, "code is nested too deeply"));
+        }
+        self.depth.set(self.depth.get() + 1);
+        Ok(Nesting { depth: &self.depth })
     }
 
 
     // 解析脚本，并返回根节点
     pub fn parse(&self, code: &str) -> Result<SimpleASTNode, io::Error> {
-        let lexer = simple_lexer::SimpleLexer::new();
-        let mut tokens = lexer.tokenize(code);
-        self.get_root(&mut tokens)
+        self.parse_with(&simple_lexer::SimpleLexer::new(), code)
     }
 
-    // 语法解析：根节点
-    fn get_root<T: TokenReader>(&self, tokens: &mut T) -> Result<SimpleASTNode, io::Error> {
-        let node = SimpleASTNode::new(ASTNodeType::Program, "SimpleParser");
+    // 用指定的词法分析器解析脚本
+    pub fn parse_with(&self, lexer: &dyn Lexer, code: &str) -> Result<SimpleASTNode, io::Error> {
+        let mut tokens = lexer.tokenize(code)?;
+        self.get_root(&mut tokens)
+    }
 
+    // 从 reader 中边读边解析脚本，不需要先把整个脚本读进内存
+    pub fn parse_reader<R: io::Read>(&self, reader: R) -> Result<SimpleASTNode, io::Error> {
+        let mut tokens = simple_lexer::SimpleLexer::new().tokenize_stream(reader);
+        let root = self.get_root(&mut tokens);
 
-        while !tokens.peek().is_none() {
-            // 先看下，是不是 int 变量声明 e.g. int a = 1;
-            let mut child = self.int_declare(tokens).expect("get int declare statement failed"); // 整形字面量 node
+        // 词法错误和读取错误往往是语法错误的原因，优先报告
+        if let Some(err) = tokens.take_io_error() {
+            return Err(err);
+        }
+        if let Some(err) = tokens.get_errors().first() {
+            return Err(err.clone().into());
+        }
+        // 回溯失败之后的解析结果是不可靠的
+        if let Some(err) = tokens.take_replay_error() {
+            return Err(err);
+        }
+        root
+    }
 
-            if child.is_none() {// 不是 int 变量，看下是不是 普通的表达式。
-                child = self.expression_statement(tokens).expect("get expression statement failed");
-            }
+    // 语法解析：根节点
+    fn get_root<T: TokenReader>(&self, tokens: &mut T) -> Result<SimpleASTNode, io::Error> {
+        let mut node = SimpleASTNode::new(ASTNodeType::Program, "SimpleParser", Span::default());
+        // 已经声明过的函数名
+        let mut functions = HashSet::new();
 
-            if child.is_none() { // 不是表达式，看下是不是赋值语句 e.g.  a = 100;
-                child = self.assignment_statement(tokens).expect("get assignment statement failed");
+        while tokens.peek().is_some() {
+            // 函数只能在脚本的最外层声明
+            let mut child = self.function_declaration(tokens, &mut functions)?;
+            if child.is_none() {
+                child = self.statement(tokens)?;
             }
-
             if child.is_none() {
-                return Err(simple_calculator::invalid_input_err("unknown statement"));
+                return Err(simple_calculator::error_at(tokens, "unknown statement"));
             }
 
+            // 程序节点覆盖第一条到最后一条语句
             let child = child.unwrap();
+            node.span = if node.span == Span::default() { child.span } else { node.span.to(child.span) };
             node.add_child(RefCell::new(Rc::new(child)))
         }
 
         Ok(node)
     }
 
-    // 表达式语句，即表达式后面跟个分号。
-    fn expression_statement<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
-        let pos = tokens.get_position();
-        let mut node = self.additive(tokens)?;
-        if node.is_none() {
-            return Ok(node);
+    // 语句，不是任何一种语句时返回None
+    fn statement<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
+        let _nesting = self.enter(tokens)?;
+        // 先看下，是不是 int 变量声明 e.g. int a = 1;
+        let mut child = self.int_declare(tokens)?; // 整形字面量 node
+
+        if child.is_none() { // 块语句 { ... }
+            child = self.block(tokens)?;
         }
 
-        let node = node.unwrap();
-        let token = tokens.peek();
-        if !token.is_none() && token.unwrap().get_type() == TokenType::SemiColon {
-            let _ = tokens.read(); // 消耗分号
-            return Ok(Some(node));
+        if child.is_none() { // 看下是不是 if 语句
+            child = self.if_statement(tokens)?;
         }
 
+        if child.is_none() { // 循环，以及带标签的循环
+            child = self.loop_statement(tokens, "")?;
+        }
 
-        tokens.set_position(pos); // 回溯
-        return Ok(None);
+        if child.is_none() {
+            child = self.labeled_statement(tokens)?;
+        }
+
+        if child.is_none() { // break、continue
+            child = self.jump_statement(tokens)?;
+        }
+
+        if child.is_none() {
+            child = self.return_statement(tokens)?;
+        }
+
+        if child.is_none() { // 只有一个分号的空语句
+            child = self.empty_statement(tokens);
+        }
+
+        if child.is_none() {// 不是 int 变量，看下是不是 普通的表达式，包括赋值 e.g.  a = 100;
+            child = self.expression_statement(tokens)?;
+        }
+
+        Ok(child)
     }
 
-    // 赋值语句，如age = 10*2;
-    fn assignment_statement<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
+    /*
+    函数声明：int Identifier ( (int Identifier (, int Identifier)*)? ) block
+    和变量声明一样以 int 开头，要看到名字后面的左括号才知道是函数。函数名和参数名都不能重复，functions 是已经声明过的函数名。
+    */
+    fn function_declaration<T: TokenReader>(&self, tokens: &mut T, functions: &mut HashSet<String>) -> Result<Option<SimpleASTNode>, io::Error> {
+        let types: Vec<_> = (0..3).map(|n| tokens.peek_nth(n).map(|token| token.get_type())).collect();
+        if types != [Some(TokenType::Int), Some(TokenType::Identifier), Some(TokenType::LeftParen)] {
+            return Ok(None);
+        }
+
+        let start = tokens.read().unwrap().get_span(); // 消耗掉 int
+        let token = tokens.read().unwrap();
+        let name = token.get_text().to_string();
+        if !functions.insert(identifier::normalize(&name)) {
+            let msg = format!("duplicate function {}", name);
+            return Err(simple_calculator::invalid_input_err_at(msg.as_str(), token.get_span().start));
+        }
+        let _ = tokens.read(); // 消耗掉 (
+        let mut node = SimpleASTNode::new(ASTNodeType::FunctionDeclaration, &name, start);
+        let mut parameters = HashSet::new();
+
+        if tokens.expect(TokenType::RightParen).is_err() {
+            loop {
+                if tokens.expect(TokenType::Int).is_err() {
+                    return Err(simple_calculator::error_at(tokens, "invalid function declaration, expecting a parameter"));
+                }
+                let token = match tokens.expect(TokenType::Identifier) {
+                    Ok(token) => token,
+                    Err(_) => return Err(simple_calculator::error_at(tokens, "parameter name expected")),
+                };
+                if !parameters.insert(identifier::normalize(token.get_text())) {
+                    let msg = format!("duplicate parameter {}", token.get_text());
+                    return Err(simple_calculator::invalid_input_err_at(msg.as_str(), token.get_span().start));
+                }
+                node.add_child(RefCell::new(Rc::new(SimpleASTNode::new(ASTNodeType::FormalParameter, token.get_text(), token.get_span()))));
+
+                if tokens.expect(TokenType::Comma).is_err() {
+                    break;
+                }
+            }
+            if tokens.expect(TokenType::RightParen).is_err() {
+                return Err(simple_calculator::error_at(tokens, "expecting right parenthesis"));
+            }
+        }
+
+        let e = simple_calculator::error_at(tokens, "invalid function declaration, expecting a body");
+        let body = self.block(tokens)?.ok_or(e)?;
+        node.span = start.to(body.span);
+        node.add_child(RefCell::new(Rc::new(body)));
+
+        Ok(Some(node))
+    }
+
+    // 块语句：{ statement* }，对应 PlayScript.g4 中的 compoundStatement
+    fn block<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
+        let _nesting = self.enter(tokens)?;
+        let start = match tokens.expect(TokenType::LeftBrace) {
+            Ok(token) => token.get_span(),
+            Err(_) => return Ok(None),
+        };
+        let mut node = SimpleASTNode::new(ASTNodeType::Block, "", start);
+
+        loop {
+            if let Ok(token) = tokens.expect(TokenType::RightBrace) {
+                node.span = start.to(token.get_span());
+                return Ok(Some(node));
+            }
+            if tokens.peek().is_none() {
+                return Err(simple_calculator::error_at(tokens, "invalid block, expecting right brace"));
+            }
+
+            let e = simple_calculator::error_at(tokens, "unknown statement");
+            let child = self.statement(tokens)?.ok_or(e)?;
+            node.add_child(RefCell::new(Rc::new(child)));
+        }
+    }
+
+    /*
+    if 语句：if (expression) statement (else statement)?
+    else 和最近的还没有 else 的 if 配对：内层的 if 解析完 then 分支之后，会先看到 else 并把它拿走，
+    所以 if (a) if (b) x = 1; else x = 2; 中的 else 属于 if (b)。
+    */
+    fn if_statement<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
         let token = tokens.peek();
-        if token.is_none() || token.unwrap().get_type() != TokenType::Identifier { // 标识符
+        if token.is_none() || token.unwrap().get_type() != TokenType::If {
             return Ok(None);
         }
 
-        // token.type = TokenType::Identifier
+        let start = tokens.read().unwrap().get_span(); // 消耗掉 if
+        let mut node = SimpleASTNode::new(ASTNodeType::IfStmt, "if", start);
+
+        if tokens.expect(TokenType::LeftParen).is_err() {
+            return Err(simple_calculator::error_at(tokens, "invalid if statement, expecting left parenthesis"));
+        }
+        let e = simple_calculator::error_at(tokens, "invalid if statement, expecting a condition");
+        let condition = self.expression(tokens)?.ok_or(e)?;
+        if tokens.expect(TokenType::RightParen).is_err() {
+            return Err(simple_calculator::error_at(tokens, "expecting right parenthesis"));
+        }
 
-        let token = tokens.read().unwrap(); // 消耗标识符
-        let mut node = SimpleASTNode::new(ASTNodeType::AssignmentStmt, token.get_text());
+        let e = simple_calculator::error_at(tokens, "invalid if statement, expecting a statement");
+        let then_branch = self.statement(tokens)?.ok_or(e)?;
+        node.span = start.to(then_branch.span);
+        node.add_child(RefCell::new(Rc::new(condition)));
+        node.add_child(RefCell::new(Rc::new(then_branch)));
 
         let token = tokens.peek();
-        if !token.is_none() && token.unwrap().get_type() == TokenType::Assignment { // =
-            let _ = tokens.read(); // 消耗 =
-            let child = self.additive(tokens)?;
-            if child.is_none() {
-                return Err(simple_calculator::invalid_input_err("invalid assignment statement, expecting an expression"));
-            }
+        if token.is_some() && token.unwrap().get_type() == TokenType::Else {
+            let _ = tokens.read(); // 消耗掉 else
+            let e = simple_calculator::error_at(tokens, "invalid else branch, expecting a statement");
+            let else_branch = self.statement(tokens)?.ok_or(e)?;
+            node.span = start.to(else_branch.span);
+            node.add_child(RefCell::new(Rc::new(else_branch)));
+        }
 
-            let child = child.unwrap();
+        Ok(Some(node))
+    }
+
+    // while 或者 for 循环，label 是循环的标签
+    fn loop_statement<T: TokenReader>(&self, tokens: &mut T, label: &str) -> Result<Option<SimpleASTNode>, io::Error> {
+        match tokens.peek().map(|token| token.get_type()) {
+            Some(TokenType::While) => self.while_statement(tokens, label).map(Some),
+            Some(TokenType::For) => self.for_statement(tokens, label).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    // while 语句：while (expression) statement
+    fn while_statement<T: TokenReader>(&self, tokens: &mut T, label: &str) -> Result<SimpleASTNode, io::Error> {
+        let start = tokens.read().unwrap().get_span(); // 消耗掉 while
+        let mut node = SimpleASTNode::new(ASTNodeType::WhileStmt, label, start);
+
+        if tokens.expect(TokenType::LeftParen).is_err() {
+            return Err(simple_calculator::error_at(tokens, "invalid while statement, expecting left parenthesis"));
+        }
+        let e = simple_calculator::error_at(tokens, "invalid while statement, expecting a condition");
+        let condition = self.expression(tokens)?.ok_or(e)?;
+        if tokens.expect(TokenType::RightParen).is_err() {
+            return Err(simple_calculator::error_at(tokens, "expecting right parenthesis"));
+        }
+
+        let e = simple_calculator::error_at(tokens, "invalid while statement, expecting a statement");
+        let body = self.statement(tokens)?.ok_or(e)?;
+        node.span = start.to(body.span);
+        node.add_child(RefCell::new(Rc::new(condition)));
+        node.add_child(RefCell::new(Rc::new(body)));
+
+        Ok(node)
+    }
+
+    /*
+    for 语句：for (init; condition; step) statement
+    init 是变量声明或者表达式语句，自己带着分号；step 是不带分号的表达式。赋值也是表达式。
+    三个部分都可以省略，省略的部分用空语句占位，省略条件表示一直循环。
+    */
+    fn for_statement<T: TokenReader>(&self, tokens: &mut T, label: &str) -> Result<SimpleASTNode, io::Error> {
+        let start = tokens.read().unwrap().get_span(); // 消耗掉 for
+        let mut node = SimpleASTNode::new(ASTNodeType::ForStmt, label, start);
+
+        if tokens.expect(TokenType::LeftParen).is_err() {
+            return Err(simple_calculator::error_at(tokens, "invalid for statement, expecting left parenthesis"));
+        }
+
+        let mut init = self.empty_statement(tokens);
+        if init.is_none() {
+            init = self.int_declare(tokens)?;
+        }
+        if init.is_none() {
+            init = self.expression_statement(tokens)?;
+        }
+        let e = simple_calculator::error_at(tokens, "invalid for statement, expecting an initializer");
+        let init = init.ok_or(e)?;
+
+        let condition = match self.expression(tokens)? {
+            Some(condition) => condition,
+            None => self.empty_node(tokens),
+        };
+        if tokens.expect(TokenType::SemiColon).is_err() {
+            return Err(simple_calculator::error_at(tokens, "invalid for statement, expecting semicolon"));
+        }
+
+        let step = match self.expression(tokens)? {
+            Some(step) => step,
+            None => self.empty_node(tokens),
+        };
+        if tokens.expect(TokenType::RightParen).is_err() {
+            return Err(simple_calculator::error_at(tokens, "expecting right parenthesis"));
+        }
+
+        let e = simple_calculator::error_at(tokens, "invalid for statement, expecting a statement");
+        let body = self.statement(tokens)?.ok_or(e)?;
+        node.span = start.to(body.span);
+        for child in [init, condition, step, body] {
             node.add_child(RefCell::new(Rc::new(child)));
+        }
 
-            let token = tokens.peek();
-            if token.is_none() || token.unwrap().get_type() != TokenType::SemiColon {
-                return Err(simple_calculator::invalid_input_err("invalid statement, expecting semicolon"));
-            }
+        Ok(node)
+    }
+
+    // 带标签的循环，如 outer: while (a > 0) ...，标签记在循环节点的文本中
+    fn labeled_statement<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
+        let first = tokens.peek_nth(0).map(|token| token.get_type());
+        let second = tokens.peek_nth(1).map(|token| token.get_type());
+        if first != Some(TokenType::Identifier) || second != Some(TokenType::Colon) {
+            return Ok(None);
+        }
+
+        let token = tokens.read().unwrap(); // 消耗掉标签
+        let (label, start) = (token.get_text().to_string(), token.get_span());
+        let _ = tokens.read(); // 消耗掉 :
+
+        let e = simple_calculator::error_at(tokens, "invalid labeled statement, expecting a loop");
+        let mut node = self.loop_statement(tokens, &label)?.ok_or(e)?;
+        node.span = start.to(node.span);
+        Ok(Some(node))
+    }
+
+    // break 和 continue，后面可以跟着循环的标签，如 break outer;
+    fn jump_statement<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
+        let node_type = match tokens.peek().map(|token| token.get_type()) {
+            Some(TokenType::Break) => ASTNodeType::BreakStmt,
+            Some(TokenType::Continue) => ASTNodeType::ContinueStmt,
+            _ => return Ok(None),
+        };
+
+        let start = tokens.read().unwrap().get_span(); // 消耗掉 break 或者 continue
+        let label = match tokens.peek() {
+            Some(token) if token.get_type() == TokenType::Identifier => tokens.read().unwrap().get_text().to_string(),
+            _ => String::new(),
+        };
+
+        let end = match tokens.expect(TokenType::SemiColon) {
+            Ok(token) => token.get_span(),
+            Err(_) => return Err(simple_calculator::error_at(tokens, "invalid statement, expecting semicolon")),
+        };
+        Ok(Some(SimpleASTNode::new(node_type, &label, start.to(end))))
+    }
 
-            let _ = tokens.read(); // 消耗;
-            return Ok(Some(node));
+    // return 语句：return expression? ;
+    fn return_statement<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
+        let start = match tokens.expect(TokenType::Return) {
+            Ok(token) => token.get_span(),
+            Err(_) => return Ok(None),
+        };
+        let mut node = SimpleASTNode::new(ASTNodeType::ReturnStmt, "return", start);
+
+        if let Some(child) = self.expression(tokens)? {
+            node.add_child(RefCell::new(Rc::new(child)));
         }
 
-        tokens.unread();// 回溯
-        return Ok(None);
+        let end = match tokens.expect(TokenType::SemiColon) {
+            Ok(token) => token.get_span(),
+            Err(_) => return Err(simple_calculator::error_at(tokens, "invalid statement, expecting semicolon")),
+        };
+        node.span = start.to(end);
+        Ok(Some(node))
+    }
+
+    // 空语句 ;
+    fn empty_statement<T: TokenReader>(&self, tokens: &mut T) -> Option<SimpleASTNode> {
+        let token = tokens.expect(TokenType::SemiColon).ok()?;
+        Some(SimpleASTNode::new(ASTNodeType::EmptyStmt, "", token.get_span()))
+    }
+
+    // for 循环中省略的部分，位置在下一个 Token 的开头
+    fn empty_node<T: TokenReader>(&self, tokens: &T) -> SimpleASTNode {
+        let start = tokens.peek().map(|token| token.get_span().start).unwrap_or_default();
+        SimpleASTNode::new(ASTNodeType::EmptyStmt, "", Span::new(start, start))
+    }
+
+    // 表达式语句，即表达式后面跟个分号。赋值也是表达式，a = 10*2; 同样是表达式语句
+    fn expression_statement<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
+        let node = self.expression(tokens)?;
+        if node.is_none() {
+            return Ok(node);
+        }
+
+        let mut node = node.unwrap();
+        match tokens.expect(TokenType::SemiColon) {
+            Ok(token) => node.span = node.span.to(token.get_span()), // 消耗分号
+            Err(_) => return Err(simple_calculator::error_at(tokens, "invalid statement, expecting semicolon")),
+        }
+        Ok(Some(node))
     }
 
 
@@ -164,107 +705,113 @@ impl SimpleParser {
 
         //  token.Type = TokenType::Int
 
-        let _ = tokens.read(); // 消耗掉int
-        let e = io::Error::new(io::ErrorKind::InvalidInput, "variable name expected");
-        let token = tokens.peek().ok_or(e)?;
-
-        if token.get_type() != TokenType::Identifier { // 标识符
-            return Err(simple_calculator::invalid_input_err("variable name expected"));
+        let start = tokens.read().unwrap().get_span(); // 消耗掉int
+        let token = tokens.peek();
+        if token.is_none() || token.unwrap().get_type() != TokenType::Identifier { // 标识符
+            return Err(simple_calculator::error_at(tokens, "variable name expected"));
         }
 
         // token.Type = TokenType::Identifier
 
         let token = tokens.read().unwrap(); // 消耗掉 Identifier
-        let mut node = SimpleASTNode::new(ASTNodeType::IntDeclaration, token.get_text());
+        let mut node = SimpleASTNode::new(ASTNodeType::IntDeclaration, token.get_text(), start);
 
         let token = tokens.peek();
-        if !token.is_none() && token.unwrap().get_type() == TokenType::Assignment {
+        if token.is_some() && token.unwrap().get_type() == TokenType::Assignment {
             let _ = tokens.read(); // 消耗掉 =
 
-            let e = simple_calculator::invalid_input_err("invalid variable initialization, expecting an expression");
-            let child = self.additive(tokens)?.ok_or(e)?;
+            let e = simple_calculator::error_at(tokens, "invalid variable initialization, expecting an expression");
+            let child = self.expression(tokens)?.ok_or(e)?;
             node.add_child(RefCell::new(Rc::new(child)));
         }
 
         let token = tokens.peek();
         if token.is_none() || token.unwrap().get_type() != TokenType::SemiColon {
-            return Err(simple_calculator::invalid_input_err("invalid statement, expecting semicolon"));
+            return Err(simple_calculator::error_at(tokens, "invalid statement, expecting semicolon"));
         }
 
-        let _ = tokens.read(); // 消耗掉 ;
+        let end = tokens.read().unwrap().get_span(); // 消耗掉 ;
+        node.span = start.to(end);
 
         Ok(Some(node))
     }
 
 
-    // 加法表达式 add -> mul add' add' -> + mul add' | ε
-    // add -> mul (+ mul)*
-    fn additive<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
-        let mut child1 = self.multiplicative(tokens)?;
-        if child1.is_none() {
-            return Ok(None);
-        }
-
-        let mut child1 = child1.unwrap();
-
-        loop {
-            let token = tokens.peek();
-            // if !token.is_none() {
-            //     let token_text = token.unwrap().get_text();
-            //     println!("additive - loop - token_text : {}", token_text);
-            // }
-
-
-            if token.is_none() || (token.unwrap().get_type() != TokenType::Plus &&
-                token.unwrap().get_type() != TokenType::Minus) {
-                break;
-            }
-
-            // token => + 或者 -
-            let token_text = tokens.read().unwrap().get_text();
-            let node = SimpleASTNode::new(ASTNodeType::Additive, token_text);
-            let e = simple_calculator::invalid_input_err("invalid additive expression, expecting the right part.");
-            let child2 = self.multiplicative(tokens)?.ok_or(e)?;
-
-            node.add_child(RefCell::new(Rc::new(child1)));
-            node.add_child(RefCell::new(Rc::new(child2)));
-
-            child1 = node;
-        }
-
-        Ok(Some(child1))
+    // 表达式，从优先级最低的赋值开始
+    fn expression<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
+        self.pratt(tokens, 0)
     }
 
-
-    // 语法解析：乘法表达式 mul -> pri | mul * pri | mul / pri
-    fn multiplicative<T: TokenReader>(&self, tokens: &mut T) -> Result<Option<SimpleASTNode>, io::Error> {
-        let mut child1 = self.primary(tokens)?;
-        if child1.is_none() {
-            return Ok(None);
-        }
-
-        let mut child1 = child1.unwrap();
-
-        loop {
-            let token = tokens.peek();
-            if token.is_none() || (token.unwrap().get_type() != TokenType::Star &&
-                token.unwrap().get_type() != TokenType::Slash) {
-                break;
+    /*
+    Pratt 解析：先解析前缀运算或者基础表达式作为左边的部分，再不断读后面的后缀、中缀运算符，
+    直到遇到优先级低于 min 的运算符为止。左结合的运算符右边只接受优先级更高的运算，
+    所以 a - b - c 是 (a - b) - c；右结合的运算符右边接受同一优先级的运算，a = b = 1 是 a = (b = 1)。
+    */
+    fn pratt<T: TokenReader>(&self, tokens: &mut T, min: u8) -> Result<Option<SimpleASTNode>, io::Error> {
+        let _nesting = self.enter(tokens)?;
+        let token_type = tokens.peek().map(|token| token.get_type());
+        let mut left = match token_type.and_then(|token_type| find_operator(token_type, Fixity::Prefix)) {
+            Some(operator) => {
+                let token = tokens.read().unwrap(); // 消耗掉前缀运算符
+                let (text, start) = (token.get_text().to_string(), token.get_span());
+                match negative_literal(tokens, &text, start) {
+                    Some(node) => node,
+                    None => {
+                        let msg = format!("invalid expression, expecting an operand of {}", text);
+                        let e = simple_calculator::error_at(tokens, &msg);
+                        let operand = self.pratt(tokens, operator.precedence)?.ok_or(e)?;
+                        check_operand(&text, start, &operand)?;
+                        unary_node(operator.node_type.clone(), &text, start.to(operand.span), operand)
+                    }
+                }
+            }
+            None => match self.primary(tokens)? {
+                Some(node) => node,
+                None => return Ok(None),
+            },
+        };
+
+        while let Some(token_type) = tokens.peek().map(|token| token.get_type()) {
+            if let Some(operator) = find_operator(token_type, Fixity::Postfix) {
+                if operator.precedence < min {
+                    break;
+                }
+                let token = tokens.read().unwrap(); // 消耗掉后缀运算符
+                check_operand(token.get_text(), token.get_span(), &left)?;
+                let span = left.span.to(token.get_span());
+                left = unary_node(operator.node_type.clone(), token.get_text(), span, left);
+                continue;
             }
 
-            // token => / 或者 *
-            let token_text = tokens.read().unwrap().get_text();
-            let node = SimpleASTNode::new(ASTNodeType::Multiplicative, token_text);
-            let e = simple_calculator::invalid_input_err("invalid additive expression, expecting the right part.");
-            let child2 = self.primary(tokens)?.ok_or(e)?;
-
-            node.add_child(RefCell::new(Rc::new(child1)));
-            node.add_child(RefCell::new(Rc::new(child2)));
-
-            child1 = node;
+            let operator = match find_operator(token_type, Fixity::Infix).or_else(|| find_operator(token_type, Fixity::RightInfix)) {
+                Some(operator) if operator.precedence >= min => operator,
+                _ => break,
+            };
+            let token = tokens.read().unwrap(); // 消耗掉中缀运算符
+            let (text, span) = (token.get_text().to_string(), token.get_span());
+            let next = if operator.fixity == Fixity::RightInfix { operator.precedence } else { operator.precedence + 1 };
+            let msg = format!("invalid expression, expecting the right part of {}", text);
+            let e = simple_calculator::error_at(tokens, &msg);
+            let right = self.pratt(tokens, next)?.ok_or(e)?;
+
+            left = if operator.node_type == ASTNodeType::AssignmentStmt {
+                // 赋值的左边必须是变量，节点和赋值语句一样：文本是变量名，子节点是右边的值
+                if !is_variable(&left) {
+                    return Err(simple_calculator::invalid_input_err_at("invalid assignment, expecting a variable on the left", span.start));
+                }
+                let (name, span) = (left.get_text().to_string(), left.span.to(right.span));
+                // 复合赋值 a += b 展开成 a = a + b
+                let value = match compound_operator(token_type).and_then(|binary| find_operator(binary, Fixity::Infix)) {
+                    Some(binary) => binary_node(binary.node_type.clone(), &text[..text.len() - 1], left, right),
+                    None => right,
+                };
+                unary_node(ASTNodeType::AssignmentStmt, &name, span, value)
+            } else {
+                binary_node(operator.node_type.clone(), &text, left, right)
+            };
         }
 
-        Ok(Some(child1))
+        Ok(Some(left))
     }
 
     /// 语法解析：基础表达式, pri -> Id | Literal | (exp)
@@ -278,34 +825,209 @@ impl SimpleParser {
         let txt = token.get_text();
         // println!("primary token {}", txt);
         match token.get_type() {
-            TokenType::IntLiteral => { // 整型字面量
+            TokenType::IntLiteral | TokenType::HexLiteral | TokenType::BinaryLiteral | TokenType::OctalLiteral => { // 整型字面量
+                simple_calculator::int_literal(tokens).map(Some)
+            }
+            TokenType::FloatLiteral => Err(simple_calculator::error_at(tokens, "floating-point literals are not supported")),
+            TokenType::BooleanLiteral => { // true、false，别名也统一成 true、false
+                let token = tokens.read().unwrap();
+                let text = if token.get_value() == Some(&TokenValue::Bool(true)) { "true" } else { "false" };
+                Ok(Some(SimpleASTNode::new(ASTNodeType::BooleanLiteral, text, token.get_span())))
+            }
+            TokenType::CharLiteral => { // 字符字面量，节点的文本是处理完转义之后的字符
                 let token = tokens.read().unwrap();
-                Ok(Some(SimpleASTNode::new(ASTNodeType::IntLiteral, token.get_text())))
+                let text = match token.get_value() {
+                    Some(TokenValue::Char(ch)) => ch.to_string(),
+                    _ => token.get_text().to_string(),
+                };
+                Ok(Some(SimpleASTNode::new(ASTNodeType::CharLiteral, &text, token.get_span())))
             }
-            TokenType::Identifier => { // 变量名
+            TokenType::NullLiteral => {
                 let token = tokens.read().unwrap();
-                Ok(Some(SimpleASTNode::new(ASTNodeType::Identifier, token.get_text())))
+                Ok(Some(SimpleASTNode::new(ASTNodeType::NullLiteral, "null", token.get_span())))
             }
-            TokenType::LeftParen => { // (
-                let _ = tokens.read().unwrap(); // 消耗掉 (
+            TokenType::Identifier => { // 变量名，后面跟着左括号时是函数调用
+                let token = tokens.read().unwrap();
+                let (name, start) = (token.get_text().to_string(), token.get_span());
+                if tokens.peek().map(|token| token.get_type()) == Some(TokenType::LeftParen) {
+                    return self.function_call(tokens, &name, start).map(Some);
+                }
+                Ok(Some(SimpleASTNode::new(ASTNodeType::Identifier, &name, start)))
+            }
+            TokenType::LeftParen => { // (，节点的范围包括两边的括号
+                let start = tokens.read().unwrap().get_span(); // 消耗掉 (
 
-                let node = self.additive(tokens)?;
+                let node = self.expression(tokens)?;
                 if node.is_none() {
-                    return Err(simple_calculator::invalid_input_err("expecting an additive expression inside parenthesis"));
+                    return Err(simple_calculator::error_at(tokens, "expecting an additive expression inside parenthesis"));
                 }
 
                 let token = tokens.peek();
                 if token.is_none() || token.unwrap().get_type() != TokenType::RightParen {
-                    return Err(simple_calculator::invalid_input_err("expecting right parenthesis"));
+                    return Err(simple_calculator::error_at(tokens, "expecting right parenthesis"));
                 }
 
                 // token.type = TokenType::RightParen
 
-                let _ = tokens.read(); // 消耗掉 )
-                return Ok(node);
+                let end = tokens.read().unwrap().get_span(); // 消耗掉 )
+                Ok(node.map(|mut node| {
+                    node.span = start.to(end);
+                    node
+                }))
             }
-            _ => return Ok(None)
+            _ => Ok(None)
         }
     }
+
+    // 函数调用：Identifier ( (expression (, expression)*)? )，函数名已经读过了，start 是它的位置
+    fn function_call<T: TokenReader>(&self, tokens: &mut T, name: &str, start: Span) -> Result<SimpleASTNode, io::Error> {
+        let _ = tokens.read(); // 消耗掉 (
+        let mut node = SimpleASTNode::new(ASTNodeType::FunctionCall, name, start);
+
+        if tokens.peek().map(|token| token.get_type()) != Some(TokenType::RightParen) {
+            loop {
+                let e = simple_calculator::error_at(tokens, "invalid function call, expecting an argument");
+                let argument = self.expression(tokens)?.ok_or(e)?;
+                node.add_child(RefCell::new(Rc::new(argument)));
+
+                if tokens.expect(TokenType::Comma).is_err() {
+                    break;
+                }
+            }
+        }
+
+        let end = match tokens.expect(TokenType::RightParen) {
+            Ok(token) => token.get_span(),
+            Err(_) => return Err(simple_calculator::error_at(tokens, "expecting right parenthesis")),
+        };
+        node.span = start.to(end);
+        Ok(node)
+    }
+}
+
+
+// 运算符出现的位置。中缀运算符默认左结合，RightInfix 是右结合的中缀运算符
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fixity {
+    Prefix,
+    Infix,
+    RightInfix,
+    Postfix,
+}
+
+struct Operator {
+    token_type: TokenType,
+    fixity: Fixity,
+    // 优先级，数字越大结合得越紧
+    precedence: u8,
+    node_type: ASTNodeType,
+}
+
+const fn operator(token_type: TokenType, fixity: Fixity, precedence: u8, node_type: ASTNodeType) -> Operator {
+    Operator { token_type, fixity, precedence, node_type }
+}
+
+// 表达式中的运算符，优先级从低到高，和 Java 一致。增加运算符只需要在这里加一行
+const OPERATORS: [Operator; 39] = [
+    operator(TokenType::Assignment, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::PlusAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::MinusAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::StarAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::SlashAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::PercentAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::LeftShiftAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::RightShiftAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::UnsignedRightShiftAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::BitAndAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::BitXorAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::BitOrAssign, Fixity::RightInfix, 1, ASTNodeType::AssignmentStmt),
+    operator(TokenType::Or, Fixity::Infix, 2, ASTNodeType::Logical),
+    operator(TokenType::And, Fixity::Infix, 3, ASTNodeType::Logical),
+    operator(TokenType::BitOr, Fixity::Infix, 4, ASTNodeType::Bitwise),
+    operator(TokenType::BitXor, Fixity::Infix, 5, ASTNodeType::Bitwise),
+    operator(TokenType::BitAnd, Fixity::Infix, 6, ASTNodeType::Bitwise),
+    operator(TokenType::EQ, Fixity::Infix, 7, ASTNodeType::Equality),
+    operator(TokenType::NE, Fixity::Infix, 7, ASTNodeType::Equality),
+    operator(TokenType::GT, Fixity::Infix, 8, ASTNodeType::Relational),
+    operator(TokenType::GE, Fixity::Infix, 8, ASTNodeType::Relational),
+    operator(TokenType::LT, Fixity::Infix, 8, ASTNodeType::Relational),
+    operator(TokenType::LE, Fixity::Infix, 8, ASTNodeType::Relational),
+    operator(TokenType::LeftShift, Fixity::Infix, 9, ASTNodeType::Shift),
+    operator(TokenType::RightShift, Fixity::Infix, 9, ASTNodeType::Shift),
+    operator(TokenType::UnsignedRightShift, Fixity::Infix, 9, ASTNodeType::Shift),
+    operator(TokenType::Plus, Fixity::Infix, 10, ASTNodeType::Additive),
+    operator(TokenType::Minus, Fixity::Infix, 10, ASTNodeType::Additive),
+    operator(TokenType::Star, Fixity::Infix, 11, ASTNodeType::Multiplicative),
+    operator(TokenType::Slash, Fixity::Infix, 11, ASTNodeType::Multiplicative),
+    operator(TokenType::Percent, Fixity::Infix, 11, ASTNodeType::Multiplicative),
+    operator(TokenType::Plus, Fixity::Prefix, 12, ASTNodeType::Unary),
+    operator(TokenType::Minus, Fixity::Prefix, 12, ASTNodeType::Unary),
+    operator(TokenType::Not, Fixity::Prefix, 12, ASTNodeType::Unary),
+    operator(TokenType::BitNot, Fixity::Prefix, 12, ASTNodeType::Unary),
+    operator(TokenType::Inc, Fixity::Prefix, 12, ASTNodeType::Unary),
+    operator(TokenType::Dec, Fixity::Prefix, 12, ASTNodeType::Unary),
+    operator(TokenType::Inc, Fixity::Postfix, 13, ASTNodeType::Postfix),
+    operator(TokenType::Dec, Fixity::Postfix, 13, ASTNodeType::Postfix),
+];
+
+fn find_operator(token_type: TokenType, fixity: Fixity) -> Option<&'static Operator> {
+    OPERATORS.iter().find(|operator| operator.token_type == token_type && operator.fixity == fixity)
 }
 
+// 复合赋值对应的二元运算符
+fn compound_operator(token_type: TokenType) -> Option<TokenType> {
+    match token_type {
+        TokenType::PlusAssign => Some(TokenType::Plus),
+        TokenType::MinusAssign => Some(TokenType::Minus),
+        TokenType::StarAssign => Some(TokenType::Star),
+        TokenType::SlashAssign => Some(TokenType::Slash),
+        TokenType::PercentAssign => Some(TokenType::Percent),
+        TokenType::LeftShiftAssign => Some(TokenType::LeftShift),
+        TokenType::RightShiftAssign => Some(TokenType::RightShift),
+        TokenType::UnsignedRightShiftAssign => Some(TokenType::UnsignedRightShift),
+        TokenType::BitAndAssign => Some(TokenType::BitAnd),
+        TokenType::BitXorAssign => Some(TokenType::BitXor),
+        TokenType::BitOrAssign => Some(TokenType::BitOr),
+        _ => None,
+    }
+}
+
+// 只有一个子节点的运算
+fn unary_node(node_type: ASTNodeType, text: &str, span: Span, child: SimpleASTNode) -> SimpleASTNode {
+    let node = SimpleASTNode::new(node_type, text, span);
+    node.add_child(RefCell::new(Rc::new(child)));
+    node
+}
+
+// 有两个子节点的运算
+fn binary_node(node_type: ASTNodeType, text: &str, left: SimpleASTNode, right: SimpleASTNode) -> SimpleASTNode {
+    let node = SimpleASTNode::new(node_type, text, left.span.to(right.span));
+    node.add_child(RefCell::new(Rc::new(left)));
+    node.add_child(RefCell::new(Rc::new(right)));
+    node
+}
+
+// 和 Java 一样，2147483648 超出了 int 的范围，只能紧跟在负号后面，合起来是一个值为 int 最小值的字面量
+fn negative_literal<T: TokenReader>(tokens: &mut T, operator: &str, span: Span) -> Option<SimpleASTNode> {
+    let token = tokens.peek().filter(|token| operator == "-" && token.get_type() == TokenType::IntLiteral)?;
+    let text = format!("-{}", token.get_text());
+    if number::int_value(token.get_text()).is_some() || number::int_value(&text).is_none() {
+        return None;
+    }
+    let token = tokens.read().unwrap();
+    Some(SimpleASTNode::new(ASTNodeType::IntLiteral, &text, span.to(token.get_span())))
+}
+
+// 只有单独的变量名可以被赋值，(a) 这样加了括号的不行。加括号的节点范围包括括号，比变量名长
+fn is_variable(node: &SimpleASTNode) -> bool {
+    node.get_type() == ASTNodeType::Identifier && node.span.end.offset - node.span.start.offset == node.get_text().len()
+}
+
+// ++ 和 -- 会修改操作数，操作数必须是变量
+fn check_operand(operator: &str, span: Span, operand: &SimpleASTNode) -> Result<(), io::Error> {
+    if (operator == "++" || operator == "--") && !is_variable(operand) {
+        let msg = format!("invalid operand of {}, expecting a variable", operator);
+        return Err(simple_calculator::invalid_input_err_at(&msg, span.start));
+    }
+    Ok(())
+}
@@ -2,15 +2,235 @@ use std::{env, io};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 use std::io::Write;
 use std::rc::Rc;
 use std::str::FromStr;
+use std::thread;
 
-use crate::lexer::{ASTNode, ASTNodeType, simple_lexer, TokenReader, TokenType};
+use crate::lexer::{ASTNode, ASTNodeType, identifier, LexErrorReason, number, simple_lexer, Span, TokenReader, TokenType};
+use crate::lexer::keyword::KeywordTable;
 use crate::lexer::simple_calculator;
 use crate::lexer::simple_calculator::SimpleASTNode;
 use crate::lexer::simple_parser::SimpleParser;
 
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::lexer::{ASTNodeType, Span};
+    use crate::lexer::simple_calculator::SimpleASTNode;
+    use crate::lexer::simple_parser::SimpleParser;
+
+    use super::{SimpleScript, Value};
+
+    #[test]
+    pub fn test_normalized_variable() {
+        // cafe\u{301} 和 caf\u{e9} 看起来都是 café
+        let root = SimpleParser::new().parse("int cafe\u{301} = 1; caf\u{e9} = caf\u{e9} + 1; _tmp = 0;").unwrap();
+        let mut script = SimpleScript::new(false);
+        let err = script.evaluate(&Rc::new(root), "").err().unwrap();
+        assert_eq!(err.to_string(), "you dont define variable _tmp");
+        assert_eq!(script.variables.len(), 1);
+        assert_eq!(script.variables["caf\u{e9}"], Value::Int(2));
+    }
+
+    #[test]
+    pub fn test_if_else() {
+        let code = "int a = 5; int b = 0;\n\
+            if (a > 3) b = 1; else b = 2;\n\
+            if (a <= 3) b = b + 10; else if (a != 5) b = b + 20; else b = b + 30;\n\
+            if ('a' == 97) if (a < 0) b = 0; else b = b * 2;\n\
+            if (true == false) b = 0;";
+        let root = SimpleParser::new().parse(code).unwrap();
+        let mut script = SimpleScript::new(false);
+        script.evaluate(&Rc::new(root), "").unwrap();
+        assert_eq!(script.variables["b"], Value::Int(62));
+
+        let root = SimpleParser::new().parse("int c = 1 < 2; c = null == 0;").unwrap();
+        script.evaluate(&Rc::new(root), "").unwrap();
+        assert_eq!(script.variables["c"], Value::Bool(false));
+
+        let root = SimpleParser::new().parse("if (a) b = 1;").unwrap();
+        let err = script.evaluate(&Rc::new(root), "").err().unwrap();
+        assert_eq!(err.to_string(), "condition must be a boolean, found 5 at 1:5");
+        let root = SimpleParser::new().parse("if (true < 1) b = 1;").unwrap();
+        let err = script.evaluate(&Rc::new(root), "").err().unwrap();
+        assert_eq!(err.to_string(), "operator < cannot be applied to true at 1:5");
+    }
+
+    #[test]
+    pub fn test_loops() {
+        let code = "int sum = 0; int i = 0;\n\
+            while (i < 10) i = i + 1;\n\
+            for (int j = 0; j < 10; j = j + 1) if (j == 5) continue; else sum = sum + j;\n\
+            int n = 0; int a = 0; int b = 0;\n\
+            outer: for (a = 0; ; a = a + 1) for (b = 0; b < 10; b = b + 1) if (b == 2) continue outer; else if (a == 3) break outer; else n = n + 1;";
+        let root = SimpleParser::new().parse(code).unwrap();
+        let mut script = SimpleScript::new(false);
+        script.evaluate(&Rc::new(root), "").unwrap();
+        assert_eq!(script.variables["i"], Value::Int(10));
+        assert_eq!(script.variables["sum"], Value::Int(40));
+        assert_eq!(script.variables["n"], Value::Int(6));
+        assert_eq!(script.variables["a"], Value::Int(3));
+
+        for (code, message) in [
+            ("break;", "break outside of a loop at 1:1"),
+            ("if (true) continue;", "continue outside of a loop at 1:11"),
+            ("inner: while (true) while (true) break outer;", "undefined label outer at 1:34"),
+            ("while (1) ;", "condition must be a boolean, found 1 at 1:8"),
+        ] {
+            let root = SimpleParser::new().parse(code).unwrap();
+            let err = script.evaluate(&Rc::new(root), "").err().unwrap();
+            assert_eq!(err.to_string(), message, "evaluate {}", code);
+        }
+    }
+
+    #[test]
+    pub fn test_block_scope() {
+        let code = "int a = 1; int b = 0;\n\
+            { int a = 10; b = a; { a = a + 1; int c = a; b = b + c; } }\n\
+            b = b + a;\n\
+            for (int i = 0; i < 3; i = i + 1) { int t = i; if (t == 1) { int u = 0; continue; } b = b + t; }\n\
+            while (true) { int w = 0; break; }";
+        let root = SimpleParser::new().parse(code).unwrap();
+        let mut script = SimpleScript::new(false);
+        script.evaluate(&Rc::new(root), "").unwrap();
+        assert_eq!(script.variables["a"], Value::Int(1));
+        assert_eq!(script.variables["b"], Value::Int(24));
+        assert_eq!(script.variables.len(), 2);
+        assert!(script.frames.len() == 1 && script.frames[0].scopes.is_empty());
+
+        // 离开块之后块中的变量就不存在了
+        for code in ["{ int c = 1; } c = 2;", "for (int i = 0; i < 1; i = i + 1) { } i = 1;", "{ int d = 1; { d = 2; x = 1; } }"] {
+            let root = SimpleParser::new().parse(code).unwrap();
+            assert!(script.evaluate(&Rc::new(root), "").is_err(), "evaluate {}", code);
+            assert!(script.frames.len() == 1 && script.frames[0].scopes.is_empty(), "evaluate {}", code);
+        }
+        assert_eq!(script.variables.len(), 2);
+    }
+
+    #[test]
+    pub fn test_functions() {
+        let code = "int total = fib(10);\n\
+            int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\n\
+            int max(int a, int b) { if (a > b) { return a; } return b; }\n\
+            int nothing() { int total = 1; }\n\
+            int n = 100; int m = max(n, max(3, 7)) + max(1, 2); int z = nothing();\n\
+            int s = 0; for (int i = 0; i < 5; i = i + 1) { if (i == 3) break; s = s + max(i, 1); }";
+        let root = SimpleParser::new().parse(code).unwrap();
+        let mut script = SimpleScript::new(false);
+        script.evaluate(&Rc::new(root), "").unwrap();
+        assert_eq!(script.variables["total"], Value::Int(55));
+        assert_eq!(script.variables["n"], Value::Int(100));
+        assert_eq!(script.variables["m"], Value::Int(102));
+        assert_eq!(script.variables["z"], Value::Null);
+        assert_eq!(script.variables["s"], Value::Int(4));
+        assert_eq!(script.frames.len(), 1);
+
+        for (code, message) in [
+            ("int f(int n) { return n + m; } int m = 1; int k = f(1);", ""),
+            // 被调用的函数看不到调用者的局部变量
+            ("int g() { int local = 1; return h(); } int h() { local = 2; return 0; } g();", "you dont define variable local"),
+            ("int r = nope(1);", "undefined function nope at 1:9"),
+            ("int r = max(1);", "function max expects 2 arguments, found 1 at 1:9"),
+            ("return 1;", "return outside of a function at 1:1"),
+            ("int b() { break; } while (true) { b(); }", "break outside of a loop at 1:11"),
+            ("int deep(int n) { return deep(n + 1); } deep(0);", "call depth exceeds 100 when calling deep at 1:26"),
+        ] {
+            let root = SimpleParser::new().parse(code).unwrap();
+            match script.evaluate(&Rc::new(root), "") {
+                Ok(_) => assert!(message.is_empty(), "evaluate {}", code),
+                Err(err) => assert_eq!(err.to_string(), message, "evaluate {}", code),
+            }
+            assert_eq!(script.frames.len(), 1, "evaluate {}", code);
+        }
+        assert_eq!(script.variables["k"], Value::Int(2));
+
+        let root = SimpleParser::new().parse("int down(int n) { if (n == 0) return 0; return down(n - 1) + 1; } int d = down(10);").unwrap();
+        let mut script = SimpleScript::new(false).with_max_depth(10);
+        let err = script.evaluate(&Rc::new(root), "").err().unwrap();
+        assert_eq!(err.to_string(), "call depth exceeds 10 when calling down at 1:48");
+        let root = SimpleParser::new().parse("int d = down(9);").unwrap();
+        assert_eq!(script.evaluate(&Rc::new(root), "").unwrap(), Value::Int(9));
+
+        // 调用深度的限制很大时，由栈空间的检查报错，而不是让线程的栈溢出
+        let root = SimpleParser::new().parse("int deep(int n) { return 1 + deep(n + 1) * 2; } deep(0);").unwrap();
+        let mut script = SimpleScript::new(false).with_max_depth(1_000_000);
+        let err = script.evaluate(&Rc::new(root), "").err().unwrap();
+        assert_eq!(err.to_string(), "stack overflow when calling deep at 1:30");
+        assert_eq!(script.frames.len(), 1);
+
+        // 没有函数调用，语法树嵌套得太深时也检查栈空间
+        let mut root = SimpleASTNode::new(ASTNodeType::IntLiteral, "1", Span::default());
+        for _ in 0..5000 {
+            let node = SimpleASTNode::new(ASTNodeType::Unary, "-", Span::default());
+            node.add_child(RefCell::new(Rc::new(root)));
+            root = node;
+        }
+        let mut script = SimpleScript::new(false).with_stack_budget(0);
+        let err = script.evaluate(&Rc::new(root), "").err().unwrap();
+        assert_eq!(err.to_string(), "stack overflow when evaluating Unary at 1:1");
+    }
+
+    #[test]
+    pub fn test_operators() {
+        let code = "int a = 7 % 3 + (1 << 4) + (-8 >> 1) + (-8 >>> 28) + (6 & 3 | 8 ^ 1) + ~0;\n\
+            int i = 1; int b = i++ + ++i; i--;\n\
+            int c = 0; int d = c = 5;\n\
+            int e = 0; int f = false && (e = 1) == 1 || !(e == 0); int g = true || (e = 2) == 2;\n\
+            int s = 0; for (int k = 0; k < 4; k++) s = s + k;\n\
+            int m = 1; m += 2; m *= m -= 1; m <<= 3; m |= 5; m %= 6; m >>>= 1;\n\
+            int h = 2147483647 + 1; int j = 1; while (j != 0) j = j * 2; int l = -2147483648 / -1; int o = -7 % -1;";
+        let root = SimpleParser::new().parse(code).unwrap();
+        let mut script = SimpleScript::new(false);
+        script.evaluate(&Rc::new(root), "").unwrap();
+        let expected = [("a", 38), ("b", 4), ("i", 2), ("c", 5), ("d", 5), ("e", 0), ("s", 6), ("h", i32::MIN), ("j", 0), ("l", i32::MIN), ("o", 0), ("m", 2)];
+        for (name, value) in expected {
+            assert_eq!(script.variables[name], Value::Int(value), "variable {}", name);
+        }
+        assert_eq!(script.variables["f"], Value::Bool(false));
+        assert_eq!(script.variables["g"], Value::Bool(true));
+
+        for (code, message) in [
+            ("!1;", "operand of '!' must be a boolean, found 1 at 1:2"),
+            ("true && 1;", "operand of '&&' must be a boolean, found 1 at 1:9"),
+            ("if (1) 2;", "condition must be a boolean, found 1 at 1:5"),
+            ("-true;", "operator - cannot be applied to true at 1:2"),
+            ("zz++;", "you dont define variable zz"),
+            ("1 / 0;", "division by zero at 1:5"),
+            ("int z = 1 % (a - a);", "division by zero at 1:13"),
+        ] {
+            let root = SimpleParser::new().parse(code).unwrap();
+            let err = script.evaluate(&Rc::new(root), "").err().unwrap();
+            assert_eq!(err.to_string(), message, "evaluate {}", code);
+        }
+    }
+
+    #[test]
+    pub fn test_literals() {
+        let mut script = SimpleScript::new(false);
+        let cases = [
+            ("int a = true;", Value::Bool(true)),
+            ("int b = false;", Value::Bool(false)),
+            ("int c = null;", Value::Null),
+            ("int d = '\\n';", Value::Char('\n')),
+            ("int e = '\\u{5e74}';", Value::Char('年')),
+            ("int f = 'a' + 1;", Value::Int(98)),
+        ];
+        for (code, expected) in cases {
+            let root = SimpleParser::new().parse(code).unwrap();
+            assert_eq!(script.evaluate(&Rc::new(root), "").unwrap(), expected, "evaluate {}", code);
+        }
+        assert_eq!(Value::Char('x').to_string(), "x");
+
+        let root = SimpleParser::new().parse("a + 1;").unwrap();
+        let err = script.evaluate(&Rc::new(root), "").err().unwrap();
+        assert_eq!(err.to_string(), "operator + cannot be applied to true at 1:1");
+    }
+}
+
 pub fn script_demo() {
     // 使用 `env::args()` 获取命令行参数
     let args: Vec<String> = env::args().collect();
@@ -20,20 +240,50 @@ pub fn script_demo() {
 
 
     let mut v = false;
+    let mut keywords = KeywordTable::english();
+    let mut max_depth = MAX_CALL_DEPTH;
     // 遍历并打印每个参数
     for (index, arg) in args.iter().enumerate() {
         println!("Argument {}: {}", index, arg);
         if arg == "-v" {
             v = true;
         }
+
+        // --keywords=zh 使用中文关键字，也可以是自定义的别名，比如 --keywords=si=if,sinon=else
+        if let Some(profile) = arg.strip_prefix("--keywords=") {
+            match KeywordTable::profile(profile) {
+                Ok(table) => keywords = table,
+                Err(err) => println!("{}", err),
+            }
+        }
+
+        // --max-depth=100 函数调用的最大深度
+        if let Some(depth) = arg.strip_prefix("--max-depth=") {
+            match usize::from_str(depth) {
+                Ok(depth) => max_depth = depth,
+                Err(err) => println!("invalid max depth {}: {}", depth, err),
+            }
+        }
     }
 
     // v = true;
 
+    // 在单独的线程中执行脚本，栈的大小按函数调用的最大深度分配
+    let stack_budget = max_depth.saturating_mul(FRAME_STACK).clamp(STACK_BUDGET, MAX_STACK);
+    let repl = thread::Builder::new()
+        .stack_size(stack_budget + STACK_RESERVE)
+        .spawn(move || repl(v, keywords, max_depth, stack_budget))
+        .expect("failed to spawn the script thread");
+    repl.join().unwrap();
+}
+
+// 交互式地读入并执行脚本
+fn repl(v: bool, keywords: KeywordTable, max_depth: usize, stack_budget: usize) {
     println!("Simple script language!");
 
     let parser = SimpleParser::new();
-    let mut script = SimpleScript::new(v);
+    let mut lexer = simple_lexer::SimpleLexer::with_keywords(keywords);
+    let mut script = SimpleScript::new(v).with_max_depth(max_depth).with_stack_budget(stack_budget);
 
     let mut code = String::new();
 
@@ -53,14 +303,25 @@ pub fn script_demo() {
             break;
         }
 
+        // :keywords zh 切换关键字表
+        if let Some(profile) = line.strip_prefix(":keywords ") {
+            match KeywordTable::profile(profile) {
+                Ok(table) => lexer = simple_lexer::SimpleLexer::with_keywords(table),
+                Err(err) => println!("{}", err),
+            }
+            continue;
+        }
+
         code.push_str(line);
-        code.push_str("\n");
+        code.push('\n');
 
-        if !line.ends_with(";") {
+        if !is_complete(&lexer, code.as_str()) {
             continue;
         }
 
-        let mut parse_result = parser.parse(code.as_str());
+        print_warnings(&lexer, code.as_str());
+
+        let mut parse_result = parser.parse_with(&lexer, code.as_str());
         if let Err(error) = parse_result {
             println!("parse failed: {}", error);
             code = String::new();
@@ -83,100 +344,575 @@ pub fn script_demo() {
 }
 
 
-struct SimpleScript {
-    variables: HashMap<String, i32>,
+// 输入是否已经是完整的语句：最后一个 Token 是分号或者右花括号，并且花括号都已配对。行尾可以跟注释，块注释没结束时继续读下一行
+fn is_complete(lexer: &simple_lexer::SimpleLexer, code: &str) -> bool {
+    match lexer.tokenize(code) {
+        Ok(mut tokens) => {
+            let mut last = None;
+            let mut depth = 0;
+            while let Some(token) = tokens.read() {
+                match token.get_type() {
+                    TokenType::LeftBrace => depth += 1,
+                    TokenType::RightBrace => depth -= 1,
+                    _ => (),
+                }
+                last = Some(token.get_type());
+            }
+            depth <= 0 && matches!(last, Some(TokenType::SemiColon) | Some(TokenType::RightBrace))
+        }
+        // 其他词法错误交给 parse 去报告
+        Err(err) => err.reason != LexErrorReason::UnterminatedComment,
+    }
+}
+
+
+// 打印词法警告，比如容易混淆的标识符
+fn print_warnings(lexer: &simple_lexer::SimpleLexer, code: &str) {
+    if let Ok(tokens) = lexer.tokenize(code) {
+        for warning in tokens.get_warnings() {
+            println!("warning: {}", warning);
+        }
+    }
+}
+
+
+/// 脚本中的值
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Int(i32),
+    Bool(bool),
+    Char(char),
+    Null,
+}
+
+impl Value {
+    // 整数，字符按它的码点计算，其他的值返回None
+    fn as_int(self) -> Option<i32> {
+        match self {
+            Value::Int(value) => Some(value),
+            Value::Char(ch) => Some(ch as i32),
+            _ => None,
+        }
+    }
+
+    // 参与算术运算、大小比较的整数
+    fn to_int(self, operator: &str, span: Span) -> Result<i32, io::Error> {
+        self.as_int().ok_or_else(|| {
+            let msg = format!("operator {} cannot be applied to {}", operator, self);
+            simple_calculator::invalid_input_err_at(msg.as_str(), span.start)
+        })
+    }
+
+    // 条件和逻辑运算的操作数必须是布尔值，context 说明是哪里要求的，比如 condition、operand of '!'
+    fn to_bool(self, context: &str, span: Span) -> Result<bool, io::Error> {
+        match self {
+            Value::Bool(value) => Ok(value),
+            _ => {
+                let msg = format!("{} must be a boolean, found {}", context, self);
+                Err(simple_calculator::invalid_input_err_at(msg.as_str(), span.start))
+            }
+        }
+    }
+
+    // == 的结果：整数和字符按码点比较，其他的值只和同类的值相等
+    fn equals(self, other: Value) -> bool {
+        match (self.as_int(), other.as_int()) {
+            (Some(num1), Some(num2)) => num1 == num2,
+            _ => self == other,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Char(ch) => write!(f, "{}", ch),
+            Value::Null => write!(f, "null"),
+        }
+    }
+}
+
+
+/*
+打断正常执行顺序的情况：出错，或者 break、continue、return 跳出了当前的语句。
+它们和错误一样沿着调用栈向外传，直到被对应的循环或者函数调用接住，不需要用 panic 来实现跳转。
+*/
+enum Interrupt {
+    Error(io::Error),
+    // 标签（没有标签时为空），以及 break、continue 语句的位置
+    Break(String, Span),
+    Continue(String, Span),
+    // 返回值，以及 return 语句的位置
+    Return(Value, Span),
+}
+
+impl From<io::Error> for Interrupt {
+    fn from(err: io::Error) -> Self {
+        Interrupt::Error(err)
+    }
+}
+
+// 一直传到脚本最外层的 break、continue：不在循环中，或者标签不属于任何外层的循环
+fn stray_jump(keyword: &str, label: &str, span: Span) -> io::Error {
+    let msg = if label.is_empty() {
+        format!("{} outside of a loop", keyword)
+    } else {
+        format!("undefined label {}", label)
+    };
+    simple_calculator::invalid_input_err_at(msg.as_str(), span.start)
+}
+
+
+// 默认的函数调用最大深度，超过时报错，而不是让解释器自己的栈溢出
+const MAX_CALL_DEPTH: usize = 100;
+
+// 栈空间上限之外留出的余量，给最后一次检查之后的执行，以及解析器的递归。不是函数调用的节点最多可以用掉其中的一半
+const STACK_RESERVE: usize = 512 * 1024;
+
+// 默认最多使用多少栈空间，按新线程默认的 2MB 栈计算
+const STACK_BUDGET: usize = 2 * 1024 * 1024 - STACK_RESERVE;
+
+// 估计每一层函数调用用掉的栈空间，debug 构建中大约是 11KB
+const FRAME_STACK: usize = 16 * 1024;
+
+// 按调用深度分配的栈最大不超过 1GB，更深的调用由栈空间的检查拦住
+const MAX_STACK: usize = 1024 * 1024 * 1024;
+
+// 当前的栈顶位置，栈向哪个方向增长都可以用它和栈底的距离估计用掉的栈空间
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(std::ptr::addr_of!(marker)) as usize
+}
+
+// 函数调用的栈帧，保存参数和局部变量
+#[derive(Default)]
+struct Frame {
+    // 块作用域，从外到内排列，离开块时丢掉其中声明的变量
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+struct SimpleScript<T: ASTNode> {
+    // 全局变量
+    variables: HashMap<String, Value>,
+    // 调用栈，第一个栈帧是脚本的最外层
+    frames: Vec<Frame>,
+    // 声明过的函数
+    functions: HashMap<String, Rc<T>>,
+    max_depth: usize,
+    // evaluate 开始时的栈位置，以及允许使用的栈空间
+    stack_base: usize,
+    stack_budget: usize,
     verbose: bool,
 }
 
-impl SimpleScript {
+impl<T: ASTNode> SimpleScript<T> {
     fn new(verbose: bool) -> Self {
         SimpleScript {
             variables: HashMap::new(),
+            frames: vec![Frame::default()],
+            functions: HashMap::new(),
+            max_depth: MAX_CALL_DEPTH,
+            stack_base: 0,
+            stack_budget: STACK_BUDGET,
             verbose,
         }
     }
 
-    fn evaluate<T: ASTNode>(&mut self, node: &Rc<T>, indent: &str) -> Result<i32, io::Error> {
+    // 设置函数调用的最大深度
+    fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // 设置最多使用的栈空间，应该比执行脚本的线程的栈小一些
+    fn with_stack_budget(mut self, stack_budget: usize) -> Self {
+        self.stack_budget = stack_budget;
+        self
+    }
+
+    // 执行脚本，没有被任何循环接住的 break、continue，以及函数之外的 return 也是错误
+    fn evaluate(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, io::Error> {
+        self.stack_base = stack_address();
+        match self.execute(node, indent) {
+            Ok(value) => Ok(value),
+            Err(Interrupt::Error(err)) => Err(err),
+            Err(Interrupt::Break(label, span)) => Err(stray_jump("break", &label, span)),
+            Err(Interrupt::Continue(label, span)) => Err(stray_jump("continue", &label, span)),
+            Err(Interrupt::Return(_, span)) => Err(simple_calculator::invalid_input_err_at("return outside of a function", span.start)),
+        }
+    }
+
+    // 用掉的栈空间是否超过了上限，margin 是允许超出的部分
+    fn stack_exhausted(&self, margin: usize) -> bool {
+        self.stack_base.abs_diff(stack_address()) > self.stack_budget + margin
+    }
+
+    // 当前栈帧的块作用域
+    fn scopes(&mut self) -> &mut Vec<HashMap<String, Value>> {
+        &mut self.frames.last_mut().expect("frame not found").scopes
+    }
+
+    /*
+    执行一个节点。子节点先复制出来再执行：递归调用的函数会再次执行同一个节点，不能一直借用着它的子节点。
+    各种节点分别在单独的方法中执行，这样每一层递归占用的栈空间比较少。
+    */
+    fn execute(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
         if self.verbose {
             println!("{}Calculating: {}", indent, node.get_type())
         }
+        // 不是解析器生成的语法树可能没有函数调用也嵌套得很深，其他节点也要检查栈空间。
+        // 允许多用一些余量，有函数调用时先由 call 报错
+        if node.get_type() != ASTNodeType::FunctionCall && self.stack_exhausted(STACK_RESERVE / 2) {
+            let msg = format!("stack overflow when evaluating {}", node.get_type());
+            return Err(simple_calculator::invalid_input_err_at(msg.as_str(), node.get_span().start).into());
+        }
 
-        let mut result = 0;
+        // 只有打印执行过程时才需要真正的缩进，否则只要区分是不是最外层
+        let indented;
+        let inner = if self.verbose {
+            indented = format!("{}\t", indent);
+            indented.as_str()
+        } else {
+            "\t"
+        };
+        let result = match node.get_type() {
+            ASTNodeType::Program => self.program(node, inner),
+            ASTNodeType::Block => self.block(node, inner),
+            ASTNodeType::FunctionCall => self.call(node, inner),
+            ASTNodeType::ReturnStmt => self.return_statement(node, inner),
+            ASTNodeType::Additive | ASTNodeType::Multiplicative => self.arithmetic(node, inner),
+            ASTNodeType::Equality | ASTNodeType::Relational => self.comparison(node, inner),
+            ASTNodeType::Bitwise | ASTNodeType::Shift => self.arithmetic(node, inner),
+            ASTNodeType::Logical => self.logical(node, inner),
+            ASTNodeType::Unary | ASTNodeType::Postfix => self.unary(node, inner),
+            ASTNodeType::WhileStmt => self.while_statement(node, inner),
+            ASTNodeType::ForStmt => self.for_statement(node, inner),
+            ASTNodeType::BreakStmt => Err(Interrupt::Break(node.get_text().to_string(), node.get_span())),
+            ASTNodeType::ContinueStmt => Err(Interrupt::Continue(node.get_text().to_string(), node.get_span())),
+            ASTNodeType::IfStmt => self.if_statement(node, inner),
+            ASTNodeType::IntLiteral => self.int_literal(node),
+            ASTNodeType::BooleanLiteral => Ok(Value::Bool(node.get_text() == "true")),
+            ASTNodeType::CharLiteral => Ok(Value::Char(node.get_text().chars().next().unwrap_or_default())),
+            ASTNodeType::NullLiteral => Ok(Value::Null),
+            ASTNodeType::Identifier => Ok(self.identifier(node)),
+            ASTNodeType::AssignmentStmt | ASTNodeType::IntDeclaration => self.assignment(node, inner),
+            _ => Ok(Value::Int(0)),
+        }?;
 
-        match node.get_type() {
-            ASTNodeType::Program => {
-                for child in node.get_children().iter() {
-                    result = self.evaluate(child, format!("{}\t", indent).as_str())?;
-                }
+        if self.verbose {
+            println!("{} Result:{}", indent, result);
+        } else if indent.is_empty() {
+            match node.get_type() {
+                ASTNodeType::IntDeclaration | ASTNodeType::AssignmentStmt => println!("{} : {}", node.get_text(), result),
+                ASTNodeType::Program => println!("{}", result),
+                _ => ()
             }
-            ASTNodeType::Additive | ASTNodeType::Multiplicative => {
-                let children = node.get_children();
-                let child1 = children.get(0).expect("child 1 not found");
-                let child2 = children.get(1).expect("child 2 not found");
+        }
+
+        Ok(result)
+    }
+
+    // 依次执行各条语句，结果是最后一条语句的值
+    fn program(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let children = node.get_children().clone();
+        // 先登记所有的函数，调用可以出现在声明之前
+        for child in children.iter().filter(|child| child.get_type() == ASTNodeType::FunctionDeclaration) {
+            self.functions.insert(identifier::normalize(child.get_text()), child.clone());
+        }
+
+        let mut result = Value::Int(0);
+        for child in children.iter() {
+            result = self.execute(child, indent)?;
+        }
+        Ok(result)
+    }
+
+    fn block(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        self.scopes().push(HashMap::new());
+        let children = node.get_children().clone();
+        let outcome = children.iter().try_for_each(|child| self.execute(child, indent).map(|_| ()));
+        // 出错或者 break、continue、return 跳出块时也要丢掉块中的变量
+        self.scopes().pop();
+        outcome.map(|_| Value::Int(0))
+    }
+
+    fn return_statement(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let children = node.get_children().clone();
+        let value = match children.first() {
+            Some(child) => self.execute(child, indent)?,
+            None => Value::Null,
+        };
+        Err(Interrupt::Return(value, node.get_span()))
+    }
+
+    fn arithmetic(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let children = node.get_children().clone();
+        let child1 = children.first().expect("child 1 not found");
+        let child2 = children.get(1).expect("child 2 not found");
+
+        let operator = node.get_text();
+        let num1 = self.execute(child1, indent)?.to_int(operator, child1.get_span())?;
+        let num2 = self.execute(child2, indent)?.to_int(operator, child2.get_span())?;
+        if num2 == 0 && (operator == "/" || operator == "%") {
+            return Err(simple_calculator::invalid_input_err_at("division by zero", child2.get_span().start).into());
+        }
 
-                let num1 = self.evaluate(child1, format!("{}\t", indent).as_str())?;
-                let num2 = self.evaluate(child2, format!("{}\t", indent).as_str())?;
+        // 和 Java 一样，溢出时回绕，不会让解释器 panic
+        let result = match operator {
+            "+" => Value::Int(num1.wrapping_add(num2)),
+            "-" => Value::Int(num1.wrapping_sub(num2)),
+            "*" => Value::Int(num1.wrapping_mul(num2)),
+            "/" => Value::Int(num1.wrapping_div(num2)),
+            "%" => Value::Int(num1.wrapping_rem(num2)),
+            "&" => Value::Int(num1 & num2),
+            "|" => Value::Int(num1 | num2),
+            "^" => Value::Int(num1 ^ num2),
+            "<<" => Value::Int(num1.wrapping_shl(num2 as u32)),
+            ">>" => Value::Int(num1.wrapping_shr(num2 as u32)),
+            ">>>" => Value::Int((num1 as u32).wrapping_shr(num2 as u32) as i32),
+            _ => {
+                println!("found unsupported operator: {}", node.get_text());
+                Value::Int(0)
+            }
+        };
+        Ok(result)
+    }
 
-                let operator = node.get_text();
+    fn comparison(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let children = node.get_children().clone();
+        let child1 = children.first().expect("child 1 not found");
+        let child2 = children.get(1).expect("child 2 not found");
+
+        let operator = node.get_text();
+        let value1 = self.execute(child1, indent)?;
+        let value2 = self.execute(child2, indent)?;
+
+        let compared = match operator {
+            "==" => value1.equals(value2),
+            "!=" => !value1.equals(value2),
+            _ => {
+                let num1 = value1.to_int(operator, child1.get_span())?;
+                let num2 = value2.to_int(operator, child2.get_span())?;
                 match operator {
-                    "+" => result = num1 + num2,
-                    "-" => result = num1 - num2,
-                    "*" => result = num1 * num2,
-                    "/" => result = num1 / num2,
-                    _ => println!("found unsupported operator: {}", node.get_text()),
+                    ">" => num1 > num2,
+                    ">=" => num1 >= num2,
+                    "<" => num1 < num2,
+                    _ => num1 <= num2,
                 }
             }
-            ASTNodeType::IntLiteral => {
-                result = i32::from_str(node.get_text()).unwrap_or_else(|e| {
-                    println!("parse {} failed {}", node.get_text(), e);
-                    0
-                });
-            }
-            ASTNodeType::Identifier => {
-                let var_name = node.get_text();
+        };
+        Ok(Value::Bool(compared))
+    }
 
-                if self.variables.contains_key(var_name) {
-                    let v = self.variables.get(var_name).unwrap();
+    // && 和 || 短路求值：左边已经能决定结果时不再计算右边
+    fn logical(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let children = node.get_children().clone();
+        let child1 = children.first().expect("child 1 not found");
+        let child2 = children.get(1).expect("child 2 not found");
 
-                    result = *v;
-                } else {
-                    println!(" not found variable {}", var_name);
-                }
-            }
-            ASTNodeType::AssignmentStmt | ASTNodeType::IntDeclaration => {
-                let var_name = node.get_text();
-                let node_type = node.get_type();
-                if node_type == ASTNodeType::AssignmentStmt && !self.variables.contains_key(var_name) {
-                    let msg = format!("you dont define variable {}", var_name);
-                    return Err(simple_calculator::invalid_input_err(msg.as_str()));
-                }
+        let context = format!("operand of '{}'", node.get_text());
+        let left = self.execute(child1, indent)?.to_bool(&context, child1.get_span())?;
+        if left == (node.get_text() == "||") {
+            return Ok(Value::Bool(left));
+        }
+        let right = self.execute(child2, indent)?.to_bool(&context, child2.get_span())?;
+        Ok(Value::Bool(right))
+    }
 
-                let mut child_result = 0;
-                let children = node.get_children();
-                if children.len() > 0 {
-                    let child = children.get(0);
-                    let child = child.unwrap();
-                    result = self.evaluate(child, format!("{}\t", indent).as_str())?; // 2. 这里面也会借用一次。
-                    child_result = result
+    // 前缀和后缀运算。++、-- 修改变量，前缀的结果是修改之后的值，后缀的结果是修改之前的值
+    fn unary(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let children = node.get_children().clone();
+        let child = children.first().expect("operand not found");
+        let operator = node.get_text();
+        let value = self.execute(child, indent)?;
+
+        let result = match operator {
+            "!" => Value::Bool(!value.to_bool("operand of '!'", child.get_span())?),
+            "-" => Value::Int(value.to_int(operator, child.get_span())?.wrapping_neg()),
+            "~" => Value::Int(!value.to_int(operator, child.get_span())?),
+            "+" => Value::Int(value.to_int(operator, child.get_span())?),
+            _ => {
+                let num = value.to_int(operator, child.get_span())?;
+                let updated = Value::Int(if operator == "++" { num.wrapping_add(1) } else { num.wrapping_sub(1) });
+                let var_name = identifier::normalize(child.get_text());
+                match self.variable_mut(&var_name) {
+                    Some(v) => *v = updated,
+                    None => {
+                        let msg = format!("you dont define variable {}", var_name);
+                        return Err(simple_calculator::invalid_input_err(msg.as_str()).into());
+                    }
                 }
+                if node.get_type() == ASTNodeType::Postfix { Value::Int(num) } else { updated }
+            }
+        };
+        Ok(result)
+    }
 
-
-                self.variables.insert(var_name.to_string(), child_result);
+    fn while_statement(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let children = node.get_children().clone();
+        let condition = children.first().expect("condition not found");
+        let body = children.get(1).expect("body not found");
+        while self.execute(condition, indent)?.to_bool("condition", condition.get_span())? {
+            if !self.run_body(node.get_text(), body, indent)? {
+                break;
             }
-            _ => ()
         }
+        Ok(Value::Int(0))
+    }
 
-        if self.verbose {
-            println!("{} Result:{}", indent, result);
-        } else if indent == "" {
-            match node.get_type() {
-                ASTNodeType::IntDeclaration | ASTNodeType::AssignmentStmt => println!("{} : {}", node.get_text(), result),
-                ASTNodeType::Program => println!("{}", result),
-                _ => ()
+    fn for_statement(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let children = node.get_children().clone();
+        let (init, condition, step, body) = (&children[0], &children[1], &children[2], &children[3]);
+        // 初始化部分声明的变量只在循环中可见
+        self.scopes().push(HashMap::new());
+        let outcome = self.execute(init, indent).and_then(|_| loop {
+            // 省略了条件的 for 循环一直执行，直到 break
+            if condition.get_type() != ASTNodeType::EmptyStmt
+                && !self.execute(condition, indent)?.to_bool("condition", condition.get_span())? {
+                break Ok(());
             }
+            if !self.run_body(node.get_text(), body, indent)? {
+                break Ok(());
+            }
+            self.execute(step, indent)?;
+        });
+        self.scopes().pop();
+        outcome.map(|_| Value::Int(0))
+    }
+
+    // 只执行条件选中的分支，没有 else 分支时什么都不做
+    fn if_statement(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let children = node.get_children().clone();
+        let condition = children.first().expect("condition not found");
+        let branch = if self.execute(condition, indent)?.to_bool("condition", condition.get_span())? {
+            children.get(1)
+        } else {
+            children.get(2)
+        };
+
+        match branch {
+            Some(branch) => self.execute(branch, indent),
+            None => Ok(Value::Int(0)),
         }
+    }
+
+    fn int_literal(&mut self, node: &Rc<T>) -> Result<Value, Interrupt> {
+        let value = number::int_value(node.get_text()).ok_or_else(|| {
+            let msg = format!("integer literal {} is out of range", node.get_text());
+            simple_calculator::invalid_input_err_at(msg.as_str(), node.get_span().start)
+        })?;
+        Ok(Value::Int(value))
+    }
 
+    fn identifier(&mut self, node: &Rc<T>) -> Value {
+        let var_name = identifier::normalize(node.get_text());
+
+        if let Some(v) = self.variable_mut(&var_name) {
+            *v
+        } else {
+            println!(" not found variable {}", var_name);
+            Value::Int(0)
+        }
+    }
+
+    fn assignment(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        // 变量名按 NFC 规范化，看起来一样、编码不同的名字是同一个变量
+        let var_name = identifier::normalize(node.get_text());
+        let node_type = node.get_type();
+        if node_type == ASTNodeType::AssignmentStmt && self.variable_mut(&var_name).is_none() {
+            let msg = format!("you dont define variable {}", var_name);
+            return Err(simple_calculator::invalid_input_err(msg.as_str()).into());
+        }
+
+        let mut result = Value::Int(0);
+        let children = node.get_children().clone();
+        if let Some(child) = children.first() {
+            result = self.execute(child, indent)?;
+        }
+
+        // 声明放在最内层的作用域中，可以遮蔽外层的同名变量；赋值修改最近的一个同名变量
+        if node_type == ASTNodeType::IntDeclaration {
+            let frame = self.frames.last_mut().expect("frame not found");
+            frame.scopes.last_mut().unwrap_or(&mut self.variables).insert(var_name, result);
+        } else if let Some(v) = self.variable_mut(&var_name) {
+            *v = result;
+        }
         Ok(result)
     }
-}
+    // 从最内层的作用域向外查找变量，最后查全局变量。调用者的局部变量对被调用的函数不可见
+    fn variable_mut(&mut self, name: &str) -> Option<&mut Value> {
+        let frame = self.frames.last_mut().expect("frame not found");
+        match frame.scopes.iter_mut().rev().find(|scope| scope.contains_key(name)) {
+            Some(scope) => scope.get_mut(name),
+            None => self.variables.get_mut(name),
+        }
+    }
 
+    /*
+    调用函数：在调用者的栈帧中计算实参，再压入新的栈帧，参数是其中最外层的作用域。
+    函数体执行完没有 return 时返回 null，函数中的 break、continue 不能跳出到调用者的循环。
+    */
+    fn call(&mut self, node: &Rc<T>, indent: &str) -> Result<Value, Interrupt> {
+        let name = identifier::normalize(node.get_text());
+        let start = node.get_span().start;
+        let function = match self.functions.get(&name) {
+            Some(function) => function.clone(),
+            None => {
+                let msg = format!("undefined function {}", name);
+                return Err(simple_calculator::invalid_input_err_at(msg.as_str(), start).into());
+            }
+        };
+
+        let children = function.get_children().clone();
+        let (body, parameters) = children.split_last().expect("function body not found");
+        let arguments = node.get_children().clone();
+        if arguments.len() != parameters.len() {
+            let msg = format!("function {} expects {} arguments, found {}", name, parameters.len(), arguments.len());
+            return Err(simple_calculator::invalid_input_err_at(msg.as_str(), start).into());
+        }
+        if self.frames.len() > self.max_depth {
+            let msg = format!("call depth exceeds {} when calling {}", self.max_depth, name);
+            return Err(simple_calculator::invalid_input_err_at(msg.as_str(), start).into());
+        }
+        // 调用深度没有超过限制，但每一层用掉的栈空间取决于函数体中表达式的嵌套，还要检查真实的栈
+        if self.stack_exhausted(0) {
+            let msg = format!("stack overflow when calling {}", name);
+            return Err(simple_calculator::invalid_input_err_at(msg.as_str(), start).into());
+        }
+
+        let mut locals = HashMap::new();
+        for (parameter, argument) in parameters.iter().zip(arguments.iter()) {
+            let value = self.execute(argument, indent)?;
+            locals.insert(identifier::normalize(parameter.get_text()), value);
+        }
+
+        self.frames.push(Frame { scopes: vec![locals] });
+        let outcome = self.execute(body, indent);
+        self.frames.pop();
+
+        match outcome {
+            Ok(_) => Ok(Value::Null),
+            Err(Interrupt::Return(value, _)) => Ok(value),
+            Err(Interrupt::Break(label, span)) => Err(stray_jump("break", &label, span).into()),
+            Err(Interrupt::Continue(label, span)) => Err(stray_jump("continue", &label, span).into()),
+            Err(interrupt) => Err(interrupt),
+        }
+    }
+
+    /*
+    执行一次循环体，返回 false 表示 break 跳出了这个循环。
+    不带标签的 break、continue 属于最内层的循环，带标签的属于标签相同的循环，其他的继续向外传。
+    */
+    fn run_body(&mut self, label: &str, body: &Rc<T>, indent: &str) -> Result<bool, Interrupt> {
+        let matches = |target: &str| target.is_empty() || identifier::normalize(target) == identifier::normalize(label);
+        match self.execute(body, indent) {
+            Ok(_) => Ok(true),
+            Err(Interrupt::Break(target, _)) if matches(&target) => Ok(false),
+            Err(Interrupt::Continue(target, _)) if matches(&target) => Ok(true),
+            Err(interrupt) => Err(interrupt),
+        }
+    }
+}
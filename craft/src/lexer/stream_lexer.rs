@@ -0,0 +1,334 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use crate::lexer::{LexError, LexWarning, Token, TokenReader};
+use crate::lexer::keyword::KeywordTable;
+use crate::lexer::simple_calculator;
+use crate::lexer::simple_lexer::{OwnedTokens, Scanner};
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufReader, Read};
+
+    use crate::lexer::{Lexer, Token, TokenReader, TokenType};
+    use crate::lexer::simple_lexer::SimpleLexer;
+
+    use super::{MAX_DIAGNOSTICS, StreamTokenReader};
+
+    // 每次只给出几个字节的 Read，用来检验跨越读取边界的 Token 和多字节字符
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    fn texts<T: TokenReader>(token_reader: &mut T) -> Vec<String> {
+        let mut texts = Vec::new();
+        while let Some(token) = token_reader.read() {
+            texts.push(token.get_text().to_string());
+        }
+        texts
+    }
+
+    #[test]
+    pub fn test_same_as_tokenize() {
+        let script = "int 年龄 = 45; /* 注释 */ if (年龄 >= 17) s = \"a\\u{5e74}b\"; // end\nelse x = 1;";
+        let expected = texts(&mut SimpleLexer::new().tokenize(script).unwrap());
+
+        for step in [1, 2, 3, 7, 64] {
+            let reader = BufReader::with_capacity(4, Trickle { data: script.as_bytes(), step });
+            let mut token_reader = StreamTokenReader::new(reader);
+            assert_eq!(texts(&mut token_reader), expected, "step {}", step);
+            assert_eq!(token_reader.get_comments().len(), 2);
+            assert!(token_reader.get_errors().is_empty());
+            assert!(token_reader.take_io_error().is_none());
+        }
+    }
+
+    #[test]
+    pub fn test_replay() {
+        let mut token_reader = StreamTokenReader::with_replay(&b"a = 1; b = 2; c = 3;"[..], 4);
+        assert_eq!(token_reader.peek().unwrap().get_type(), TokenType::Identifier);
+        for _ in 0..6 {
+            token_reader.read();
+        }
+        assert_eq!(token_reader.get_position(), 6);
+
+        token_reader.unread();
+        assert_eq!(token_reader.read().unwrap().get_text(), "=");
+        token_reader.set_position(3);
+        assert_eq!(token_reader.read().unwrap().get_text(), ";");
+
+        // 向前跳过还没有读入的 Token
+        token_reader.set_position(10);
+        assert_eq!(token_reader.read().unwrap().get_text(), "3");
+
+        // 超出回放窗口的位置已经被丢弃，读取位置保持不变，并且记下错误
+        assert!(token_reader.take_replay_error().is_none());
+        assert!(!token_reader.set_position(2));
+        assert_eq!(token_reader.get_position(), 11);
+        let err = token_reader.take_replay_error().unwrap();
+        assert_eq!(err.to_string(), "cannot backtrack to token 2, only the last 4 tokens are kept at 1:20");
+        assert_eq!(token_reader.read().unwrap().get_text(), ";");
+        assert!(token_reader.read().is_none());
+
+        // unread 到回放窗口的边上为止，再退一步就记下错误
+        let mut token_reader = StreamTokenReader::with_replay(&b"a = 1; b = 2;"[..], 2);
+        for _ in 0..4 {
+            token_reader.read();
+        }
+        token_reader.unread();
+        token_reader.unread();
+        assert_eq!(token_reader.get_position(), 2);
+        assert!(token_reader.take_replay_error().is_none());
+        token_reader.unread();
+        assert_eq!(token_reader.get_position(), 2);
+        assert_eq!(token_reader.take_replay_error().unwrap().to_string(), "cannot backtrack to token 1, only the last 2 tokens are kept at 1:5");
+    }
+
+    #[test]
+    pub fn test_error() {
+        let mut token_reader = StreamTokenReader::new(&b"a = 1 $ 2; \"x"[..]);
+        assert_eq!(texts(&mut token_reader), vec!["a", "=", "1", "2", ";"]);
+        let errors: Vec<char> = token_reader.get_errors().iter().map(|e| e.ch).collect();
+        assert_eq!(errors, vec!['$', '"']);
+
+        let mut token_reader = StreamTokenReader::new(&b"a = \xff;"[..]);
+        assert_eq!(texts(&mut token_reader), vec!["a", "="]);
+        assert_eq!(token_reader.take_io_error().unwrap().kind(), io::ErrorKind::InvalidData);    }
+
+    #[test]
+    pub fn test_bounded() {
+        // 注释只保留最近的 replay 个，错误只保留前 MAX_DIAGNOSTICS 个
+        let script = "/* c */ $ ".repeat(1000) + "/* last */";
+        let mut token_reader = StreamTokenReader::with_replay(script.as_bytes(), 8);
+        assert!(token_reader.read().is_none());
+        assert_eq!(token_reader.get_comments().len(), 8);
+        assert_eq!(token_reader.get_comments().last().unwrap().get_text(), "/* last */");
+        assert_eq!(token_reader.get_errors().len(), MAX_DIAGNOSTICS);
+        assert_eq!(token_reader.get_errors()[0].position.column, 9);
+
+        assert_eq!(token_reader.take_comments().len(), 8);
+        assert!(token_reader.get_comments().is_empty());
+    }
+}
+
+
+/// 默认保留多少个已经读过的 Token，供 unread 和 set_position 回退
+pub const DEFAULT_REPLAY: usize = 256;
+
+/// 最多保留多少个词法错误和警告，之后的被丢弃
+pub const MAX_DIAGNOSTICS: usize = 100;
+
+/// 检查容易混淆的标识符时最多记住多少个不同的标识符
+pub const MAX_IDENTIFIERS: usize = 4096;
+
+/// 流式的 Token 读取器：从任意的 BufRead 中按需读入字符、识别 Token，不需要事先把整个脚本读进内存。
+/// 已经读过的 Token 只保留最近的一部分，unread、set_position 只能回退到这个范围之内，
+/// 回退失败时记下一个错误，由 take_replay_error 取出。
+/// 注释同样只保留最近的 replay 个，词法错误和警告只保留前 MAX_DIAGNOSTICS 个，
+/// 检查容易混淆的标识符时只记住前 MAX_IDENTIFIERS 个不同的标识符，内存占用不随输入增长。
+pub struct StreamTokenReader<R: BufRead> {
+    reader: R,
+    scanner: Scanner<OwnedTokens>,
+    // 还没有解码成字符的字节，一个多字节字符可能跨越两次读取
+    pending: Vec<u8>,
+    // 已经识别出来的 Token。buffer[0] 在整个 Token 流中的位置是 base
+    buffer: VecDeque<Box<dyn Token>>,
+    base: usize,
+    pos: usize,
+    replay: usize,
+    eof: bool,
+    io_error: Option<io::Error>,
+    // 第一次回退到回放窗口之外时的错误
+    replay_error: Option<io::Error>,
+}
+
+impl<R: BufRead> StreamTokenReader<R> {
+    pub fn new(reader: R) -> Self {
+        StreamTokenReader::with_replay(reader, DEFAULT_REPLAY)
+    }
+
+    // replay 是可以回退的 Token 数
+    pub fn with_replay(reader: R, replay: usize) -> Self {
+        StreamTokenReader::with_keywords(reader, KeywordTable::english(), replay)
+    }
+
+    pub fn with_keywords(reader: R, keywords: KeywordTable, replay: usize) -> Self {
+        let mut token_reader = StreamTokenReader {
+            reader,
+            scanner: Scanner::new(OwnedTokens::new(), keywords).with_identifier_limit(MAX_IDENTIFIERS),
+            pending: Vec::new(),
+            buffer: VecDeque::new(),
+            base: 0,
+            pos: 0,
+            replay,
+            eof: false,
+            io_error: None,
+            replay_error: None,
+        };
+
+        // peek 不能修改读取器，所以总是提前准备好下一个 Token
+        token_reader.fill(1);
+        token_reader
+    }
+
+    // 到目前为止遇到的词法错误
+    pub fn get_errors(&self) -> &[LexError] {
+        &self.scanner.errors
+    }
+
+    // 到目前为止的词法警告
+    pub fn get_warnings(&self) -> &[LexWarning] {
+        &self.scanner.warnings
+    }
+
+    // 最近读到的注释，最多 replay 个
+    pub fn get_comments(&self) -> &[Box<dyn Token>] {
+        &self.scanner.sink.comments
+    }
+
+    // 取出目前保留的注释，之后读到的注释重新开始累积
+    pub fn take_comments(&mut self) -> Vec<Box<dyn Token>> {
+        std::mem::take(&mut self.scanner.sink.comments)
+    }
+
+    // 读取时遇到的 IO 错误，包括不合法的 UTF-8。出错之后 Token 流就结束了
+    pub fn take_io_error(&mut self) -> Option<io::Error> {
+        self.io_error.take()
+    }
+
+    // set_position 要回退到回放窗口之外时的错误。这时读取位置没有改变，依赖回溯的结果是不可靠的
+    pub fn take_replay_error(&mut self) -> Option<io::Error> {
+        self.replay_error.take()
+    }
+
+    // 已经识别出来的 Token 的结束位置（不含）
+    fn end(&self) -> usize {
+        self.base + self.buffer.len()
+    }
+
+    // 读入字符，直到 Token 流中至少有 end 个 Token，或者输入结束
+    fn fill(&mut self, end: usize) {
+        while self.end() < end && !self.eof {
+            self.read_chunk();
+            self.buffer.extend(self.scanner.sink.tokens.drain(..));
+            self.trim();
+        }
+
+        // 丢掉回放窗口之外的 Token
+        while self.base + self.replay < self.pos && !self.buffer.is_empty() {
+            self.buffer.pop_front();
+            self.base += 1;
+        }
+    }
+
+    // 丢掉多余的注释、错误和警告
+    fn trim(&mut self) {
+        let comments = &mut self.scanner.sink.comments;
+        comments.drain(..comments.len().saturating_sub(self.replay));
+        self.scanner.errors.truncate(MAX_DIAGNOSTICS);
+        self.scanner.warnings.truncate(MAX_DIAGNOSTICS);
+    }
+
+    // 从 reader 读一块字节，解码之后交给有限自动机
+    fn read_chunk(&mut self) {
+        let chunk = match self.reader.fill_buf() {
+            Ok(chunk) => chunk,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => return,
+            Err(err) => return self.stop(Some(err)),
+        };
+        if chunk.is_empty() {
+            let err = if self.pending.is_empty() {
+                None
+            } else {
+                Some(io::Error::new(io::ErrorKind::InvalidData, "incomplete utf-8 sequence at end of stream"))
+            };
+            return self.stop(err);
+        }
+
+        let len = chunk.len();
+        self.pending.extend_from_slice(chunk);
+        self.reader.consume(len);
+
+        let (valid, err) = match std::str::from_utf8(&self.pending) {
+            Ok(text) => (text.len(), None),
+            Err(err) => (err.valid_up_to(), err.error_len().map(|_| err)),
+        };
+        for ch in std::str::from_utf8(&self.pending[..valid]).unwrap().chars() {
+            self.scanner.feed(ch);
+        }
+        self.pending.drain(..valid);
+
+        if let Some(err) = err {
+            self.stop(Some(io::Error::new(io::ErrorKind::InvalidData, err)));
+        }
+    }
+
+    // 输入结束或者出错，把最后一个 Token 也放进去
+    fn stop(&mut self, err: Option<io::Error>) {
+        self.scanner.finish();
+        self.eof = true;
+        self.io_error = err;
+    }
+}
+
+impl<R: BufRead> TokenReader for StreamTokenReader<R> {
+    fn read(&mut self) -> Option<&dyn Token> {
+        self.fill(self.pos + 2);
+        if self.pos < self.end() {
+            self.pos += 1;
+            self.buffer.get(self.pos - 1 - self.base).map(|token| token.as_ref())
+        } else {
+            None
+        }
+    }
+
+    fn peek(&self) -> Option<&dyn Token> {
+        self.buffer.get(self.pos - self.base).map(|token| token.as_ref())
+    }
+
+    fn peek_nth(&mut self, k: usize) -> Option<&dyn Token> {
+        self.fill(self.pos + k + 1);
+        self.buffer.get(self.pos + k - self.base).map(|token| token.as_ref())
+    }
+
+    // 和 set_position 一样，退到回放窗口之外时记下错误
+    fn unread(&mut self) {
+        if self.pos > 0 {
+            self.set_position(self.pos - 1);
+        }
+    }
+
+    fn get_position(&self) -> usize {
+        self.pos
+    }
+
+    // 回退时不能早于回放窗口；向前跳时按需读入新的 Token
+    fn set_position(&mut self, position: usize) -> bool {
+        if position < self.base {
+            if self.replay_error.is_none() {
+                let msg = format!("cannot backtrack to token {}, only the last {} tokens are kept", position, self.replay);
+                self.replay_error = Some(simple_calculator::error_at(self, &msg));
+            }
+            return false;
+        }
+
+        self.fill(position + 1);
+        if position > self.end() {
+            return false;
+        }
+
+        self.pos = position;
+        self.fill(position + 1);
+        true
+    }
+}
@@ -1,4 +1,5 @@
 #![allow(unused)]
+#![allow(clippy::items_after_test_module)]
 
 use lexer::simple_calculator;
 use lexer::simple_lexer;